@@ -0,0 +1,82 @@
+//! Narrowing completion responses down to what fits in an agent's context.
+
+use lsp_types::{CompletionItem, CompletionResponse};
+
+use crate::pagination::{PageInfo, PageInputs};
+
+/// Keeps the items of `response` whose label starts with `prefix` (ignoring case), sorted
+/// as the server ranked them by `sortText`, and only the requested `page` of them. The
+/// returned [`PageInfo`] counts every item that matched `prefix`.
+pub fn filter_completions(
+    response: CompletionResponse,
+    prefix: Option<&str>,
+    page: &PageInputs,
+    default_limit: Option<usize>,
+) -> (CompletionResponse, PageInfo) {
+    let narrow = |mut items: Vec<CompletionItem>| {
+        if let Some(prefix) = prefix {
+            let prefix = prefix.to_lowercase();
+            items.retain(|item| item.label.to_lowercase().starts_with(&prefix));
+        }
+        // Items without a `sortText` sort by their label, as clients do.
+        items.sort_by(|a, b| sort_key(a).cmp(sort_key(b)));
+        page.page(items, default_limit)
+    };
+    match response {
+        CompletionResponse::Array(items) => {
+            let (items, page) = narrow(items);
+            (CompletionResponse::Array(items), page)
+        }
+        CompletionResponse::List(mut list) => {
+            let (items, page) = narrow(list.items);
+            list.items = items;
+            (CompletionResponse::List(list), page)
+        }
+    }
+}
+
+fn sort_key(item: &CompletionItem) -> &str {
+    item.sort_text.as_deref().unwrap_or(&item.label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(label: &str, sort_text: &str) -> CompletionItem {
+        CompletionItem {
+            label: label.to_string(),
+            sort_text: Some(sort_text.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn filters_by_prefix_then_truncates_in_sort_order() {
+        let response = CompletionResponse::Array(vec![
+            item("get_user", "3"),
+            item("add_user", "2"),
+            item("Get", "1"),
+            item("update_user_email", "0"),
+        ]);
+
+        let page = PageInputs {
+            offset: None,
+            limit: Some(1),
+        };
+        let (response, page) = filter_completions(response, Some("get"), &page, None);
+
+        let CompletionResponse::Array(items) = response else {
+            panic!("expected an array");
+        };
+        let labels: Vec<_> = items.iter().map(|item| item.label.as_str()).collect();
+        assert_eq!(labels, ["Get"]);
+        assert_eq!(
+            page,
+            PageInfo {
+                total: 2,
+                has_more: true
+            }
+        );
+    }
+}
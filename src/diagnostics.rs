@@ -0,0 +1,413 @@
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use lsp_types::{Diagnostic, DiagnosticSeverity, Range, TextEdit};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::process::Command;
+
+use crate::lsp_schema::{RangeSchema, TextEditSchema};
+use crate::text_edits::{PositionEncoding, column_offset};
+
+/// Number of diagnostics at each severity.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct SeverityCounts {
+    pub errors: usize,
+    pub warnings: usize,
+    pub information: usize,
+    pub hints: usize,
+}
+
+impl SeverityCounts {
+    pub fn add(&mut self, diagnostic: &Diagnostic) {
+        // The LSP spec says a missing severity is up to the client; treat it as an error.
+        match diagnostic.severity.unwrap_or(DiagnosticSeverity::ERROR) {
+            DiagnosticSeverity::WARNING => self.warnings += 1,
+            DiagnosticSeverity::INFORMATION => self.information += 1,
+            DiagnosticSeverity::HINT => self.hints += 1,
+            _ => self.errors += 1,
+        }
+    }
+
+    fn merge(&mut self, other: &SeverityCounts) {
+        self.errors += other.errors;
+        self.warnings += other.warnings;
+        self.information += other.information;
+        self.hints += other.hints;
+    }
+}
+
+/// Parses a severity name as accepted by the diagnostics tools.
+pub fn parse_severity(name: &str) -> Result<DiagnosticSeverity> {
+    match name.to_ascii_lowercase().as_str() {
+        "error" => Ok(DiagnosticSeverity::ERROR),
+        "warning" => Ok(DiagnosticSeverity::WARNING),
+        "information" | "info" => Ok(DiagnosticSeverity::INFORMATION),
+        "hint" => Ok(DiagnosticSeverity::HINT),
+        _ => Err(anyhow!(
+            "Invalid severity `{}`: expected \"error\", \"warning\", \"information\" or \"hint\"",
+            name
+        )),
+    }
+}
+
+/// Checks that `code` is a rustc error code like `E0425`, returning it uppercased.
+pub fn parse_error_code(code: &str) -> Result<String> {
+    let normalized = code.trim().to_ascii_uppercase();
+    let digits = normalized.strip_prefix('E').unwrap_or_default();
+    if digits.len() == 4 && digits.bytes().all(|b| b.is_ascii_digit()) {
+        Ok(normalized)
+    } else {
+        Err(anyhow!(
+            "Invalid error code `{}`: expected `E` and four digits, like `E0425`",
+            code
+        ))
+    }
+}
+
+/// How long `rustc --explain` may take.
+const EXPLAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The long explanation of a rustc error code, as the markdown `rustc --explain` prints.
+/// Runs the `rustc` in `RUSTC` if set.
+pub async fn explain_error_code(code: &str) -> Result<String> {
+    let code = parse_error_code(code)?;
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let output = tokio::time::timeout(
+        EXPLAIN_TIMEOUT,
+        Command::new(&rustc)
+            .arg("--explain")
+            .arg(&code)
+            .kill_on_drop(true)
+            .output(),
+    )
+    .await
+    .map_err(|_| anyhow!("`{} --explain {}` timed out", rustc, code))?
+    .map_err(|e| anyhow!("Failed to run {}: {}", rustc, e))?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "`{} --explain {}` failed: {}",
+            rustc,
+            code,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Most source lines shown in a diagnostic's context; longer ranges are cut short.
+const MAX_CONTEXT_LINES: usize = 5;
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+#[schemars(bound = "")]
+pub struct FileDiagnostics<D = Diagnostic> {
+    /// Counts over all the file's diagnostics, including any filtered out of `diagnostics`.
+    pub counts: SeverityCounts,
+    /// LSP `Diagnostic`s, with any details the tool adds alongside their fields.
+    #[schemars(with = "Vec<Value>")]
+    pub diagnostics: Vec<D>,
+}
+
+impl FileDiagnostics {
+    pub fn new(diagnostics: Vec<Diagnostic>) -> Self {
+        let mut counts = SeverityCounts::default();
+        for diagnostic in &diagnostics {
+            counts.add(diagnostic);
+        }
+        Self {
+            counts,
+            diagnostics,
+        }
+    }
+
+    /// Drops diagnostics less severe than `min`, keeping the counts of all of them.
+    pub fn retain_min_severity(&mut self, min: DiagnosticSeverity) {
+        // Severities are ordered from ERROR (1) to HINT (4); a missing one counts as an error.
+        self.diagnostics
+            .retain(|d| d.severity.unwrap_or(DiagnosticSeverity::ERROR) <= min);
+    }
+}
+
+/// A diagnostic with what an agent needs to act on it.
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticDetails {
+    #[serde(flatten)]
+    pub diagnostic: Diagnostic,
+    /// The affected lines with the range underlined, as rustc prints them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    /// Whether the server offers a quick fix for this diagnostic.
+    pub has_quickfix: bool,
+}
+
+/// A quick fix offered for a diagnostic.
+#[derive(Debug, Clone)]
+pub struct QuickFix {
+    pub title: String,
+    /// The fix's edits to the diagnostic's file.
+    pub edits: Vec<TextEdit>,
+    /// Whether the fix also changes other files, which a fix plan leaves alone.
+    pub edits_other_files: bool,
+}
+
+/// A fix chosen for a diagnostic.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct PlannedFix {
+    pub diagnostic: String,
+    #[schemars(with = "RangeSchema")]
+    pub range: Range,
+    pub fix: String,
+    #[schemars(with = "Vec<TextEditSchema>")]
+    pub edits: Vec<TextEdit>,
+}
+
+/// A diagnostic with quick fixes, none of which made it into the plan.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct SkippedFix {
+    pub diagnostic: String,
+    #[schemars(with = "RangeSchema")]
+    pub range: Range,
+    pub reason: String,
+}
+
+/// Non-conflicting quick fixes for a file's diagnostics, in file order.
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct FixPlan {
+    pub fixes: Vec<PlannedFix>,
+    pub skipped: Vec<SkippedFix>,
+    /// Whether the plan was written to the file.
+    pub applied: bool,
+}
+
+impl FixPlan {
+    /// All the edits of the plan, to apply together.
+    pub fn edits(&self) -> Vec<TextEdit> {
+        self.fixes
+            .iter()
+            .flat_map(|fix| fix.edits.clone())
+            .collect()
+    }
+}
+
+/// Picks at most one quick fix per diagnostic so that no two chosen fixes edit overlapping
+/// text. Errors are served first, then fixes are taken in the order the server offered them.
+pub fn plan_fixes(mut candidates: Vec<(Diagnostic, Vec<QuickFix>)>) -> FixPlan {
+    candidates.retain(|(_, fixes)| !fixes.is_empty());
+    candidates.sort_by_key(|(diagnostic, _)| {
+        (
+            diagnostic.severity.unwrap_or(DiagnosticSeverity::ERROR),
+            diagnostic.range.start,
+        )
+    });
+
+    let mut plan = FixPlan::default();
+    let mut taken: Vec<Range> = Vec::new();
+    for (diagnostic, fixes) in candidates {
+        let local = fixes.iter().filter(|fix| !fix.edits_other_files);
+        let chosen = local.clone().find(|fix| {
+            fix.edits
+                .iter()
+                .all(|edit| taken.iter().all(|range| !overlaps(range, &edit.range)))
+        });
+        match chosen {
+            Some(fix) => {
+                taken.extend(fix.edits.iter().map(|edit| edit.range));
+                plan.fixes.push(PlannedFix {
+                    diagnostic: diagnostic.message,
+                    range: diagnostic.range,
+                    fix: fix.title.clone(),
+                    edits: fix.edits.clone(),
+                });
+            }
+            None => {
+                let reason = if local.count() == 0 {
+                    "Every fix also edits other files"
+                } else {
+                    "Every fix overlaps an edit already in the plan"
+                };
+                plan.skipped.push(SkippedFix {
+                    diagnostic: diagnostic.message,
+                    range: diagnostic.range,
+                    reason: reason.to_string(),
+                });
+            }
+        }
+    }
+    plan.fixes.sort_by_key(|fix| fix.range.start);
+    plan
+}
+
+/// Whether edits to `a` and `b` would interfere. Insertions at the same point count,
+/// since their order would be ambiguous.
+fn overlaps(a: &Range, b: &Range) -> bool {
+    (a.start < b.end && b.start < a.end) || a.start == b.start
+}
+
+/// Renders the lines of `text` covered by `range`, each followed by a line of
+/// carets under the covered columns, with 1-based line numbers in a gutter. `range` counts
+/// columns in `encoding` units.
+pub fn source_context(text: &str, range: &Range, encoding: PositionEncoding) -> String {
+    let lines: Vec<&str> = text.lines().collect();
+    let start = range.start.line as usize;
+    let end = (range.end.line as usize)
+        .min(start + MAX_CONTEXT_LINES - 1)
+        .min(lines.len().saturating_sub(1));
+    let gutter = (end + 1).to_string().len();
+
+    let mut out = Vec::new();
+    for (index, line) in lines.iter().enumerate().take(end + 1).skip(start) {
+        let from = if index == start {
+            char_index(line, range.start.character, encoding)
+        } else {
+            line.len() - line.trim_start().len()
+        };
+        let to = if index == range.end.line as usize {
+            char_index(line, range.end.character, encoding)
+        } else {
+            line.chars().count()
+        };
+        let carets = to.saturating_sub(from).max(1);
+        out.push(format!("{:>gutter$} | {}", index + 1, line));
+        out.push(format!(
+            "{:>gutter$} | {}{}",
+            "",
+            " ".repeat(from),
+            "^".repeat(carets)
+        ));
+    }
+    if end < range.end.line as usize && end + 1 < lines.len() {
+        out.push(format!("{:>gutter$} | ...", ""));
+    }
+    out.join("\n")
+}
+
+/// Converts an LSP column into a char index within `line`.
+fn char_index(line: &str, column: u32, encoding: PositionEncoding) -> usize {
+    line[..column_offset(line, column, encoding)]
+        .chars()
+        .count()
+}
+
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct WorkspaceDiagnosticsSummary {
+    pub totals: SeverityCounts,
+    pub files_with_errors: usize,
+    pub files: BTreeMap<String, FileDiagnostics>,
+}
+
+pub fn summarize_workspace_diagnostics(
+    diagnostics: HashMap<String, Vec<Diagnostic>>,
+) -> WorkspaceDiagnosticsSummary {
+    let mut totals = SeverityCounts::default();
+    let mut files = BTreeMap::new();
+    for (uri, diagnostics) in diagnostics {
+        let file = FileDiagnostics::new(diagnostics);
+        totals.merge(&file.counts);
+        files.insert(uri, file);
+    }
+
+    WorkspaceDiagnosticsSummary {
+        files_with_errors: files.values().filter(|f| f.counts.errors > 0).count(),
+        totals,
+        files,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::Position;
+
+    fn range(line: u32, start: u32, end: u32) -> Range {
+        Range::new(Position::new(line, start), Position::new(line, end))
+    }
+
+    fn quick_fix(title: &str, edit_range: Range) -> QuickFix {
+        QuickFix {
+            title: title.to_string(),
+            edits: vec![TextEdit {
+                range: edit_range,
+                new_text: String::new(),
+            }],
+            edits_other_files: false,
+        }
+    }
+
+    #[test]
+    fn fix_plan_skips_conflicting_fixes() {
+        let diagnostic = |message: &str, severity, range| Diagnostic {
+            range,
+            severity: Some(severity),
+            message: message.to_string(),
+            ..Default::default()
+        };
+        let candidates = vec![
+            (
+                diagnostic("unused", DiagnosticSeverity::WARNING, range(1, 0, 4)),
+                vec![quick_fix("remove", range(1, 0, 8))],
+            ),
+            (
+                diagnostic("mismatch", DiagnosticSeverity::ERROR, range(1, 6, 9)),
+                vec![quick_fix("convert", range(1, 6, 9))],
+            ),
+            (
+                diagnostic("missing", DiagnosticSeverity::ERROR, range(3, 0, 2)),
+                vec![],
+            ),
+        ];
+
+        let plan = plan_fixes(candidates);
+
+        let fixes: Vec<_> = plan.fixes.iter().map(|fix| fix.fix.as_str()).collect();
+        assert_eq!(fixes, vec!["convert"]);
+        assert_eq!(plan.skipped.len(), 1);
+        assert_eq!(plan.skipped[0].diagnostic, "unused");
+    }
+
+    #[test]
+    fn severity_filter_keeps_counts() {
+        let diagnostic = |severity| Diagnostic {
+            severity: Some(severity),
+            ..Default::default()
+        };
+        let mut file = FileDiagnostics::new(vec![
+            diagnostic(DiagnosticSeverity::ERROR),
+            diagnostic(DiagnosticSeverity::HINT),
+            diagnostic(DiagnosticSeverity::WARNING),
+        ]);
+        file.retain_min_severity(parse_severity("warning").unwrap());
+        assert_eq!(file.diagnostics.len(), 2);
+        assert_eq!(file.counts.hints, 1);
+        assert!(parse_severity("fatal").is_err());
+    }
+
+    #[test]
+    fn error_codes_are_validated() {
+        assert_eq!(parse_error_code("e0425").unwrap(), "E0425");
+        assert!(parse_error_code("E425").is_err());
+        assert!(parse_error_code("E0425; rm -rf /").is_err());
+        assert!(parse_error_code("--help").is_err());
+    }
+
+    #[test]
+    fn context_underlines_the_range() {
+        let text = "fn main() {\n    error_function();\n}\n";
+        let range = Range::new(Position::new(1, 4), Position::new(1, 18));
+        assert_eq!(
+            source_context(text, &range, PositionEncoding::Utf16),
+            "2 |     error_function();\n  |     ^^^^^^^^^^^^^^"
+        );
+    }
+
+    #[test]
+    fn context_spans_lines_from_their_indentation() {
+        let text = "let x = foo(\n    1,\n);\n";
+        let range = Range::new(Position::new(0, 8), Position::new(2, 1));
+        assert_eq!(
+            source_context(text, &range, PositionEncoding::Utf16),
+            "1 | let x = foo(\n  |         ^^^^\n2 |     1,\n  |     ^^\n3 | );\n  | ^"
+        );
+    }
+}
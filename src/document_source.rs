@@ -0,0 +1,36 @@
+//! Where the bridge reads the text of documents it opens in the server.
+
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Supplies the text of documents that are opened without explicit content.
+///
+/// The bridge reads files from disk by default; embedders can substitute a source to
+/// serve synthetic or remote content instead.
+pub trait DocumentSource: Send + Sync {
+    /// The current text of the file at `path`.
+    fn read(&self, path: &Path) -> io::Result<String>;
+}
+
+/// Reads documents from the filesystem.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FileSystemSource;
+
+impl DocumentSource for FileSystemSource {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        std::fs::read_to_string(path)
+    }
+}
+
+/// Serves fixed contents by path, e.g. for tests.
+impl DocumentSource for HashMap<PathBuf, String> {
+    fn read(&self, path: &Path) -> io::Result<String> {
+        self.get(path).cloned().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is not in the document source", path.display()),
+            )
+        })
+    }
+}
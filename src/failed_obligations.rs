@@ -23,14 +23,14 @@ pub struct CandidateData {
     pub nested_goals: Vec<ProofTreeData>,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 #[serde(untagged)]
 pub enum Candidates {
     Count(usize),
     Candidates(Vec<GoalCandidate>),
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 pub struct GoalTree {
     pub goal: String,
     pub result: String,
@@ -39,7 +39,7 @@ pub struct GoalTree {
     pub candidates: Candidates,
 }
 
-#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
 pub struct GoalCandidate {
     pub kind: String,
     pub result: String,
@@ -93,7 +93,7 @@ impl FailedObligationsState {
             });
         }
 
-        let goal_index = if candidates.len() > 0 {
+        let goal_index = if !candidates.is_empty() {
             Some(Uuid::new_v4().to_string())
         } else {
             None
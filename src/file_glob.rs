@@ -0,0 +1,223 @@
+//! Finding workspace files, by glob pattern or by walking them as git would.
+
+use std::path::{Path, PathBuf};
+
+/// Whether `path`, with `/` separators, matches `pattern`. In the pattern `*` matches any
+/// part of a path component, `?` one character, and a `**` component any number of
+/// components.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<_> = pattern.split('/').filter(|part| !part.is_empty()).collect();
+    let path: Vec<_> = path.split('/').filter(|part| !part.is_empty()).collect();
+    match_components(&pattern, &path)
+}
+
+fn match_components(pattern: &[&str], path: &[&str]) -> bool {
+    match pattern.split_first() {
+        None => path.is_empty(),
+        Some((&"**", rest)) => (0..=path.len()).any(|skip| match_components(rest, &path[skip..])),
+        Some((first, rest)) => match path.split_first() {
+            Some((component, path)) => {
+                let first: Vec<_> = first.chars().collect();
+                let component: Vec<_> = component.chars().collect();
+                match_component(&first, &component) && match_components(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+fn match_component(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| match_component(rest, &name[skip..])),
+        Some((&c, rest)) => match name.split_first() {
+            Some((&n, name)) => (c == '?' || c == n) && match_component(rest, name),
+            None => false,
+        },
+    }
+}
+
+/// The files under `root` whose path relative to it matches `pattern`, sorted. Hidden
+/// directories, `target` and paths ignored by `.gitignore` files are skipped.
+pub fn find_files(root: &Path, pattern: &str) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    walk_files(root, root, &mut |path, relative, _| {
+        if glob_match(pattern, relative) {
+            files.push(path.to_path_buf());
+        }
+    });
+    files.sort();
+    files
+}
+
+/// Calls `visit` with the path, the `/`-separated path relative to `root`, and the size of
+/// each file under `dir`, which must lie within `root`. Hidden directories, `target` and
+/// paths ignored by `.gitignore` files between `root` and the file are skipped.
+pub fn walk_files(root: &Path, dir: &Path, visit: &mut impl FnMut(&Path, &str, u64)) {
+    let mut rules = Vec::new();
+    let mut ancestor = root.to_path_buf();
+    read_gitignore(root, &ancestor, &mut rules);
+    if let Ok(below) = dir.strip_prefix(root) {
+        for component in below.components() {
+            ancestor.push(component);
+            read_gitignore(root, &ancestor, &mut rules);
+        }
+    }
+    walk_dir(root, dir, &mut rules, visit);
+}
+
+fn walk_dir(
+    root: &Path,
+    dir: &Path,
+    rules: &mut Vec<IgnoreRule>,
+    visit: &mut impl FnMut(&Path, &str, u64),
+) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let Ok(relative) = path.strip_prefix(root) else {
+            continue;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        if file_type.is_dir() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.starts_with('.') || name == "target" || is_ignored(rules, &relative, true) {
+                continue;
+            }
+            let inherited = rules.len();
+            read_gitignore(root, &path, rules);
+            walk_dir(root, &path, rules, visit);
+            rules.truncate(inherited);
+        } else if file_type.is_file() && !is_ignored(rules, &relative, false) {
+            let size = entry.metadata().map_or(0, |metadata| metadata.len());
+            visit(&path, &relative, size);
+        }
+    }
+}
+
+/// One pattern from a `.gitignore` file.
+struct IgnoreRule {
+    /// The directory of the `.gitignore`, relative to the walk's root, with a trailing `/`
+    /// unless it is the root itself.
+    base: String,
+    pattern: String,
+    /// A `!pattern`, re-including what an earlier pattern ignored.
+    negated: bool,
+    /// A `pattern/`, matching only directories.
+    dir_only: bool,
+    /// Whether the pattern contains a `/`, tying it to `base` instead of matching a name at
+    /// any depth.
+    anchored: bool,
+}
+
+/// Appends the rules of `dir`'s `.gitignore`, if it has one.
+fn read_gitignore(root: &Path, dir: &Path, rules: &mut Vec<IgnoreRule>) {
+    let Ok(text) = std::fs::read_to_string(dir.join(".gitignore")) else {
+        return;
+    };
+    let base = match dir.strip_prefix(root) {
+        Ok(base) if base.as_os_str().is_empty() => String::new(),
+        Ok(base) => format!("{}/", base.to_string_lossy().replace('\\', "/")),
+        Err(_) => return,
+    };
+    rules.extend(
+        text.lines()
+            .filter_map(|line| parse_ignore_rule(&base, line)),
+    );
+}
+
+fn parse_ignore_rule(base: &str, line: &str) -> Option<IgnoreRule> {
+    let line = line.trim_end();
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.strip_prefix('\\').unwrap_or(line)),
+    };
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let anchored = line.contains('/');
+    Some(IgnoreRule {
+        base: base.to_string(),
+        pattern: line.trim_start_matches('/').to_string(),
+        negated,
+        dir_only,
+        anchored,
+    })
+}
+
+/// Whether the last rule matching `relative` ignores it, as in git.
+fn is_ignored(rules: &[IgnoreRule], relative: &str, is_dir: bool) -> bool {
+    rules
+        .iter()
+        .rev()
+        .find(|rule| {
+            if rule.dir_only && !is_dir {
+                return false;
+            }
+            let Some(below) = relative.strip_prefix(&rule.base) else {
+                return false;
+            };
+            if rule.anchored {
+                glob_match(&rule.pattern, below)
+            } else {
+                let name = below.rsplit('/').next().unwrap_or(below);
+                glob_match(&rule.pattern, name)
+            }
+        })
+        .is_some_and(|rule| !rule.negated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn double_star_matches_any_depth() {
+        assert!(glob_match("src/**/*.rs", "src/main.rs"));
+        assert!(glob_match("src/**/*.rs", "src/a/b/lib.rs"));
+        assert!(!glob_match("src/**/*.rs", "tests/main.rs"));
+        assert!(!glob_match("src/**/*.rs", "src/main.rs.bak"));
+    }
+
+    #[test]
+    fn star_stays_within_a_component() {
+        assert!(glob_match("src/*.rs", "src/main.rs"));
+        assert!(!glob_match("src/*.rs", "src/bin/main.rs"));
+        assert!(glob_match("src/ma?n.rs", "src/main.rs"));
+    }
+
+    #[test]
+    fn gitignore_rules_apply_last_match_first() {
+        let rules: Vec<_> = ["*.log", "!keep.log", "/generated/", "docs/*.rs"]
+            .into_iter()
+            .filter_map(|line| parse_ignore_rule("", line))
+            .collect();
+
+        assert!(is_ignored(&rules, "a/b/debug.log", false));
+        assert!(!is_ignored(&rules, "a/keep.log", false));
+        assert!(is_ignored(&rules, "generated", true));
+        assert!(!is_ignored(&rules, "src/generated", true));
+        assert!(is_ignored(&rules, "docs/example.rs", false));
+        assert!(!is_ignored(&rules, "src/docs/example.rs", false));
+    }
+
+    #[test]
+    fn nested_gitignore_rules_are_relative_to_their_directory() {
+        let rules: Vec<_> = parse_ignore_rule("crates/demo/", "/out.rs")
+            .into_iter()
+            .collect();
+
+        assert!(is_ignored(&rules, "crates/demo/out.rs", false));
+        assert!(!is_ignored(&rules, "out.rs", false));
+    }
+}
@@ -0,0 +1,319 @@
+//! Best-effort extraction of the parts of rust-analyzer's hover markdown.
+
+use lsp_types::{Hover, HoverContents, MarkedString};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+/// The pieces of a hover, pulled out of its markdown.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, JsonSchema)]
+pub struct HoverSummary {
+    /// The item's declaration, e.g. a function signature.
+    pub signature: Option<String>,
+    /// The documentation, as markdown.
+    pub docs: Option<String>,
+    /// The crate the item is defined in.
+    pub source_crate: Option<String>,
+}
+
+/// Splits a hover into its signature, docs and defining crate.
+///
+/// rust-analyzer renders a hover as a code block with the item's path, a code block with
+/// its declaration, then a `---` rule followed by the docs. Anything that doesn't fit that
+/// shape is left out rather than failing.
+pub fn summarize_hover(hover: &Hover) -> HoverSummary {
+    let markdown = hover_markdown(&hover.contents);
+    let lines: Vec<&str> = markdown.lines().collect();
+    let (header, docs) = split_at_rule(&lines);
+
+    let blocks = code_blocks(header);
+    let signature = blocks.last().cloned();
+    let source_crate = (blocks.len() > 1)
+        .then(|| {
+            blocks[0]
+                .split("::")
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        })
+        .filter(|krate| !krate.is_empty());
+    let docs = docs
+        .map(|docs| docs.join("\n").trim().to_string())
+        .filter(|docs| !docs.is_empty());
+
+    HoverSummary {
+        signature,
+        docs,
+        source_crate,
+    }
+}
+
+/// The fully qualified path of the item a hover describes, e.g. `my_crate::Person::new`.
+///
+/// rust-analyzer heads the hover of an item with the path of its module or parent type, and
+/// the item's own name comes from its declaration. Locals, parameters and primitive types get
+/// no such heading; for those the error says why there is no path.
+pub fn qualified_path(hover: &Hover) -> Result<String, String> {
+    let markdown = hover_markdown(&hover.contents);
+    let lines: Vec<&str> = markdown.lines().collect();
+    let blocks = code_blocks(split_at_rule(&lines).0);
+    let [parent, .., declaration] = blocks.as_slice() else {
+        let reason = match blocks.first() {
+            Some(block) if block.starts_with("let ") => {
+                "The symbol is a local variable, which has no path"
+            }
+            Some(_) => {
+                "The symbol is a local variable, parameter or primitive type, which has no path"
+            }
+            None => "The hover at this position doesn't describe an item",
+        };
+        return Err(reason.to_string());
+    };
+    let parent = parent.lines().next().unwrap_or_default().trim();
+    let name = declared_name(declaration)
+        .ok_or_else(|| format!("Couldn't find the item's name in `{}`", declaration))?;
+    Ok(format!("{}::{}", parent, name))
+}
+
+/// The type of the value a hover describes: a local, parameter, field, constant or static,
+/// or a literal, whose hover is just its type.
+///
+/// Hovers of items that aren't values, such as functions and types, give an error saying
+/// what the symbol is instead.
+pub fn value_type(hover: &Hover) -> Result<String, String> {
+    const NOT_VALUES: &[&str] = &[
+        "fn",
+        "struct",
+        "enum",
+        "union",
+        "trait",
+        "type",
+        "mod",
+        "impl",
+        "macro_rules!",
+    ];
+    let markdown = hover_markdown(&hover.contents);
+    let lines: Vec<&str> = markdown.lines().collect();
+    let blocks = code_blocks(split_at_rule(&lines).0);
+    let Some(declaration) = blocks.last() else {
+        return Err("The hover at this position doesn't describe a value".to_string());
+    };
+    let first_line = declaration.lines().next().unwrap_or_default().trim();
+
+    let mut tokens = first_line.split_whitespace().peekable();
+    let mut binding = false;
+    while let Some(&token) = tokens.peek() {
+        if token.starts_with("pub") || matches!(token, "unsafe" | "async" | "extern") {
+            tokens.next();
+        } else if matches!(token, "let" | "mut" | "const" | "static") {
+            binding = true;
+            tokens.next();
+        } else {
+            break;
+        }
+    }
+    let rest = tokens.collect::<Vec<_>>().join(" ");
+    if let Some(keyword) = rest
+        .split_whitespace()
+        .next()
+        .filter(|k| NOT_VALUES.contains(k))
+    {
+        let kind = match keyword {
+            "fn" => "function",
+            "mod" => "module",
+            "type" => "type alias",
+            "impl" => "impl block",
+            "macro_rules!" => "macro",
+            kind => kind,
+        };
+        return Err(format!(
+            "The symbol at this position is a {}, not a value",
+            kind
+        ));
+    }
+    match rest.split_once(": ") {
+        // `name: Type`, or `name: Type = value` for constants.
+        Some((name, ty)) if binding || !name.contains(['(', '<', ' ']) => {
+            let ty = ty.split_once(" = ").map_or(ty, |(ty, _)| ty);
+            Ok(ty.trim().to_string())
+        }
+        // A literal's hover is a lone block with its type.
+        _ if blocks.len() == 1 && !binding && !declaration.contains('\n') => Ok(rest),
+        _ => Err(format!("Couldn't find a type in `{}`", first_line)),
+    }
+}
+
+/// The name an item's declaration introduces, e.g. `Person` for `pub struct Person<T>`.
+/// For a signature that declares nothing, such as a primitive type's, its first word.
+pub fn declared_name(signature: &str) -> Option<String> {
+    const QUALIFIERS: &[&str] = &["unsafe", "async", "extern", "default", "const", "static"];
+    const KEYWORDS: &[&str] = &["struct", "enum", "union", "trait", "type", "fn", "mod"];
+    let first_line = signature.lines().next()?;
+    let name = first_line.split_whitespace().find(|token| {
+        !token.starts_with("pub")
+            && !token.starts_with('"')
+            && !QUALIFIERS.contains(token)
+            && !KEYWORDS.contains(token)
+    })?;
+    let end = name
+        .find(|c: char| c != '_' && !c.is_alphanumeric())
+        .unwrap_or(name.len());
+    Some(name[..end].to_string()).filter(|name| !name.is_empty())
+}
+
+fn hover_markdown(contents: &HoverContents) -> String {
+    let marked = |marked: &MarkedString| match marked {
+        MarkedString::String(text) => text.clone(),
+        MarkedString::LanguageString(code) => {
+            format!("```{}\n{}\n```", code.language, code.value)
+        }
+    };
+    match contents {
+        HoverContents::Markup(markup) => markup.value.clone(),
+        HoverContents::Scalar(scalar) => marked(scalar),
+        HoverContents::Array(array) => array.iter().map(marked).collect::<Vec<_>>().join("\n\n"),
+    }
+}
+
+/// Splits hover lines at the `---` rule into the header and the docs below it.
+fn split_at_rule<'a>(lines: &'a [&'a str]) -> (&'a [&'a str], Option<&'a [&'a str]>) {
+    match lines.iter().position(|line| line.trim() == "---") {
+        Some(rule) => (&lines[..rule], Some(&lines[rule + 1..])),
+        None => (lines, None),
+    }
+}
+
+/// The contents of each fenced code block in `lines`.
+fn code_blocks(lines: &[&str]) -> Vec<String> {
+    let mut blocks = Vec::new();
+    let mut current: Option<Vec<&str>> = None;
+    for line in lines {
+        if line.trim_start().starts_with("```") {
+            match current.take() {
+                Some(block) => blocks.push(block.join("\n")),
+                None => current = Some(Vec::new()),
+            }
+        } else if let Some(block) = &mut current {
+            block.push(line);
+        }
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{MarkupContent, MarkupKind};
+
+    fn hover(markdown: &str) -> Hover {
+        Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: markdown.to_string(),
+            }),
+            range: None,
+        }
+    }
+
+    #[test]
+    fn signature_docs_and_crate_are_extracted() {
+        let summary = summarize_hover(&hover(
+            "\n```rust\nmy_crate::Person\n```\n\n```rust\npub fn new(name: String) -> Self\n```\n\n---\n\nCreates a person.\n\n```rust\nPerson::new(\"a\".into());\n```\n",
+        ));
+
+        assert_eq!(
+            summary,
+            HoverSummary {
+                signature: Some("pub fn new(name: String) -> Self".to_string()),
+                docs: Some(
+                    "Creates a person.\n\n```rust\nPerson::new(\"a\".into());\n```".to_string()
+                ),
+                source_crate: Some("my_crate".to_string()),
+            }
+        );
+    }
+
+    #[test]
+    fn qualified_path_joins_parent_and_name() {
+        assert_eq!(
+            qualified_path(&hover(
+                "```rust\nmy_crate::db::Database\n```\n\n```rust\npub fn add_user(&mut self, person: Person) -> u32\n```\n\n---\n\nAdds a user."
+            )),
+            Ok("my_crate::db::Database::add_user".to_string())
+        );
+        assert_eq!(
+            qualified_path(&hover(
+                "```rust\nmy_crate::Status\n```\n\n```rust\nPending(String)\n```"
+            )),
+            Ok("my_crate::Status::Pending".to_string())
+        );
+        assert!(
+            qualified_path(&hover("```rust\nlet id: u32\n```"))
+                .unwrap_err()
+                .contains("local variable")
+        );
+    }
+
+    #[test]
+    fn value_type_reads_bindings_fields_and_literals() {
+        let value_type = |markdown: &str| value_type(&hover(markdown));
+        assert_eq!(
+            value_type("```rust\nlet sum: i32\n```\n\n---\n\nsize = 4, align = 0x4"),
+            Ok("i32".to_string())
+        );
+        assert_eq!(
+            value_type("```rust\nlet mut db: Database\n```"),
+            Ok("Database".to_string())
+        );
+        assert_eq!(
+            value_type("```rust\nmy_crate::Person\n```\n\n```rust\npub name: String\n```"),
+            Ok("String".to_string())
+        );
+        assert_eq!(
+            value_type("```rust\nmy_crate\n```\n\n```rust\npub const MAX: usize = 10\n```"),
+            Ok("usize".to_string())
+        );
+        assert_eq!(
+            value_type("```rust\nnumbers: &[i32]\n```"),
+            Ok("&[i32]".to_string())
+        );
+        assert_eq!(value_type("```rust\ni32\n```"), Ok("i32".to_string()));
+        assert_eq!(
+            value_type(
+                "```rust\nmy_crate\n```\n\n```rust\npub fn calculate_sum(numbers: &[i32]) -> i32\n```"
+            ),
+            Err("The symbol at this position is a function, not a value".to_string())
+        );
+        assert!(
+            value_type("```rust\nmy_crate::Status\n```\n\n```rust\nPending(String)\n```").is_err()
+        );
+        assert!(value_type("just text").is_err());
+    }
+
+    #[test]
+    fn declared_name_skips_qualifiers_and_generics() {
+        assert_eq!(
+            declared_name("pub struct Person").as_deref(),
+            Some("Person")
+        );
+        assert_eq!(
+            declared_name("pub(crate) enum Shape<T: Clone> {\n    Circle(T),\n}").as_deref(),
+            Some("Shape")
+        );
+        assert_eq!(declared_name("const X: u32 = 1").as_deref(), Some("X"));
+        assert_eq!(declared_name("i32").as_deref(), Some("i32"));
+        assert_eq!(declared_name(""), None);
+    }
+
+    #[test]
+    fn unexpected_markdown_yields_what_it_can() {
+        assert_eq!(
+            summarize_hover(&hover("just text")),
+            HoverSummary::default()
+        );
+
+        let summary = summarize_hover(&hover("```rust\nlet x: i32\n```"));
+        assert_eq!(summary.signature.as_deref(), Some("let x: i32"));
+        assert_eq!(summary.source_crate, None);
+    }
+}
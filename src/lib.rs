@@ -1,15 +1,59 @@
+mod completion;
+mod diagnostics;
+mod document_source;
 mod failed_obligations;
+mod file_glob;
+mod hover;
 mod lsp_client;
+mod lsp_schema;
+mod pagination;
+mod ra_extensions;
+mod response_cache;
+mod runner;
 mod rust_analyzer_mcp;
+mod snippets;
+mod symbols;
+mod syntax_tree;
+mod text_edits;
+mod tool_error;
+mod tool_fn;
 
+pub use document_source::{DocumentSource, FileSystemSource};
 pub use rust_analyzer_mcp::{
-    BridgeState, BridgeType, SERVER_ID, build_server, with_bridge_and_document,
+    BridgeState, BridgeType, CargoSettings, SERVER_ID, ServerStatus, apply_document_edits,
+    build_server, close_document, with_bridge_and_document, with_bridge_and_document_content,
 };
 use sacp::{Conductor, ConnectTo, Proxy};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Default)]
 pub struct RustAnalyzerProxy {
     pub workspace_path: Option<String>,
+    /// Further roots for the same rust-analyzer to analyze, such as the unrelated crates of
+    /// a monorepo. Without a `workspace_path`, the first one is the workspace root.
+    pub workspace_folders: Vec<PathBuf>,
+    /// Cargo features to analyze with. Changing these (or the fields below) only takes
+    /// effect once rust-analyzer reloads the workspace.
+    pub cargo_features: Vec<String>,
+    /// Analyze with every cargo feature enabled, overriding `cargo_features`.
+    pub cargo_all_features: bool,
+    /// Target triple to analyze for, like `--target`.
+    pub cargo_target: Option<String>,
+    /// Target directory for rust-analyzer's own cargo runs, like `CARGO_TARGET_DIR`, so
+    /// they don't contend with other builds of the workspace.
+    pub cargo_target_dir: Option<String>,
+    /// Connect to a rust-analyzer already listening at this address (e.g. `127.0.0.1:9257`)
+    /// instead of starting one, such as a server running in a container.
+    pub tcp_address: Option<String>,
+    /// Environment variables for the rust-analyzer process, such as `CARGO`, `RUSTC` or
+    /// `CARGO_NET_OFFLINE`. They are merged into the environment it inherits, overriding
+    /// variables of the same name. Unused with `tcp_address`.
+    pub env: HashMap<String, String>,
+    /// Read documents from here instead of the filesystem, e.g. to serve synthetic
+    /// content in tests.
+    pub document_source: Option<Arc<dyn DocumentSource>>,
 }
 
 impl ConnectTo<Conductor> for RustAnalyzerProxy {
@@ -20,7 +64,22 @@ impl ConnectTo<Conductor> for RustAnalyzerProxy {
         Proxy
             .builder()
             .name("rust-analyzer-proxy")
-            .with_mcp_server(build_server(self.workspace_path).await?)
+            .with_mcp_server(
+                build_server(
+                    self.workspace_path,
+                    self.workspace_folders,
+                    CargoSettings {
+                        features: self.cargo_features,
+                        all_features: self.cargo_all_features,
+                        target: self.cargo_target,
+                        target_dir: self.cargo_target_dir,
+                    },
+                    self.tcp_address,
+                    self.env,
+                    self.document_source,
+                )
+                .await?,
+            )
             .connect_to(client)
             .await
     }
@@ -1,35 +1,406 @@
 use anyhow::{Result, anyhow};
 use lsp_types::*;
+use schemars::JsonSchema;
+use serde::Serialize;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::pin::Pin;
 use std::process::Stdio;
 use std::sync::Arc;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
 use tokio::process::{Child, Command};
-use tokio::sync::{Mutex, mpsc, oneshot};
+use tokio::sync::{Mutex, mpsc, oneshot, watch};
 use tracing::error;
 
+use crate::snippets::strip_snippet_edits;
+use crate::text_edits::PositionEncoding;
+
 type NotifSubscription =
     Box<dyn (Fn(serde_json::Value) -> Pin<Box<dyn Future<Output = bool> + Send>>) + Send>;
 
+/// Where the response to a request is delivered.
+type ResponseSender = oneshot::Sender<Result<Value>>;
+
+/// The requests awaiting a response, by id.
+type PendingRequests = Arc<Mutex<HashMap<u64, ResponseSender>>>;
+
+/// How many `window/*` messages from the server are retained.
+const MAX_SERVER_MESSAGES: usize = 100;
+
+/// How many lines of the server's stderr are retained.
+const MAX_SERVER_LOG_LINES: usize = 200;
+
+/// How long a request waits for the server to become quiescent before being sent anyway.
+const REQUEST_READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Requests that must go out regardless of the server status.
+const UNGATED_METHODS: &[&str] = &["initialize", "shutdown"];
+
+/// What the client needs to know about a particular language server beyond plain LSP.
+#[derive(Debug, Clone)]
+pub struct ServerProfile {
+    /// Name used in logs and error messages, e.g. `rust-analyzer`.
+    pub name: String,
+    /// The `workspace/configuration` section the settings are served under.
+    pub settings_section: String,
+    /// Client capabilities to advertise under `experimental`.
+    pub experimental_capabilities: Option<Value>,
+    /// Whether the server reports readiness via `experimental/serverStatus`. Servers that
+    /// don't are treated as ready as soon as they're initialized.
+    pub reports_status: bool,
+    /// Requests sent without waiting for the server to be ready, such as the
+    /// introspection requests used to find out why it isn't.
+    pub ungated_methods: Vec<String>,
+    /// How to install the server, shown when its binary can't be found.
+    pub install_hint: String,
+}
+
 pub struct LspClient {
-    child: Child,
+    /// The server process, or `None` when connected to a remote server over TCP.
+    child: Option<Child>,
+    profile: ServerProfile,
     request_tx: mpsc::UnboundedSender<LspMessage>,
     next_id: std::sync::atomic::AtomicU64,
+    /// Registered by [`Self::send_request`] before the request is queued, so the reader
+    /// always finds the sender however quickly the response comes back.
+    pending_requests: PendingRequests,
     notification_subscriptions: Arc<Mutex<HashMap<String, Vec<NotifSubscription>>>>,
+    server_messages: Arc<Mutex<VecDeque<ServerMessage>>>,
+    /// The last lines the server wrote to stderr, oldest first.
+    server_log: Arc<Mutex<VecDeque<String>>>,
+    /// The settings sent at initialize, also served to `workspace/configuration`.
+    configuration: Arc<Mutex<Value>>,
+    /// Whether the last `experimental/serverStatus` reported the server as quiescent.
+    ready_rx: watch::Receiver<bool>,
+    /// What the server said it supports in its initialize response.
+    capabilities: std::sync::OnceLock<ServerCapabilities>,
+    /// The server's name and version, if it sent them in its initialize response.
+    server_info: std::sync::OnceLock<ServerInfo>,
+    /// The position encoding the server chose from those we offered.
+    position_encoding: std::sync::OnceLock<PositionEncoding>,
+    /// Whether the server advertised `typeHierarchyProvider`, which [`ServerCapabilities`]
+    /// has no field for.
+    type_hierarchy_support: std::sync::OnceLock<bool>,
+    /// The latest `textDocument/publishDiagnostics` for each URI.
+    published_diagnostics: Arc<Mutex<HashMap<String, Vec<Diagnostic>>>>,
+    /// Work the server has begun and not yet ended, oldest first.
+    progress: Arc<Mutex<Vec<Progress>>>,
+    /// Cleared once the server's output ends, because it exited or the connection dropped.
+    alive: Arc<AtomicBool>,
+}
+
+/// Work the server reports through `$/progress`, such as indexing or a cargo check.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct Progress {
+    /// The progress token, e.g. `rust-analyzer/flycheck/0`.
+    pub token: String,
+    pub title: String,
+    /// What is being worked on, e.g. the crate being indexed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// How far along the work is, from 0 to 100, if the server says.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub percentage: Option<u32>,
+}
+
+/// Updates the ongoing `progress`, in the order it began, with a `$/progress` notification.
+fn apply_progress(progress: &mut Vec<Progress>, params: ProgressParams) {
+    let token = match params.token {
+        NumberOrString::Number(number) => number.to_string(),
+        NumberOrString::String(string) => string,
+    };
+    let ProgressParamsValue::WorkDone(value) = params.value;
+    match value {
+        WorkDoneProgress::Begin(begin) => {
+            progress.retain(|work| work.token != token);
+            progress.push(Progress {
+                token,
+                title: begin.title,
+                message: begin.message,
+                percentage: begin.percentage,
+            });
+        }
+        WorkDoneProgress::Report(report) => {
+            if let Some(work) = progress.iter_mut().find(|work| work.token == token) {
+                if report.message.is_some() {
+                    work.message = report.message;
+                }
+                if report.percentage.is_some() {
+                    work.percentage = report.percentage;
+                }
+            }
+        }
+        WorkDoneProgress::End(_) => progress.retain(|work| work.token != token),
+    }
+}
+
+/// A message the server asked us to show or log via `window/showMessage`,
+/// `window/logMessage` or `window/showMessageRequest`.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ServerMessage {
+    pub method: String,
+    pub level: String,
+    pub message: String,
 }
 
 enum LspMessage {
     Request(LspRequest),
     Notification(LspNotification),
+    Response(LspResponse),
 }
 
 struct LspRequest {
     id: u64,
     method: String,
     params: Value,
-    response_tx: oneshot::Sender<Result<Value>>,
+}
+
+/// An error response from the server.
+#[derive(Debug, Clone)]
+pub struct LspError {
+    /// The JSON-RPC error code, e.g. [`METHOD_NOT_FOUND`].
+    pub code: i64,
+    /// The error object as the server sent it.
+    pub error: Value,
+}
+
+impl LspError {
+    /// Whether `error` is the server saying it doesn't know the request's method.
+    pub fn is_method_not_found(error: &anyhow::Error) -> bool {
+        error
+            .downcast_ref::<LspError>()
+            .is_some_and(|error| error.code == METHOD_NOT_FOUND)
+    }
+}
+
+impl std::fmt::Display for LspError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LSP error: {}", self.error)
+    }
+}
+
+impl std::error::Error for LspError {}
+
+/// Returned when a request is not sent because the server did not advertise
+/// the capability it needs.
+#[derive(Debug, Clone)]
+pub struct Unsupported {
+    /// The server's name, e.g. `rust-analyzer`.
+    pub server: String,
+    pub method: String,
+}
+
+impl std::fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` is not supported by this {} version",
+            self.method, self.server
+        )
+    }
+}
+
+impl std::error::Error for Unsupported {}
+
+/// Returned when the server's binary can't be started, such as when it isn't installed.
+/// Retrying won't help until the configuration changes.
+#[derive(Debug, Clone)]
+pub struct SpawnFailed(pub String);
+
+impl std::fmt::Display for SpawnFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SpawnFailed {}
+
+/// Returned for requests to a server that has exited, naming the server.
+#[derive(Debug, Clone)]
+pub struct ServerExited(pub String);
+
+impl std::fmt::Display for ServerExited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} exited", self.0)
+    }
+}
+
+impl std::error::Error for ServerExited {}
+
+/// Standard requests whose availability is announced in [`ServerCapabilities`].
+pub const CAPABILITY_METHODS: &[&str] = &[
+    "textDocument/hover",
+    "textDocument/completion",
+    "textDocument/signatureHelp",
+    "textDocument/declaration",
+    "textDocument/definition",
+    "textDocument/typeDefinition",
+    "textDocument/implementation",
+    "textDocument/references",
+    "textDocument/documentHighlight",
+    "textDocument/documentSymbol",
+    "textDocument/codeAction",
+    "textDocument/codeLens",
+    "textDocument/documentLink",
+    "textDocument/formatting",
+    "textDocument/rangeFormatting",
+    "textDocument/onTypeFormatting",
+    "textDocument/rename",
+    "textDocument/prepareRename",
+    "textDocument/foldingRange",
+    "textDocument/selectionRange",
+    "textDocument/prepareCallHierarchy",
+    "textDocument/prepareTypeHierarchy",
+    "textDocument/semanticTokens/full",
+    "textDocument/semanticTokens/range",
+    "textDocument/inlayHint",
+    "textDocument/inlineValue",
+    "textDocument/linkedEditingRange",
+    "textDocument/moniker",
+    "textDocument/diagnostic",
+    "workspace/diagnostic",
+    "workspace/symbol",
+    "workspace/executeCommand",
+    "codeLens/resolve",
+    "documentLink/resolve",
+    "codeAction/resolve",
+    "completionItem/resolve",
+    "inlayHint/resolve",
+];
+
+/// Requests governed by `typeHierarchyProvider`.
+const TYPE_HIERARCHY_METHODS: &[&str] = &[
+    "textDocument/prepareTypeHierarchy",
+    "typeHierarchy/supertypes",
+    "typeHierarchy/subtypes",
+];
+
+/// Whether `capabilities` allow the request `method`, or `None` if the method
+/// isn't governed by a server capability.
+pub fn method_supported(capabilities: &ServerCapabilities, method: &str) -> Option<bool> {
+    fn enabled(provider: Option<&OneOf<bool, impl Sized>>) -> bool {
+        matches!(provider, Some(OneOf::Left(true) | OneOf::Right(_)))
+    }
+
+    let c = capabilities;
+    let supported = match method {
+        "textDocument/hover" => !matches!(
+            c.hover_provider,
+            None | Some(HoverProviderCapability::Simple(false))
+        ),
+        "textDocument/completion" => c.completion_provider.is_some(),
+        "completionItem/resolve" => c
+            .completion_provider
+            .as_ref()
+            .and_then(|options| options.resolve_provider)
+            .unwrap_or(false),
+        "textDocument/signatureHelp" => c.signature_help_provider.is_some(),
+        "textDocument/declaration" => !matches!(
+            c.declaration_provider,
+            None | Some(DeclarationCapability::Simple(false))
+        ),
+        "textDocument/definition" => enabled(c.definition_provider.as_ref()),
+        "textDocument/typeDefinition" => !matches!(
+            c.type_definition_provider,
+            None | Some(TypeDefinitionProviderCapability::Simple(false))
+        ),
+        "textDocument/implementation" => !matches!(
+            c.implementation_provider,
+            None | Some(ImplementationProviderCapability::Simple(false))
+        ),
+        "textDocument/references" => enabled(c.references_provider.as_ref()),
+        "textDocument/documentHighlight" => enabled(c.document_highlight_provider.as_ref()),
+        "textDocument/documentSymbol" => enabled(c.document_symbol_provider.as_ref()),
+        "textDocument/codeAction" => !matches!(
+            c.code_action_provider,
+            None | Some(CodeActionProviderCapability::Simple(false))
+        ),
+        "codeAction/resolve" => matches!(
+            &c.code_action_provider,
+            Some(CodeActionProviderCapability::Options(options))
+                if options.resolve_provider == Some(true)
+        ),
+        "textDocument/codeLens" => c.code_lens_provider.is_some(),
+        "codeLens/resolve" => c
+            .code_lens_provider
+            .as_ref()
+            .and_then(|options| options.resolve_provider)
+            .unwrap_or(false),
+        "textDocument/documentLink" => c.document_link_provider.is_some(),
+        "documentLink/resolve" => c
+            .document_link_provider
+            .as_ref()
+            .and_then(|options| options.resolve_provider)
+            .unwrap_or(false),
+        "textDocument/formatting" => enabled(c.document_formatting_provider.as_ref()),
+        "textDocument/rangeFormatting" => enabled(c.document_range_formatting_provider.as_ref()),
+        "textDocument/onTypeFormatting" => c.document_on_type_formatting_provider.is_some(),
+        "textDocument/rename" => enabled(c.rename_provider.as_ref()),
+        "textDocument/prepareRename" => matches!(
+            &c.rename_provider,
+            Some(OneOf::Right(options)) if options.prepare_provider == Some(true)
+        ),
+        "textDocument/foldingRange" => !matches!(
+            c.folding_range_provider,
+            None | Some(FoldingRangeProviderCapability::Simple(false))
+        ),
+        "textDocument/selectionRange" => !matches!(
+            c.selection_range_provider,
+            None | Some(SelectionRangeProviderCapability::Simple(false))
+        ),
+        "textDocument/prepareCallHierarchy" => !matches!(
+            c.call_hierarchy_provider,
+            None | Some(CallHierarchyServerCapability::Simple(false))
+        ),
+        "textDocument/semanticTokens/full" | "textDocument/semanticTokens/range" => {
+            let options = c
+                .semantic_tokens_provider
+                .as_ref()
+                .map(|provider| match provider {
+                    SemanticTokensServerCapabilities::SemanticTokensOptions(options) => options,
+                    SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(
+                        options,
+                    ) => &options.semantic_tokens_options,
+                });
+            match (method, options) {
+                (_, None) => false,
+                ("textDocument/semanticTokens/full", Some(options)) => !matches!(
+                    options.full,
+                    None | Some(SemanticTokensFullOptions::Bool(false))
+                ),
+                (_, Some(options)) => options.range == Some(true),
+            }
+        }
+        "textDocument/inlayHint" => enabled(c.inlay_hint_provider.as_ref()),
+        "inlayHint/resolve" => matches!(
+            &c.inlay_hint_provider,
+            Some(OneOf::Right(InlayHintServerCapabilities::Options(options)))
+                if options.resolve_provider == Some(true)
+        ),
+        "textDocument/inlineValue" => enabled(c.inline_value_provider.as_ref()),
+        "textDocument/linkedEditingRange" => !matches!(
+            c.linked_editing_range_provider,
+            None | Some(LinkedEditingRangeServerCapabilities::Simple(false))
+        ),
+        "textDocument/moniker" => enabled(c.moniker_provider.as_ref()),
+        "textDocument/diagnostic" => c.diagnostic_provider.is_some(),
+        "workspace/diagnostic" => {
+            c.diagnostic_provider
+                .as_ref()
+                .is_some_and(|provider| match provider {
+                    DiagnosticServerCapabilities::Options(options) => options.workspace_diagnostics,
+                    DiagnosticServerCapabilities::RegistrationOptions(options) => {
+                        options.diagnostic_options.workspace_diagnostics
+                    }
+                })
+        }
+        "workspace/symbol" => enabled(c.workspace_symbol_provider.as_ref()),
+        "workspace/executeCommand" => c.execute_command_provider.is_some(),
+        _ => return None,
+    };
+    Some(supported)
 }
 
 pub struct LspNotification {
@@ -37,14 +408,237 @@ pub struct LspNotification {
     params: Option<serde_json::Value>,
 }
 
+/// A semantic token with absolute position and legend names resolved.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct DecodedSemanticToken {
+    pub line: u32,
+    pub start: u32,
+    pub length: u32,
+    pub token_type: String,
+    pub modifiers: Vec<String>,
+}
+
+/// Decodes the delta-encoded semantic tokens array into absolute positions,
+/// resolving type and modifier indices through `legend`.
+pub fn decode_semantic_tokens(
+    data: &[SemanticToken],
+    legend: &SemanticTokensLegend,
+) -> Vec<DecodedSemanticToken> {
+    let mut line = 0;
+    let mut start = 0;
+    data.iter()
+        .map(|token| {
+            if token.delta_line == 0 {
+                start += token.delta_start;
+            } else {
+                line += token.delta_line;
+                start = token.delta_start;
+            }
+
+            let token_type = legend
+                .token_types
+                .get(token.token_type as usize)
+                .map(|t| t.as_str().to_string())
+                .unwrap_or_else(|| format!("unknown({})", token.token_type));
+            let modifiers = legend
+                .token_modifiers
+                .iter()
+                .enumerate()
+                .filter(|(bit, _)| *bit < 32 && token.token_modifiers_bitset & (1 << bit) != 0)
+                .map(|(_, m)| m.as_str().to_string())
+                .collect();
+
+            DecodedSemanticToken {
+                line,
+                start,
+                length: token.length,
+                token_type,
+                modifiers,
+            }
+        })
+        .collect()
+}
+
+/// Our reply to a request initiated by the server.
+struct LspResponse {
+    id: Value,
+    result: std::result::Result<Value, Value>,
+}
+
+/// JSON-RPC error code for requests the receiver doesn't implement.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// A message as written to the server.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum JsonRpcMessage {
+    Request {
+        jsonrpc: &'static str,
+        id: u64,
+        method: String,
+        params: Value,
+    },
+    Notification {
+        jsonrpc: &'static str,
+        method: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        params: Option<Value>,
+    },
+    Response {
+        jsonrpc: &'static str,
+        id: Value,
+        result: Value,
+    },
+    ErrorResponse {
+        jsonrpc: &'static str,
+        id: Value,
+        error: Value,
+    },
+}
+
+impl JsonRpcMessage {
+    /// The message to write for `message`, along with the request's id if it is a request.
+    fn from_lsp(message: LspMessage) -> (Self, Option<u64>) {
+        const JSONRPC: &str = "2.0";
+        match message {
+            LspMessage::Request(request) => (
+                JsonRpcMessage::Request {
+                    jsonrpc: JSONRPC,
+                    id: request.id,
+                    method: request.method,
+                    params: request.params,
+                },
+                Some(request.id),
+            ),
+            LspMessage::Notification(notification) => (
+                JsonRpcMessage::Notification {
+                    jsonrpc: JSONRPC,
+                    method: notification.method,
+                    params: notification.params,
+                },
+                None,
+            ),
+            LspMessage::Response(response) => (
+                match response.result {
+                    Ok(result) => JsonRpcMessage::Response {
+                        jsonrpc: JSONRPC,
+                        id: response.id,
+                        result,
+                    },
+                    Err(error) => JsonRpcMessage::ErrorResponse {
+                        jsonrpc: JSONRPC,
+                        id: response.id,
+                        error,
+                    },
+                },
+                None,
+            ),
+        }
+    }
+
+    /// The message with its `Content-Length` header, ready to write.
+    fn frame(&self) -> serde_json::Result<String> {
+        let content = serde_json::to_string(self)?;
+        Ok(format!(
+            "Content-Length: {}\r\n\r\n{}",
+            content.len(),
+            content
+        ))
+    }
+}
+
+/// Computes our reply to a request initiated by the server.
+fn server_request_response(
+    method: &str,
+    params: &Value,
+    settings_section: &str,
+    configuration: &Value,
+) -> std::result::Result<Value, Value> {
+    match method {
+        "workspace/configuration" => {
+            let items = params
+                .get("items")
+                .and_then(|items| items.as_array())
+                .map(|items| items.as_slice())
+                .unwrap_or_default();
+            Ok(Value::Array(
+                items
+                    .iter()
+                    .map(|item| match item.get("section").and_then(|s| s.as_str()) {
+                        Some(section) if section != settings_section => Value::Null,
+                        _ => configuration.clone(),
+                    })
+                    .collect(),
+            ))
+        }
+        // We have no UI, so no action is ever chosen.
+        "window/showMessageRequest" => Ok(Value::Null),
+        "window/workDoneProgress/create" | "client/registerCapability" => Ok(Value::Null),
+        _ => Err(serde_json::json!({
+            "code": METHOD_NOT_FOUND,
+            "message": format!("Unsupported request: {}", method),
+        })),
+    }
+}
+
+/// Explains why `command` couldn't be started, pointing at `install_hint` when it isn't
+/// installed.
+fn spawn_error(command: &str, install_hint: &str, error: std::io::Error) -> anyhow::Error {
+    let message =
+        if error.kind() == std::io::ErrorKind::NotFound && find_executable(command).is_none() {
+            format!("{} not found; {}", command, install_hint)
+        } else {
+            format!("Failed to spawn {}: {}", command, error)
+        };
+    SpawnFailed(message).into()
+}
+
+/// Where `command` would be found: itself if it's a path, otherwise the first match on `PATH`.
+fn find_executable(command: &str) -> Option<std::path::PathBuf> {
+    let command = std::path::Path::new(command);
+    if command.components().count() > 1 {
+        return command.is_file().then(|| command.to_path_buf());
+    }
+    std::env::split_paths(&std::env::var_os("PATH")?)
+        .map(|dir| dir.join(command))
+        .find(|candidate| candidate.is_file())
+}
+
+/// The workspace folder at `uri`, named after its last path segment.
+fn workspace_folder(uri: &Uri) -> WorkspaceFolder {
+    let name = uri
+        .path()
+        .segments()
+        .rfind(|segment| !segment.as_str().is_empty())
+        .map(|segment| segment.decode().into_string_lossy().into_owned())
+        .unwrap_or_default();
+    WorkspaceFolder {
+        uri: uri.clone(),
+        name,
+    }
+}
+
 impl LspClient {
-    pub async fn new(command: &str, args: &[&str], root_uri: Uri) -> Result<Self> {
+    /// Starts `command` and initializes it for `roots`, sending `settings` as the
+    /// initialization options. The first root is the workspace root; see [`Self::initialize`].
+    ///
+    /// The server inherits our environment, with `env` added on top of it.
+    pub async fn new(
+        command: &str,
+        args: &[&str],
+        env: &HashMap<String, String>,
+        roots: Vec<Uri>,
+        profile: ServerProfile,
+        settings: Value,
+    ) -> Result<Self> {
         let mut child = Command::new(command)
             .args(args)
+            .envs(env)
             .stdin(Stdio::piped())
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
-            .spawn()?;
+            .spawn()
+            .map_err(|e| spawn_error(command, &profile.install_hint, e))?;
 
         let stdin = child
             .stdin
@@ -55,115 +649,205 @@ impl LspClient {
             .take()
             .ok_or_else(|| anyhow!("Failed to get stdout"))?;
 
+        let server_log = Arc::new(Mutex::new(VecDeque::new()));
         if let Some(stderr) = child.stderr.take() {
-            tokio::spawn(async move {
-                let mut reader = BufReader::new(stderr);
+            tokio::spawn(Self::stderr_task(
+                stderr,
+                profile.name.clone(),
+                server_log.clone(),
+            ));
+        }
+
+        Self::start(
+            Some(child),
+            stdout,
+            stdin,
+            server_log,
+            roots,
+            profile,
+            settings,
+        )
+        .await
+    }
+
+    /// Connects to a server already listening at `addr` and initializes it for
+    /// `roots`. The remote server must see the workspace at the same paths we do.
+    pub async fn connect_tcp(
+        addr: impl tokio::net::ToSocketAddrs,
+        roots: Vec<Uri>,
+        profile: ServerProfile,
+        settings: Value,
+    ) -> Result<Self> {
+        let stream = tokio::net::TcpStream::connect(addr)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to {}: {}", profile.name, e))?;
+        let (reader, writer) = stream.into_split();
+        let server_log = Arc::new(Mutex::new(VecDeque::new()));
+
+        Self::start(None, reader, writer, server_log, roots, profile, settings).await
+    }
+
+    /// Starts the I/O tasks over the server's output and input, then initializes it.
+    async fn start(
+        child: Option<Child>,
+        reader: impl AsyncRead + Unpin + Send + 'static,
+        writer: impl AsyncWrite + Unpin + Send + 'static,
+        server_log: Arc<Mutex<VecDeque<String>>>,
+        roots: Vec<Uri>,
+        profile: ServerProfile,
+        settings: Value,
+    ) -> Result<Self> {
+        let (request_tx, request_rx) = mpsc::unbounded_channel();
+        let pending_requests = PendingRequests::default();
+        let server_messages = Arc::new(Mutex::new(VecDeque::new()));
+        let configuration = Arc::new(Mutex::new(settings));
+
+        let (ready_tx, ready_rx) = watch::channel(!profile.reports_status);
+        let ready_tx = Arc::new(ready_tx);
+        let track_status: NotifSubscription = Box::new(move |params: Value| {
+            let ready_tx = ready_tx.clone();
+            Box::pin(async move {
+                if let Some(quiescent) = params.get("quiescent").and_then(|q| q.as_bool()) {
+                    ready_tx.send_replace(quiescent);
+                }
+                false
+            })
+        });
 
-                let mut string = String::new();
-                while let Ok(_) = reader.read_line(&mut string).await {
-                    if string.is_empty() {
-                        break;
+        let published_diagnostics = Arc::new(Mutex::new(HashMap::new()));
+        let track_diagnostics: NotifSubscription = {
+            let published_diagnostics = published_diagnostics.clone();
+            Box::new(move |params: Value| {
+                let published_diagnostics = published_diagnostics.clone();
+                Box::pin(async move {
+                    if let Ok(params) = serde_json::from_value::<PublishDiagnosticsParams>(params) {
+                        published_diagnostics
+                            .lock()
+                            .await
+                            .insert(params.uri.to_string(), params.diagnostics);
                     }
-                    if tracing::enabled!(tracing::Level::DEBUG) {
-                        eprint!("[rust-analyzer]: {}", string);
+                    false
+                })
+            })
+        };
+
+        let progress = Arc::new(Mutex::new(Vec::new()));
+        let track_progress: NotifSubscription = {
+            let progress = progress.clone();
+            Box::new(move |params: Value| {
+                let progress = progress.clone();
+                Box::pin(async move {
+                    if let Ok(params) = serde_json::from_value::<ProgressParams>(params) {
+                        apply_progress(&mut *progress.lock().await, params);
                     }
-                    string.clear();
-                }
-            });
-        }
+                    false
+                })
+            })
+        };
 
-        let (request_tx, request_rx) = mpsc::unbounded_channel();
-        let pending_requests = std::sync::Arc::new(Mutex::new(HashMap::<
-            u64,
-            oneshot::Sender<Result<Value>>,
-        >::new()));
-        let notification_subscriptions = Arc::new(Mutex::new(HashMap::new()));
+        let notification_subscriptions = Arc::new(Mutex::new(HashMap::from([
+            ("experimental/serverStatus".to_string(), vec![track_status]),
+            (
+                "textDocument/publishDiagnostics".to_string(),
+                vec![track_diagnostics],
+            ),
+            ("$/progress".to_string(), vec![track_progress]),
+        ])));
 
         // Start I/O tasks
+        let alive = Arc::new(AtomicBool::new(true));
         tokio::spawn(Self::write_task(
-            stdin,
+            writer,
             request_rx,
             pending_requests.clone(),
         ));
-        tokio::spawn(Self::read_task(
-            stdout,
-            pending_requests,
-            notification_subscriptions.clone(),
-        ));
+        tokio::spawn({
+            let read_task = Self::read_task(
+                reader,
+                request_tx.clone(),
+                pending_requests.clone(),
+                notification_subscriptions.clone(),
+                server_messages.clone(),
+                profile.clone(),
+                configuration.clone(),
+            );
+            let alive = alive.clone();
+            let name = profile.name.clone();
+            let pending_requests = pending_requests.clone();
+            async move {
+                read_task.await;
+                Self::fail_pending_requests(&alive, &pending_requests, &name).await;
+            }
+        });
 
         let client = Self {
             child,
+            profile,
             request_tx,
             next_id: std::sync::atomic::AtomicU64::new(1),
+            pending_requests,
             notification_subscriptions,
+            server_messages,
+            server_log,
+            configuration,
+            ready_rx,
+            capabilities: std::sync::OnceLock::new(),
+            server_info: std::sync::OnceLock::new(),
+            position_encoding: std::sync::OnceLock::new(),
+            type_hierarchy_support: std::sync::OnceLock::new(),
+            published_diagnostics,
+            progress,
+            alive,
         };
 
         // Initialize
-        client.initialize(root_uri).await?;
+        client.initialize(roots).await?;
 
         Ok(client)
     }
 
     async fn write_task(
-        mut stdin: tokio::process::ChildStdin,
+        mut stdin: impl AsyncWrite + Unpin,
         mut request_rx: mpsc::UnboundedReceiver<LspMessage>,
-        pending_requests: std::sync::Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value>>>>>,
+        pending_requests: PendingRequests,
     ) {
-        while let Some(req) = request_rx.recv().await {
-            let (id, method, params) = match req {
-                LspMessage::Request(req) => {
-                    // Store the response channel
-                    pending_requests
-                        .lock()
-                        .await
-                        .insert(req.id, req.response_tx);
-                    (Some(req.id), req.method, Some(req.params))
+        while let Some(message) = request_rx.recv().await {
+            let (message, id) = JsonRpcMessage::from_lsp(message);
+            let frame = match message.frame() {
+                Ok(frame) => frame,
+                Err(e) => {
+                    // Only this message is lost; the stream is still intact.
+                    error!("Failed to serialize LSP message: {}", e);
+                    if let Some(id) = id
+                        && let Some(tx) = pending_requests.lock().await.remove(&id)
+                    {
+                        let _ = tx.send(Err(anyhow!("Failed to serialize request: {}", e)));
+                    }
+                    continue;
                 }
-                LspMessage::Notification(not) => (None, not.method, not.params),
             };
 
-            let mut message = serde_json::Map::new();
-            message.insert(
-                "jsonrpc".to_string(),
-                serde_json::Value::String("2.0".to_string()),
-            );
-            message.insert("method".to_string(), serde_json::Value::String(method));
-            if let Some(id) = id {
-                message.insert("id".to_string(), serde_json::Value::Number(id.into()));
-            }
-            if let Some(params) = params {
-                message.insert("params".to_string(), params);
-            }
-
-            let content = serde_json::to_string(&message).unwrap();
-            let header = format!("Content-Length: {}\r\n\r\n", content.len());
-
-            if let Err(e) = stdin.write_all(header.as_bytes()).await {
-                error!("Failed to write header: {}", e);
-                if let Some(id) = id
-                    && let Some(tx) = pending_requests.lock().await.remove(&id)
-                {
-                    let _ = tx.send(Err(anyhow!("Failed to write header: {}", e)));
-                }
-                break;
-            }
-            if let Err(e) = stdin.write_all(content.as_bytes()).await {
-                error!("Failed to write content: {}", e);
+            if let Err(e) = stdin.write_all(frame.as_bytes()).await {
+                error!("Failed to write message: {}", e);
                 if let Some(id) = id
                     && let Some(tx) = pending_requests.lock().await.remove(&id)
                 {
-                    let _ = tx.send(Err(anyhow!("Failed to write content: {}", e)));
+                    let _ = tx.send(Err(anyhow!("Failed to write message: {}", e)));
                 }
                 break;
             }
-            tracing::debug!("Sent LSP message ({} bytes): {}", content.len(), content);
+            tracing::debug!("Sent LSP message ({} bytes): {}", frame.len(), frame);
         }
     }
 
     async fn read_task(
-        stdout: tokio::process::ChildStdout,
-        pending_requests: Arc<Mutex<HashMap<u64, oneshot::Sender<Result<Value>>>>>,
+        stdout: impl AsyncRead + Unpin,
+        response_tx: mpsc::UnboundedSender<LspMessage>,
+        pending_requests: PendingRequests,
         notification_subscriptions: Arc<Mutex<HashMap<String, Vec<NotifSubscription>>>>,
+        server_messages: Arc<Mutex<VecDeque<ServerMessage>>>,
+        profile: ServerProfile,
+        configuration: Arc<Mutex<Value>>,
     ) {
         let mut reader = BufReader::new(stdout);
         let mut buffer = String::new();
@@ -206,16 +890,44 @@ impl LspClient {
             let content_str = String::from_utf8_lossy(&content);
             tracing::debug!("Received LSP message ({} bytes): {}", length, content_str);
             if let Ok(message) = serde_json::from_str::<Value>(&content_str) {
-                if let Some(id) = message.get("id").and_then(|v| v.as_u64()) {
+                let method = message.get("method").and_then(|v| v.as_str());
+                if let Some(
+                    method @ ("window/showMessage"
+                    | "window/logMessage"
+                    | "window/showMessageRequest"),
+                ) = method
+                {
+                    Self::record_server_message(&server_messages, &profile.name, method, &message)
+                        .await;
+                }
+
+                if let (Some(id), Some(method)) = (message.get("id"), method) {
+                    // A request initiated by the server.
+                    let params = message.get("params").cloned().unwrap_or(Value::Null);
+                    let result = server_request_response(
+                        method,
+                        &params,
+                        &profile.settings_section,
+                        &*configuration.lock().await,
+                    );
+                    let _ = response_tx.send(LspMessage::Response(LspResponse {
+                        id: id.clone(),
+                        result,
+                    }));
+                } else if let Some(id) = message.get("id").and_then(|v| v.as_u64()) {
                     if let Some(tx) = pending_requests.lock().await.remove(&id) {
                         let result = if let Some(error) = message.get("error") {
-                            Err(anyhow!("LSP error: {}", error))
+                            Err(LspError {
+                                code: error.get("code").and_then(|c| c.as_i64()).unwrap_or(0),
+                                error: error.clone(),
+                            }
+                            .into())
                         } else {
                             Ok(message.get("result").cloned().unwrap_or(Value::Null))
                         };
                         let _ = tx.send(result);
                     }
-                } else if let Some(method) = message.get("method").and_then(|v| v.as_str()) {
+                } else if let Some(method) = method {
                     let params = message.get("params").cloned().unwrap_or(Value::Null);
                     let mut subs = notification_subscriptions.lock().await;
                     let mut new_subs = Vec::with_capacity(subs.len());
@@ -230,27 +942,278 @@ impl LspClient {
         }
     }
 
+    /// Marks the server as gone once its output has ended, and fails every request still
+    /// waiting for a response instead of leaving it hanging.
+    async fn fail_pending_requests(
+        alive: &AtomicBool,
+        pending_requests: &Mutex<HashMap<u64, ResponseSender>>,
+        name: &str,
+    ) {
+        alive.store(false, Ordering::SeqCst);
+        error!("{} exited", name);
+        for (_, tx) in pending_requests.lock().await.drain() {
+            let _ = tx.send(Err(ServerExited(name.to_string()).into()));
+        }
+    }
+
+    /// Logs each line the server writes to stderr through tracing and keeps it in the
+    /// ring buffer.
+    async fn stderr_task(
+        stderr: tokio::process::ChildStderr,
+        name: String,
+        server_log: Arc<Mutex<VecDeque<String>>>,
+    ) {
+        let mut lines = BufReader::new(stderr).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            tracing::debug!("[{} stderr] {}", name, line);
+            let mut log = server_log.lock().await;
+            if log.len() == MAX_SERVER_LOG_LINES {
+                log.pop_front();
+            }
+            log.push_back(line);
+        }
+    }
+
+    /// Logs a `window/*` message from the server called `name` through tracing and keeps it
+    /// in the ring buffer.
+    async fn record_server_message(
+        server_messages: &Mutex<VecDeque<ServerMessage>>,
+        name: &str,
+        method: &str,
+        message: &Value,
+    ) {
+        let Some(params) = message.get("params") else {
+            return;
+        };
+        let text = params
+            .get("message")
+            .and_then(|m| m.as_str())
+            .unwrap_or_default()
+            .to_string();
+        let typ = params
+            .get("type")
+            .and_then(|t| serde_json::from_value::<MessageType>(t.clone()).ok())
+            .unwrap_or(MessageType::LOG);
+
+        let level = match typ {
+            MessageType::ERROR => {
+                tracing::error!("[{}] {}: {}", name, method, text);
+                "error"
+            }
+            MessageType::WARNING => {
+                tracing::warn!("[{}] {}: {}", name, method, text);
+                "warning"
+            }
+            MessageType::INFO => {
+                tracing::info!("[{}] {}: {}", name, method, text);
+                "info"
+            }
+            _ => {
+                tracing::debug!("[{}] {}: {}", name, method, text);
+                "log"
+            }
+        };
+
+        let mut messages = server_messages.lock().await;
+        if messages.len() == MAX_SERVER_MESSAGES {
+            messages.pop_front();
+        }
+        messages.push_back(ServerMessage {
+            method: method.to_string(),
+            level: level.to_string(),
+            message: text,
+        });
+    }
+
+    /// The most recent `window/*` messages from the server, oldest first.
+    pub async fn server_messages(&self) -> Vec<ServerMessage> {
+        self.server_messages.lock().await.iter().cloned().collect()
+    }
+
+    /// The work the server is doing, as reported through `$/progress`, oldest first.
+    pub async fn progress(&self) -> Vec<Progress> {
+        self.progress.lock().await.clone()
+    }
+
+    /// The most recent lines the server wrote to stderr, oldest first.
+    pub async fn server_log(&self) -> Vec<String> {
+        self.server_log.lock().await.iter().cloned().collect()
+    }
+
+    /// Whether the server is still running. Once it exits every request fails, and the
+    /// client should be replaced.
+    pub fn is_alive(&self) -> bool {
+        self.alive.load(Ordering::SeqCst)
+    }
+
+    /// Whether the server currently reports itself as quiescent (done loading and indexing).
+    pub fn is_ready(&self) -> bool {
+        *self.ready_rx.borrow()
+    }
+
+    /// A receiver that observes whether the server is quiescent, for waiting on it
+    /// without borrowing the client.
+    pub fn ready_receiver(&self) -> watch::Receiver<bool> {
+        self.ready_rx.clone()
+    }
+
+    /// Waits until the server reports itself as quiescent.
+    pub async fn wait_server_ready(&self) {
+        let mut ready_rx = self.ready_rx.clone();
+        let _ = ready_rx.wait_for(|ready| *ready).await;
+    }
+
     pub async fn request(&self, method: &str, params: Value) -> Result<Value> {
-        // FIXME: store server status and don't send prior to being ready
+        if !self.is_alive() {
+            return Err(ServerExited(self.profile.name.clone()).into());
+        }
+        if !self.supports(method) {
+            return Err(Unsupported {
+                server: self.profile.name.clone(),
+                method: method.to_string(),
+            }
+            .into());
+        }
+
+        let ungated = UNGATED_METHODS.contains(&method)
+            || self.profile.ungated_methods.iter().any(|m| m == method);
+        if !ungated
+            && tokio::time::timeout(REQUEST_READY_TIMEOUT, self.wait_server_ready())
+                .await
+                .is_err()
+        {
+            tracing::warn!("Sending {} before {} is ready", method, self.profile.name);
+        }
+
+        self.send_request(method, params).await
+    }
 
+    /// Sends a request without waiting for the server to be ready.
+    async fn send_request(&self, method: &str, params: Value) -> Result<Value> {
+        if !self.is_alive() {
+            return Err(ServerExited(self.profile.name.clone()).into());
+        }
         let id = self
             .next_id
             .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
         let (response_tx, response_rx) = oneshot::channel();
 
-        self.request_tx.send(LspMessage::Request(LspRequest {
+        // Register the sender before the request can be written, so even an immediate
+        // response finds it.
+        self.pending_requests.lock().await.insert(id, response_tx);
+        // The reader may have failed the pending requests just before the insert.
+        if !self.is_alive() {
+            self.pending_requests.lock().await.remove(&id);
+            return Err(ServerExited(self.profile.name.clone()).into());
+        }
+        let sent = self.request_tx.send(LspMessage::Request(LspRequest {
             id,
             method: method.to_string(),
             params,
-            response_tx,
-        }))?;
+        }));
+        if let Err(e) = sent {
+            self.pending_requests.lock().await.remove(&id);
+            return Err(e.into());
+        }
 
         response_rx.await?
     }
 
-    pub async fn notify(&self, method: &str, params: Option<Value>) -> Result<()> {
-        // FIXME: store server status and don't send prior to being ready
+    /// Sends a cheap request and times the response, without waiting for the server to
+    /// finish loading. Fails if no response arrives within `timeout`.
+    pub async fn ping(&self, timeout: Duration) -> Result<Duration> {
+        let params = WorkspaceSymbolParams {
+            query: String::new(),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        };
+        let start = std::time::Instant::now();
+        tokio::time::timeout(
+            timeout,
+            self.send_request("workspace/symbol", serde_json::to_value(params)?),
+        )
+        .await
+        .map_err(|_| {
+            anyhow!(
+                "{} did not respond within {:?}",
+                self.profile.name,
+                start.elapsed()
+            )
+        })??;
+        Ok(start.elapsed())
+    }
 
+    /// The capabilities from the server's initialize response, once it has been received.
+    pub fn capabilities(&self) -> Option<&ServerCapabilities> {
+        self.capabilities.get()
+    }
+
+    /// What the `character` of positions sent to and received from the server counts, as
+    /// the server chose at initialize.
+    pub fn position_encoding(&self) -> PositionEncoding {
+        self.position_encoding.get().copied().unwrap_or_default()
+    }
+
+    /// The server's name and version from its initialize response.
+    pub fn server_info(&self) -> Option<&ServerInfo> {
+        self.server_info.get()
+    }
+
+    /// Whether the server advertised support for the request `method`.
+    ///
+    /// Methods with no corresponding capability, such as rust-analyzer's own
+    /// extensions, are assumed to be supported.
+    pub fn supports(&self, method: &str) -> bool {
+        if TYPE_HIERARCHY_METHODS.contains(&method) {
+            return self.type_hierarchy_support.get().copied().unwrap_or(true);
+        }
+        self.capabilities
+            .get()
+            .and_then(|capabilities| method_supported(capabilities, method))
+            .unwrap_or(true)
+    }
+
+    /// The settings served to `workspace/configuration`.
+    pub async fn configuration(&self) -> Value {
+        self.configuration.lock().await.clone()
+    }
+
+    /// Changes the settings served to `workspace/configuration` and tells the server, which
+    /// picks them up without restarting.
+    pub async fn update_configuration(&self, update: impl FnOnce(&mut Value)) -> Result<()> {
+        let settings = {
+            let mut configuration = self.configuration.lock().await;
+            update(&mut configuration);
+            configuration.clone()
+        };
+        // Servers like rust-analyzer ignore these and ask for the settings again.
+        let params = DidChangeConfigurationParams {
+            settings: serde_json::json!({ &self.profile.settings_section: settings }),
+        };
+        self.notify(
+            "workspace/didChangeConfiguration",
+            Some(serde_json::to_value(params)?),
+        )
+        .await
+    }
+
+    /// Tells the server to start analyzing the `added` folders and stop analyzing the
+    /// `removed` ones.
+    pub async fn change_workspace_folders(&self, added: &[Uri], removed: &[Uri]) -> Result<()> {
+        let params = DidChangeWorkspaceFoldersParams {
+            event: WorkspaceFoldersChangeEvent {
+                added: added.iter().map(workspace_folder).collect(),
+                removed: removed.iter().map(workspace_folder).collect(),
+            },
+        };
+        self.notify(
+            "workspace/didChangeWorkspaceFolders",
+            Some(serde_json::to_value(params)?),
+        )
+        .await
+    }
+
+    pub async fn notify(&self, method: &str, params: Option<Value>) -> Result<()> {
         self.request_tx
             .send(LspMessage::Notification(LspNotification {
                 method: method.to_string(),
@@ -264,13 +1227,35 @@ impl LspClient {
     /// If the callback returns an `Err(e)`, then this function returns an `Err(e)`.
     /// If the callback returns an `Ok(None)`, then this function does not return (yet).
     /// If the callback returns an `Ok(Some(value))`, then this function returns `Ok(value)` and the notification is unsubscribed.
-    pub fn subscribe_notification<R: Send + 'static, F: Send + Sync + Clone + 'static>(
+    pub async fn subscribe_notification<R: Send + 'static, F>(
+        &self,
+        method: String,
+        callback: F,
+    ) -> Result<R>
+    where
+        F: Fn(Value) -> Pin<Box<dyn Future<Output = Result<Option<R>>> + Send>>
+            + Send
+            + Sync
+            + Clone
+            + 'static,
+    {
+        self.register_notification(method, callback).await.await?
+    }
+
+    /// Like [`Self::subscribe_notification`], but returns once the subscription is registered.
+    /// Use this to subscribe before sending whatever triggers the notification, so it can't
+    /// be missed.
+    pub async fn register_notification<R: Send + 'static, F>(
         &self,
         method: String,
         callback: F,
-    ) -> impl Future<Output = Result<R>> + use<'_, R, F>
+    ) -> oneshot::Receiver<Result<R>>
     where
-        F: Fn(Value) -> Pin<Box<dyn Future<Output = Result<Option<R>>> + Send>>,
+        F: Fn(Value) -> Pin<Box<dyn Future<Output = Result<Option<R>>> + Send>>
+            + Send
+            + Sync
+            + Clone
+            + 'static,
     {
         let (response_tx, response_rx) = oneshot::channel();
         let response_tx = Arc::new(Mutex::new(Some(response_tx)));
@@ -294,31 +1279,28 @@ impl LspClient {
             })
         };
 
-        async move {
-            let mut notifs_lock = self.notification_subscriptions.lock().await;
-            let notifs = notifs_lock
-                .entry(method.to_string())
-                .or_insert_with(Vec::new);
-            notifs.push(Box::new(f));
-            drop(notifs_lock);
+        let mut notifs_lock = self.notification_subscriptions.lock().await;
+        let notifs = notifs_lock
+            .entry(method.to_string())
+            .or_insert_with(Vec::new);
+        notifs.push(Box::new(f));
 
-            let response = response_rx.await?;
-            response
-        }
+        response_rx
     }
 
+    /// Initializes the server with `roots[0]` as its root URI. With more than one root, all
+    /// of them are also sent as workspace folders, for a server analyzing several.
     #[allow(deprecated)]
-    async fn initialize(&self, root_uri: Uri) -> Result<()> {
+    async fn initialize(&self, roots: Vec<Uri>) -> Result<()> {
+        let root_uri = roots
+            .first()
+            .cloned()
+            .ok_or_else(|| anyhow!("No workspace root to initialize"))?;
         let params = InitializeParams {
             process_id: Some(std::process::id()),
             root_path: None,
             root_uri: Some(root_uri),
-            initialization_options: Some(serde_json::json!({
-                "cargo": { "buildScripts": { "enable": true } },
-                "checkOnSave": { "enable": true, "command": "check" },
-                "diagnostics": { "enable": true },
-                "procMacro": { "enable": true }
-            })),
+            initialization_options: Some(self.configuration.lock().await.clone()),
             capabilities: ClientCapabilities {
                 text_document: Some(TextDocumentClientCapabilities {
                     hover: Some(HoverClientCapabilities {
@@ -330,6 +1312,10 @@ impl LspClient {
                         dynamic_registration: Some(false),
                         link_support: Some(false),
                     }),
+                    declaration: Some(GotoCapability {
+                        dynamic_registration: Some(false),
+                        link_support: Some(false),
+                    }),
                     references: Some(ReferenceClientCapabilities {
                         dynamic_registration: Some(false),
                     }),
@@ -339,18 +1325,67 @@ impl LspClient {
                         hierarchical_document_symbol_support: Some(true),
                         tag_support: None,
                     }),
+                    code_lens: Some(CodeLensClientCapabilities {
+                        dynamic_registration: Some(false),
+                    }),
+                    document_link: Some(DocumentLinkClientCapabilities {
+                        dynamic_registration: Some(false),
+                        tooltip_support: Some(true),
+                    }),
                     formatting: Some(DocumentFormattingClientCapabilities {
                         dynamic_registration: Some(false),
                     }),
+                    on_type_formatting: Some(DocumentOnTypeFormattingClientCapabilities {
+                        dynamic_registration: Some(false),
+                    }),
+                    linked_editing_range: Some(LinkedEditingRangeClientCapabilities {
+                        dynamic_registration: Some(false),
+                    }),
+                    type_hierarchy: Some(TypeHierarchyClientCapabilities {
+                        dynamic_registration: Some(false),
+                    }),
                     code_action: Some(CodeActionClientCapabilities {
                         dynamic_registration: Some(false),
-                        code_action_literal_support: None,
+                        // Without literal support rust-analyzer returns no actions at all.
+                        code_action_literal_support: Some(CodeActionLiteralSupport {
+                            code_action_kind: CodeActionKindLiteralSupport {
+                                value_set: [
+                                    CodeActionKind::EMPTY,
+                                    CodeActionKind::QUICKFIX,
+                                    CodeActionKind::REFACTOR,
+                                    CodeActionKind::REFACTOR_EXTRACT,
+                                    CodeActionKind::REFACTOR_INLINE,
+                                    CodeActionKind::REFACTOR_REWRITE,
+                                    CodeActionKind::SOURCE,
+                                ]
+                                .iter()
+                                .map(|kind| kind.as_str().to_string())
+                                .collect(),
+                            },
+                        }),
                         is_preferred_support: Some(false),
                         disabled_support: Some(false),
                         data_support: Some(false),
                         resolve_support: None,
                         honors_change_annotations: Some(false),
                     }),
+                    folding_range: Some(FoldingRangeClientCapabilities {
+                        dynamic_registration: Some(false),
+                        line_folding_only: Some(false),
+                        ..Default::default()
+                    }),
+                    selection_range: Some(SelectionRangeClientCapabilities {
+                        dynamic_registration: Some(false),
+                    }),
+                    semantic_tokens: Some(SemanticTokensClientCapabilities {
+                        dynamic_registration: Some(false),
+                        requests: SemanticTokensClientCapabilitiesRequests {
+                            range: Some(false),
+                            full: Some(SemanticTokensFullOptions::Bool(true)),
+                        },
+                        formats: vec![TokenFormat::RELATIVE],
+                        ..Default::default()
+                    }),
                     publish_diagnostics: Some(PublishDiagnosticsClientCapabilities {
                         related_information: Some(true),
                         tag_support: None,
@@ -364,6 +1399,7 @@ impl LspClient {
                     did_change_configuration: Some(DynamicRegistrationClientCapabilities {
                         dynamic_registration: Some(false),
                     }),
+                    workspace_folders: Some(true),
                     ..Default::default()
                 }),
                 window: Some(WindowClientCapabilities {
@@ -371,13 +1407,20 @@ impl LspClient {
                     show_message: None,
                     show_document: None,
                 }),
-                experimental: Some(serde_json::json!({
-                    "serverStatusNotification": true,
-                })),
+                general: Some(GeneralClientCapabilities {
+                    // UTF-8 spares converting columns, since Rust strings index by byte.
+                    position_encodings: Some(vec![
+                        PositionEncodingKind::UTF8,
+                        PositionEncodingKind::UTF16,
+                    ]),
+                    ..Default::default()
+                }),
+                experimental: self.profile.experimental_capabilities.clone(),
                 ..Default::default()
             },
             trace: Some(TraceValue::Off),
-            workspace_folders: None,
+            workspace_folders: (roots.len() > 1)
+                .then(|| roots.iter().map(workspace_folder).collect()),
             client_info: Some(ClientInfo {
                 name: "symposium-rust-analyzer".to_string(),
                 version: Some("0.1.0".to_string()),
@@ -386,16 +1429,24 @@ impl LspClient {
             work_done_progress_params: WorkDoneProgressParams::default(),
         };
 
-        let _response = self
+        let response = self
             .request("initialize", serde_json::to_value(params)?)
             .await?;
 
-        /*
-        if let Some(result) = response.get("capabilities") {
-            let capabilities: lsp_types::ServerCapabilities = serde_json::from_value(result.clone())?;
-            *self.capabilities.write().await = Some(ServerCapabilities::new(capabilities));
+        let _ = self.type_hierarchy_support.set(
+            response
+                .pointer("/capabilities/typeHierarchyProvider")
+                .is_some_and(|provider| !matches!(provider, Value::Null | Value::Bool(false))),
+        );
+        let result: InitializeResult = serde_json::from_value(response)
+            .map_err(|e| anyhow!("Invalid initialize response: {}", e))?;
+        let _ = self.position_encoding.set(PositionEncoding::from_kind(
+            result.capabilities.position_encoding.as_ref(),
+        ));
+        let _ = self.capabilities.set(result.capabilities);
+        if let Some(server_info) = result.server_info {
+            let _ = self.server_info.set(server_info);
         }
-        */
 
         self.notify("initialized", Some(serde_json::json!({})))
             .await?;
@@ -438,14 +1489,103 @@ impl LspClient {
         Ok(serde_json::from_value(result).unwrap_or(None))
     }
 
-    pub async fn find_references(
+    /// Unlike [`Self::goto_definition`], this stops at the item that introduces the name,
+    /// such as a `use` re-export or an `extern` block, rather than following it to its definition.
+    pub async fn go_to_declaration(
         &self,
         uri: Uri,
         position: Position,
-        include_declaration: bool,
-    ) -> Result<Option<Vec<Location>>> {
-        let params = ReferenceParams {
-            text_document_position: TextDocumentPositionParams {
+    ) -> Result<Option<request::GotoDeclarationResponse>> {
+        let params = request::GotoDeclarationParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request("textDocument/declaration", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or(None))
+    }
+
+    /// The definition of the type of the expression or binding at `position`.
+    pub async fn goto_type_definition(
+        &self,
+        uri: Uri,
+        position: Position,
+    ) -> Result<Option<request::GotoTypeDefinitionResponse>> {
+        let params = request::GotoTypeDefinitionParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request("textDocument/typeDefinition", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or(None))
+    }
+
+    /// The impls of the trait at `position`, or the trait impls of the type there.
+    pub async fn goto_implementation(
+        &self,
+        uri: Uri,
+        position: Position,
+    ) -> Result<Option<request::GotoImplementationResponse>> {
+        let params = request::GotoImplementationParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request("textDocument/implementation", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or(None))
+    }
+
+    /// The occurrences in `uri` of the symbol at `position`, each marked as a read or write
+    /// where the server can tell.
+    pub async fn document_highlight(
+        &self,
+        uri: Uri,
+        position: Position,
+    ) -> Result<Option<Vec<DocumentHighlight>>> {
+        let params = DocumentHighlightParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request(
+                "textDocument/documentHighlight",
+                serde_json::to_value(params)?,
+            )
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or(None))
+    }
+
+    pub async fn find_references(
+        &self,
+        uri: Uri,
+        position: Position,
+        include_declaration: bool,
+    ) -> Result<Option<Vec<Location>>> {
+        let params = ReferenceParams {
+            text_document_position: TextDocumentPositionParams {
                 text_document: TextDocumentIdentifier { uri },
                 position,
             },
@@ -462,10 +1602,68 @@ impl LspClient {
         Ok(serde_json::from_value(result).unwrap_or(None))
     }
 
+    pub async fn prepare_type_hierarchy(
+        &self,
+        uri: Uri,
+        position: Position,
+    ) -> Result<Vec<TypeHierarchyItem>> {
+        let params = TypeHierarchyPrepareParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        };
+
+        let result = self
+            .request(
+                "textDocument/prepareTypeHierarchy",
+                serde_json::to_value(params)?,
+            )
+            .await?;
+        let items: Option<Vec<TypeHierarchyItem>> = serde_json::from_value(result)?;
+        Ok(items.unwrap_or_default())
+    }
+
+    pub async fn type_hierarchy_supertypes(
+        &self,
+        item: TypeHierarchyItem,
+    ) -> Result<Vec<TypeHierarchyItem>> {
+        let params = TypeHierarchySupertypesParams {
+            item,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request("typeHierarchy/supertypes", serde_json::to_value(params)?)
+            .await?;
+        let items: Option<Vec<TypeHierarchyItem>> = serde_json::from_value(result)?;
+        Ok(items.unwrap_or_default())
+    }
+
+    pub async fn type_hierarchy_subtypes(
+        &self,
+        item: TypeHierarchyItem,
+    ) -> Result<Vec<TypeHierarchyItem>> {
+        let params = TypeHierarchySubtypesParams {
+            item,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request("typeHierarchy/subtypes", serde_json::to_value(params)?)
+            .await?;
+        let items: Option<Vec<TypeHierarchyItem>> = serde_json::from_value(result)?;
+        Ok(items.unwrap_or_default())
+    }
+
     pub async fn completion(
         &self,
         uri: Uri,
         position: Position,
+        context: CompletionContext,
     ) -> Result<Option<CompletionResponse>> {
         let params = CompletionParams {
             text_document_position: TextDocumentPositionParams {
@@ -474,7 +1672,7 @@ impl LspClient {
             },
             work_done_progress_params: WorkDoneProgressParams::default(),
             partial_result_params: PartialResultParams::default(),
-            context: None,
+            context: Some(context),
         };
 
         let result = self
@@ -496,6 +1694,64 @@ impl LspClient {
         Ok(serde_json::from_value(result).unwrap_or(None))
     }
 
+    /// Symbols anywhere in the workspace whose name matches `query`.
+    pub async fn workspace_symbols(&self, query: &str) -> Result<Option<WorkspaceSymbolResponse>> {
+        let params = WorkspaceSymbolParams {
+            query: query.to_string(),
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request("workspace/symbol", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or(None))
+    }
+
+    pub async fn code_lens(&self, uri: Uri) -> Result<Vec<CodeLens>> {
+        let params = CodeLensParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request("textDocument/codeLens", serde_json::to_value(params)?)
+            .await?;
+        let lenses: Option<Vec<CodeLens>> = serde_json::from_value(result).unwrap_or_default();
+        Ok(lenses.unwrap_or_default())
+    }
+
+    /// Fills in the command of a lens returned without one, e.g. a reference count.
+    pub async fn resolve_code_lens(&self, lens: CodeLens) -> Result<CodeLens> {
+        let result = self
+            .request("codeLens/resolve", serde_json::to_value(lens)?)
+            .await?;
+        Ok(serde_json::from_value(result)?)
+    }
+
+    pub async fn document_links(&self, uri: Uri) -> Result<Vec<DocumentLink>> {
+        let params = DocumentLinkParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request("textDocument/documentLink", serde_json::to_value(params)?)
+            .await?;
+        let links: Option<Vec<DocumentLink>> = serde_json::from_value(result).unwrap_or_default();
+        Ok(links.unwrap_or_default())
+    }
+
+    /// Fills in the target of a link returned without one.
+    pub async fn resolve_document_link(&self, link: DocumentLink) -> Result<DocumentLink> {
+        let result = self
+            .request("documentLink/resolve", serde_json::to_value(link)?)
+            .await?;
+        Ok(serde_json::from_value(result)?)
+    }
+
     pub async fn format_document(&self, uri: Uri) -> Result<Option<Vec<TextEdit>>> {
         let params = DocumentFormattingParams {
             text_document: TextDocumentIdentifier { uri },
@@ -516,6 +1772,73 @@ impl LspClient {
         Ok(serde_json::from_value(result).unwrap_or(None))
     }
 
+    /// The characters the server reformats on when typed, from its initialize response.
+    pub fn on_type_formatting_triggers(&self) -> Vec<String> {
+        self.capabilities
+            .get()
+            .and_then(|capabilities| capabilities.document_on_type_formatting_provider.as_ref())
+            .map(|options| {
+                std::iter::once(options.first_trigger_character.clone())
+                    .chain(options.more_trigger_character.iter().flatten().cloned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The edits the server makes after `ch` is typed just before `position`.
+    pub async fn on_type_formatting(
+        &self,
+        uri: Uri,
+        position: Position,
+        ch: &str,
+    ) -> Result<Vec<TextEdit>> {
+        let params = DocumentOnTypeFormattingParams {
+            text_document_position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            ch: ch.to_string(),
+            options: FormattingOptions {
+                tab_size: 4,
+                insert_spaces: true,
+                ..Default::default()
+            },
+        };
+
+        let result = self
+            .request(
+                "textDocument/onTypeFormatting",
+                serde_json::to_value(params)?,
+            )
+            .await?;
+        let edits: Option<Vec<TextEdit>> = serde_json::from_value(result)?;
+        Ok(edits.unwrap_or_default())
+    }
+
+    /// Ranges that must be edited together with the identifier at `position`, or
+    /// `None` if there are none.
+    pub async fn linked_editing_range(
+        &self,
+        uri: Uri,
+        position: Position,
+    ) -> Result<Option<LinkedEditingRanges>> {
+        let params = LinkedEditingRangeParams {
+            text_document_position_params: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+        };
+
+        let result = self
+            .request(
+                "textDocument/linkedEditingRange",
+                serde_json::to_value(params)?,
+            )
+            .await?;
+        Ok(serde_json::from_value(result)?)
+    }
+
     pub async fn code_actions(
         &self,
         uri: Uri,
@@ -530,12 +1853,22 @@ impl LspClient {
             partial_result_params: PartialResultParams::default(),
         };
 
-        let result = self
+        let mut result = self
             .request("textDocument/codeAction", serde_json::to_value(params)?)
             .await?;
+        strip_snippet_edits(&mut result);
         Ok(serde_json::from_value(result).unwrap_or(None))
     }
 
+    /// Fills in the `edit` of a code action the server left unresolved.
+    pub async fn resolve_code_action(&self, action: CodeAction) -> Result<CodeAction> {
+        let mut result = self
+            .request("codeAction/resolve", serde_json::to_value(action)?)
+            .await?;
+        strip_snippet_edits(&mut result);
+        Ok(serde_json::from_value(result)?)
+    }
+
     pub async fn did_open(
         &self,
         uri: Uri,
@@ -557,6 +1890,16 @@ impl LspClient {
         Ok(())
     }
 
+    pub async fn did_close(&self, uri: Uri) -> Result<()> {
+        let params = DidCloseTextDocumentParams {
+            text_document: TextDocumentIdentifier { uri },
+        };
+
+        self.notify("textDocument/didClose", Some(serde_json::to_value(params)?))
+            .await?;
+        Ok(())
+    }
+
     pub async fn did_change(
         &self,
         uri: Uri,
@@ -590,10 +1933,436 @@ impl LspClient {
             .await?;
         Ok(serde_json::from_value(result).unwrap_or(None))
     }
+
+    pub async fn folding_ranges(&self, uri: Uri) -> Result<Option<Vec<FoldingRange>>> {
+        let params = FoldingRangeParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request("textDocument/foldingRange", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or(None))
+    }
+    pub async fn selection_ranges(
+        &self,
+        uri: Uri,
+        positions: Vec<Position>,
+    ) -> Result<Option<Vec<SelectionRange>>> {
+        let params = SelectionRangeParams {
+            text_document: TextDocumentIdentifier { uri },
+            positions,
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request("textDocument/selectionRange", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or(None))
+    }
+    pub async fn semantic_tokens_full(&self, uri: Uri) -> Result<Option<SemanticTokens>> {
+        let params = SemanticTokensParams {
+            text_document: TextDocumentIdentifier { uri },
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request(
+                "textDocument/semanticTokens/full",
+                serde_json::to_value(params)?,
+            )
+            .await?;
+        let result: Option<SemanticTokensResult> = serde_json::from_value(result).unwrap_or(None);
+        Ok(result.map(|result| match result {
+            SemanticTokensResult::Tokens(tokens) => tokens,
+            SemanticTokensResult::Partial(partial) => SemanticTokens {
+                result_id: None,
+                data: partial.data,
+            },
+        }))
+    }
+
+    /// Semantic tokens for `uri`, decoded with the legend the server advertised at initialize.
+    pub async fn decoded_semantic_tokens(&self, uri: Uri) -> Result<Vec<DecodedSemanticToken>> {
+        let legend = self
+            .capabilities
+            .get()
+            .and_then(|capabilities| capabilities.semantic_tokens_provider.as_ref())
+            .map(|provider| match provider {
+                SemanticTokensServerCapabilities::SemanticTokensOptions(options) => &options.legend,
+                SemanticTokensServerCapabilities::SemanticTokensRegistrationOptions(options) => {
+                    &options.semantic_tokens_options.legend
+                }
+            })
+            .ok_or_else(|| anyhow!("Server did not advertise a semantic tokens legend"))?;
+        let tokens = self.semantic_tokens_full(uri).await?;
+        Ok(tokens
+            .map(|tokens| decode_semantic_tokens(&tokens.data, legend))
+            .unwrap_or_default())
+    }
+
+    /// The latest diagnostics the server pushed for each URI that has any.
+    pub async fn published_diagnostics(&self) -> HashMap<String, Vec<Diagnostic>> {
+        self.published_diagnostics
+            .lock()
+            .await
+            .iter()
+            .filter(|(_, diagnostics)| !diagnostics.is_empty())
+            .map(|(uri, diagnostics)| (uri.clone(), diagnostics.clone()))
+            .collect()
+    }
+
+    /// Every known diagnostic for `uri`, ordered by position: those pulled with
+    /// `textDocument/diagnostic` plus those pushed by the server, e.g. from `cargo check`.
+    pub async fn document_diagnostics(&self, uri: Uri) -> Result<Vec<Diagnostic>> {
+        let mut diagnostics = self
+            .published_diagnostics
+            .lock()
+            .await
+            .get(&uri.to_string())
+            .cloned()
+            .unwrap_or_default();
+
+        let pulled = if self.supports("textDocument/diagnostic") {
+            self.diagnostics(uri).await?
+        } else {
+            None
+        };
+        if let Some(DocumentDiagnosticReport::Full(report)) = pulled {
+            for diagnostic in report.full_document_diagnostic_report.items {
+                if !diagnostics.contains(&diagnostic) {
+                    diagnostics.push(diagnostic);
+                }
+            }
+        }
+
+        diagnostics.sort_by_key(|d| (d.range.start.line, d.range.start.character));
+        Ok(diagnostics)
+    }
+
+    /// Diagnostics for every file in the workspace, keyed by URI.
+    ///
+    /// Uses `workspace/diagnostic` when the server supports it, and otherwise
+    /// falls back to what has been pushed through `textDocument/publishDiagnostics`.
+    pub async fn workspace_diagnostics(&self) -> Result<HashMap<String, Vec<Diagnostic>>> {
+        if !self.supports("workspace/diagnostic") {
+            return Ok(self.published_diagnostics().await);
+        }
+
+        let params = WorkspaceDiagnosticParams {
+            identifier: None,
+            previous_result_ids: vec![],
+            work_done_progress_params: WorkDoneProgressParams::default(),
+            partial_result_params: PartialResultParams::default(),
+        };
+
+        let result = self
+            .request("workspace/diagnostic", serde_json::to_value(params)?)
+            .await?;
+        let items = match serde_json::from_value(result)? {
+            WorkspaceDiagnosticReportResult::Report(report) => report.items,
+            WorkspaceDiagnosticReportResult::Partial(partial) => partial.items,
+        };
+        Ok(items
+            .into_iter()
+            .filter_map(|item| match item {
+                WorkspaceDocumentDiagnosticReport::Full(full) => Some((
+                    full.uri.to_string(),
+                    full.full_document_diagnostic_report.items,
+                )),
+                WorkspaceDocumentDiagnosticReport::Unchanged(_) => None,
+            })
+            .filter(|(_, diagnostics)| !diagnostics.is_empty())
+            .collect())
+    }
 }
 
 impl Drop for LspClient {
     fn drop(&mut self) {
-        let _ = self.child.kill();
+        if let Some(child) = &mut self.child {
+            let _ = child.start_kill();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn semantic_tokens_are_decoded_to_absolute_positions() {
+        let legend = SemanticTokensLegend {
+            token_types: vec![SemanticTokenType::KEYWORD, SemanticTokenType::VARIABLE],
+            token_modifiers: vec![
+                SemanticTokenModifier::DECLARATION,
+                SemanticTokenModifier::new("mutable"),
+            ],
+        };
+        let data = [
+            SemanticToken {
+                delta_line: 2,
+                delta_start: 4,
+                length: 3,
+                token_type: 0,
+                token_modifiers_bitset: 0,
+            },
+            SemanticToken {
+                delta_line: 0,
+                delta_start: 4,
+                length: 1,
+                token_type: 1,
+                token_modifiers_bitset: 0b11,
+            },
+            SemanticToken {
+                delta_line: 1,
+                delta_start: 8,
+                length: 1,
+                token_type: 1,
+                token_modifiers_bitset: 0,
+            },
+        ];
+
+        let decoded = decode_semantic_tokens(&data, &legend);
+
+        let positions: Vec<_> = decoded.iter().map(|t| (t.line, t.start)).collect();
+        assert_eq!(positions, vec![(2, 4), (2, 8), (3, 8)]);
+        assert_eq!(decoded[0].token_type, "keyword");
+        assert_eq!(decoded[1].modifiers, vec!["declaration", "mutable"]);
+        assert!(decoded[2].modifiers.is_empty());
+    }
+
+    #[test]
+    fn workspace_configuration_is_answered_with_settings() {
+        let configuration = serde_json::json!({ "diagnostics": { "enable": true } });
+        let params = serde_json::json!({
+            "items": [{ "section": "rust-analyzer" }, { "section": "editor" }]
+        });
+
+        let result = server_request_response(
+            "workspace/configuration",
+            &params,
+            "rust-analyzer",
+            &configuration,
+        );
+
+        assert_eq!(result, Ok(serde_json::json!([configuration, null])));
+    }
+
+    #[test]
+    fn missing_server_binary_is_explained() {
+        let error = std::io::Error::from(std::io::ErrorKind::NotFound);
+
+        let message = spawn_error("/nonexistent/taplo", "install taplo-cli", error).to_string();
+
+        assert_eq!(message, "/nonexistent/taplo not found; install taplo-cli");
+    }
+
+    #[test]
+    fn messages_are_framed_with_their_length() {
+        let (message, request) =
+            JsonRpcMessage::from_lsp(LspMessage::Notification(LspNotification {
+                method: "initialized".to_string(),
+                params: None,
+            }));
+
+        let frame = message.frame().unwrap();
+        let content = r#"{"jsonrpc":"2.0","method":"initialized"}"#;
+        assert_eq!(
+            frame,
+            format!("Content-Length: {}\r\n\r\n{}", content.len(), content)
+        );
+        assert!(request.is_none());
+    }
+
+    #[test]
+    fn error_responses_carry_no_result() {
+        let (message, _) = JsonRpcMessage::from_lsp(LspMessage::Response(LspResponse {
+            id: Value::from(7),
+            result: Err(serde_json::json!({ "code": METHOD_NOT_FOUND })),
+        }));
+
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({ "jsonrpc": "2.0", "id": 7, "error": { "code": METHOD_NOT_FOUND } })
+        );
+    }
+
+    /// Reads one framed message, as a server would.
+    async fn read_message(reader: &mut (impl AsyncBufReadExt + Unpin)) -> Value {
+        let mut header = String::new();
+        reader.read_line(&mut header).await.unwrap();
+        let length: usize = header["Content-Length:".len()..].trim().parse().unwrap();
+        reader.read_line(&mut String::new()).await.unwrap();
+        let mut content = vec![0; length];
+        tokio::io::AsyncReadExt::read_exact(reader, &mut content)
+            .await
+            .unwrap();
+        serde_json::from_slice(&content).unwrap()
+    }
+
+    /// Writes one framed message, as a server would.
+    async fn write_message(writer: &mut (impl AsyncWriteExt + Unpin), message: Value) {
+        let content = message.to_string();
+        let frame = format!("Content-Length: {}\r\n\r\n{}", content.len(), content);
+        writer.write_all(frame.as_bytes()).await.unwrap();
+    }
+
+    /// Answers the initialize request with empty capabilities.
+    async fn answer_initialize(server_io: &mut (impl AsyncBufReadExt + AsyncWriteExt + Unpin)) {
+        let initialize = read_message(server_io).await;
+        let response = serde_json::json!({
+            "jsonrpc": "2.0",
+            "id": initialize["id"],
+            "result": { "capabilities": {} },
+        });
+        write_message(server_io, response).await;
+    }
+
+    /// Starts a client talking to an in-memory server.
+    async fn start_test_client(client_io: tokio::io::DuplexStream) -> LspClient {
+        let (client_reader, client_writer) = tokio::io::split(client_io);
+        let profile = ServerProfile {
+            name: "test-server".to_string(),
+            settings_section: "test".to_string(),
+            experimental_capabilities: None,
+            reports_status: false,
+            ungated_methods: Vec::new(),
+            install_hint: String::new(),
+        };
+        LspClient::start(
+            None,
+            client_reader,
+            client_writer,
+            Arc::default(),
+            vec!["file:///workspace".parse().unwrap()],
+            profile,
+            Value::Null,
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn pending_requests_fail_when_the_server_exits() {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let server = tokio::spawn(async move {
+            let mut server_io = BufReader::new(server_io);
+            answer_initialize(&mut server_io).await;
+            // Take the next request, then exit without answering it.
+            while read_message(&mut server_io).await.get("id").is_none() {}
+        });
+        let client = start_test_client(client_io).await;
+
+        // A method no capability covers, so the request goes out despite the empty ones.
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            client.request("test/slow", Value::Null),
+        )
+        .await
+        .expect("the request should fail rather than hang");
+
+        server.await.unwrap();
+        assert_eq!(result.unwrap_err().to_string(), "test-server exited");
+        assert!(!client.is_alive());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_requests_all_get_their_responses() {
+        const REQUESTS: u64 = 500;
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move {
+            let mut server_io = BufReader::new(server_io);
+            answer_initialize(&mut server_io).await;
+            // Echo each request's params back as soon as it's read.
+            loop {
+                let message = read_message(&mut server_io).await;
+                if message.get("id").is_some() {
+                    let response = serde_json::json!({
+                        "jsonrpc": "2.0",
+                        "id": message["id"],
+                        "result": message["params"],
+                    });
+                    write_message(&mut server_io, response).await;
+                }
+            }
+        });
+        let client = Arc::new(start_test_client(client_io).await);
+
+        let requests = (0..REQUESTS).map(|i| {
+            let client = client.clone();
+            tokio::spawn(async move { client.request("test/echo", Value::from(i)).await })
+        });
+        let results =
+            tokio::time::timeout(Duration::from_secs(10), futures::future::join_all(requests))
+                .await
+                .expect("every request should get a response");
+
+        for (i, result) in results.into_iter().enumerate() {
+            assert_eq!(result.unwrap().unwrap(), Value::from(i));
+        }
+    }
+
+    #[test]
+    fn progress_is_tracked_from_begin_to_end() {
+        let notification = |token: &str, value: Value| {
+            serde_json::from_value::<ProgressParams>(
+                serde_json::json!({ "token": token, "value": value }),
+            )
+            .unwrap()
+        };
+        let mut progress = Vec::new();
+
+        apply_progress(
+            &mut progress,
+            notification(
+                "rustAnalyzer/Indexing",
+                serde_json::json!({ "kind": "begin", "title": "Indexing", "percentage": 0 }),
+            ),
+        );
+        apply_progress(
+            &mut progress,
+            notification(
+                "rustAnalyzer/Indexing",
+                serde_json::json!({ "kind": "report", "message": "3/5 (serde)", "percentage": 60 }),
+            ),
+        );
+        assert_eq!(
+            progress,
+            [Progress {
+                token: "rustAnalyzer/Indexing".to_string(),
+                title: "Indexing".to_string(),
+                message: Some("3/5 (serde)".to_string()),
+                percentage: Some(60),
+            }]
+        );
+
+        apply_progress(
+            &mut progress,
+            notification(
+                "rustAnalyzer/Indexing",
+                serde_json::json!({ "kind": "end" }),
+            ),
+        );
+        assert!(progress.is_empty());
+    }
+
+    #[test]
+    fn workspace_folders_are_named_after_their_directory() {
+        let folder = workspace_folder(&"file:///repo/crates/my%20crate/".parse().unwrap());
+
+        assert_eq!(folder.name, "my crate");
+    }
+
+    #[test]
+    fn unsupported_server_request_is_method_not_found() {
+        let result = server_request_response("custom/unknown", &Value::Null, "", &Value::Null);
+
+        let error = result.unwrap_err();
+        assert_eq!(error["code"], METHOD_NOT_FOUND);
     }
 }
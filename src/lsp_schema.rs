@@ -0,0 +1,43 @@
+//! JSON schemas for the LSP types that show up in tool output.
+//!
+//! `lsp_types` doesn't implement `JsonSchema`, so output fields holding these types point
+//! `#[schemars(with = "...")]` at the mirrors below. Other LSP values are described as
+//! arbitrary JSON.
+
+// The mirrors are only ever used for their schemas.
+#![allow(dead_code)]
+
+use schemars::JsonSchema;
+
+/// A 0-based position in a document; `character` counts the units of the negotiated
+/// position encoding (UTF-8 bytes with rust-analyzer, UTF-16 code units by default).
+#[derive(JsonSchema)]
+#[schemars(rename = "Position")]
+pub struct PositionSchema {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// A range in a document, with an exclusive `end`.
+#[derive(JsonSchema)]
+#[schemars(rename = "Range")]
+pub struct RangeSchema {
+    pub start: PositionSchema,
+    pub end: PositionSchema,
+}
+
+/// A range in the document at `uri`.
+#[derive(JsonSchema)]
+#[schemars(rename = "Location")]
+pub struct LocationSchema {
+    pub uri: String,
+    pub range: RangeSchema,
+}
+
+/// Replaces `range` with `newText`.
+#[derive(JsonSchema)]
+#[schemars(rename = "TextEdit", rename_all = "camelCase")]
+pub struct TextEditSchema {
+    pub range: RangeSchema,
+    pub new_text: String,
+}
@@ -14,7 +14,15 @@ async fn run_proxy() -> Result<()> {
 }
 
 pub async fn run_mcp() -> Result<()> {
-    let mcp = build_server(None).await?;
+    let mcp = build_server(
+        None,
+        Vec::new(),
+        Default::default(),
+        None,
+        Default::default(),
+        None,
+    )
+    .await?;
     let stido = ByteStreams::new(
         tokio::io::stdout().compat_write(),
         tokio::io::stdin().compat(),
@@ -0,0 +1,55 @@
+//! Paging through tool results too large for one response, with `offset` and `limit`
+//! inputs and a `total` and `has_more` in the output.
+
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// Which page of a result to return, flattened into a tool's inputs.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, JsonSchema)]
+pub struct PageInputs {
+    /// How many results to skip, to fetch a later page. Defaults to 0.
+    pub offset: Option<usize>,
+    /// Return at most this many results. Each tool documents its default.
+    pub limit: Option<usize>,
+}
+
+/// Where a page sits in the whole result, flattened into a tool's output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, JsonSchema)]
+pub struct PageInfo {
+    /// How many results there are across all pages.
+    pub total: usize,
+    /// Whether there are results past this page; fetch them with a larger `offset`.
+    pub has_more: bool,
+}
+
+impl PageInputs {
+    /// Cuts the requested page out of `items`. Without a `limit`, at most `default_limit`
+    /// items are returned, or all of them if that is `None`.
+    pub fn page<T>(&self, items: Vec<T>, default_limit: Option<usize>) -> (Vec<T>, PageInfo) {
+        let total = items.len();
+        let offset = self.offset.unwrap_or(0);
+        let limit = self.limit.or(default_limit).unwrap_or(usize::MAX);
+        let items: Vec<T> = items.into_iter().skip(offset).take(limit).collect();
+        let has_more = offset.saturating_add(items.len()) < total;
+        (items, PageInfo { total, has_more })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_cover_the_items_in_order() {
+        let page = |offset, limit| {
+            let (items, page) = PageInputs { offset, limit }.page((0..5).collect(), Some(2));
+            (items, page.total, page.has_more)
+        };
+
+        assert_eq!(page(None, None), (vec![0, 1], 5, true));
+        assert_eq!(page(Some(2), Some(3)), (vec![2, 3, 4], 5, false));
+        assert_eq!(page(Some(9), None), (vec![], 5, false));
+        let (all, _) = PageInputs::default().page((0..500).collect::<Vec<_>>(), None);
+        assert_eq!(all.len(), 500);
+    }
+}
@@ -0,0 +1,726 @@
+//! What is specific to rust-analyzer: how to start it, and its extensions to LSP that
+//! `lsp_types` doesn't cover.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use lsp_types::{
+    GotoDefinitionResponse, Location, LocationLink, Position, Range, TextDocumentIdentifier,
+    TextDocumentPositionParams, TextEdit, Uri, WorkspaceEdit, WorkspaceSymbolResponse,
+};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::lsp_client::{LspClient, ServerProfile};
+use crate::snippets::SnippetTextEdit;
+
+/// How long [`LspClient::rebuild_proc_macros`] and [`LspClient::reload_workspace`] wait for
+/// the server to start working.
+const REBUILD_START_WAIT: Duration = Duration::from_secs(2);
+
+/// The most symbols a workspace symbol search returns. rust-analyzer's default of 128 is
+/// too few to list every function of a workspace.
+pub const WORKSPACE_SYMBOL_LIMIT: usize = 5000;
+
+/// How [`LspClient`] should drive rust-analyzer.
+pub fn rust_analyzer_profile() -> ServerProfile {
+    ServerProfile {
+        name: "rust-analyzer".to_string(),
+        settings_section: "rust-analyzer".to_string(),
+        experimental_capabilities: Some(serde_json::json!({
+            "serverStatusNotification": true,
+            // Lets assists like "Generate function" return tabstops; see `crate::snippets`.
+            "snippetTextEdit": true,
+            // rust-analyzer only emits code lenses whose commands the client claims to support.
+            "commands": {
+                "commands": [
+                    "rust-analyzer.runSingle",
+                    "rust-analyzer.debugSingle",
+                    "rust-analyzer.showReferences",
+                    "rust-analyzer.gotoLocation",
+                ],
+            },
+        })),
+        reports_status: true,
+        ungated_methods: vec![
+            "rust-analyzer/analyzerStatus".to_string(),
+            "rust-analyzer/memoryUsage".to_string(),
+            // Stale proc macros can be why the server isn't done.
+            "rust-analyzer/rebuildProcMacros".to_string(),
+        ],
+        install_hint: "install via `rustup component add rust-analyzer` or set RUST_ANALYZER_PATH"
+            .to_string(),
+    }
+}
+
+/// Starts rust-analyzer for `roots`, the workspace root followed by any other folders to
+/// analyze, with `settings` merged over our defaults and `env` set in its environment.
+pub async fn start_rust_analyzer(
+    command: &str,
+    env: &HashMap<String, String>,
+    roots: Vec<Uri>,
+    settings: Value,
+) -> Result<LspClient> {
+    LspClient::new(
+        command,
+        &[],
+        env,
+        roots,
+        rust_analyzer_profile(),
+        configuration(settings),
+    )
+    .await
+}
+
+/// Connects to a rust-analyzer listening at `addr`, with `settings` merged over our defaults.
+pub async fn connect_rust_analyzer(
+    addr: &str,
+    roots: Vec<Uri>,
+    settings: Value,
+) -> Result<LspClient> {
+    LspClient::connect_tcp(
+        addr,
+        roots,
+        rust_analyzer_profile(),
+        configuration(settings),
+    )
+    .await
+}
+
+/// Settings switching the check rust-analyzer runs on save to `command`.
+fn check_command_settings(command: CheckCommand) -> Value {
+    // `check.command` replaced `checkOnSave.command`; send both for older servers.
+    serde_json::json!({
+        "check": { "command": command.as_str() },
+        "checkOnSave": { "command": command.as_str() },
+    })
+}
+
+/// Our default rust-analyzer settings with `overrides` merged in.
+fn configuration(overrides: Value) -> Value {
+    let mut configuration = serde_json::json!({
+        "cargo": { "buildScripts": { "enable": true } },
+        "check": { "command": "check" },
+        "checkOnSave": { "enable": true, "command": "check" },
+        "diagnostics": { "enable": true },
+        "procMacro": { "enable": true },
+        "workspace": { "symbol": { "search": { "limit": WORKSPACE_SYMBOL_LIMIT } } }
+    });
+    merge_settings(&mut configuration, overrides);
+    configuration
+}
+
+/// Merges `overrides` into `base`, recursing into objects present in both.
+fn merge_settings(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                merge_settings(base.entry(key).or_insert(Value::Null), value);
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RunnablesParams {
+    pub text_document: TextDocumentIdentifier,
+    pub position: Option<Position>,
+}
+
+/// A test, bench or binary that rust-analyzer knows how to run.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Runnable {
+    pub label: String,
+    /// An LSP `LocationLink` to the runnable's item.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Value>")]
+    pub location: Option<LocationLink>,
+    /// `cargo` or `shell`; determines the shape of `args`.
+    pub kind: String,
+    pub args: Value,
+}
+
+/// A test related to the symbol under the cursor, as returned by `rust-analyzer/relatedTests`.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TestInfo {
+    pub runnable: Runnable,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatchingBraceParams {
+    pub text_document: TextDocumentIdentifier,
+    pub positions: Vec<Position>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JoinLinesParams {
+    pub text_document: TextDocumentIdentifier,
+    pub ranges: Vec<Range>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MoveItemDirection {
+    Up,
+    Down,
+}
+
+impl std::str::FromStr for MoveItemDirection {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "up" => Ok(Self::Up),
+            "down" => Ok(Self::Down),
+            _ => Err(anyhow!(
+                "Invalid direction `{}`: expected \"up\" or \"down\"",
+                s
+            )),
+        }
+    }
+}
+
+/// The cargo command rust-analyzer runs to check the workspace when a file is saved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckCommand {
+    Check,
+    Clippy,
+}
+
+impl CheckCommand {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Check => "check",
+            Self::Clippy => "clippy",
+        }
+    }
+}
+
+impl std::str::FromStr for CheckCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "check" => Ok(Self::Check),
+            "clippy" => Ok(Self::Clippy),
+            _ => Err(anyhow!(
+                "Invalid check command `{}`: expected \"check\" or \"clippy\"",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MoveItemParams {
+    pub direction: MoveItemDirection,
+    pub text_document: TextDocumentIdentifier,
+    pub range: Range,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SsrParams {
+    pub query: String,
+    pub parse_only: bool,
+    /// Document and position used to resolve paths mentioned in the rule.
+    #[serde(flatten)]
+    pub position: TextDocumentPositionParams,
+    /// Ranges to restrict the search to; empty searches the whole workspace.
+    pub selections: Vec<Range>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AnalyzerStatusParams {
+    pub text_document: Option<TextDocumentIdentifier>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyntaxTreeParams {
+    pub text_document: TextDocumentIdentifier,
+    /// Only the part of the tree covering this range; the whole file if `None`.
+    pub range: Option<Range>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpenCargoTomlParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewSyntaxTreeParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewItemTreeParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InterpretFunctionParams {
+    pub text_document: TextDocumentIdentifier,
+    /// A position inside the function to run.
+    pub position: Position,
+}
+
+/// What running a function in rust-analyzer's MIR interpreter printed, followed by its
+/// return value or the reason evaluation stopped.
+pub type InterpretFunctionResponse = String;
+
+/// `WorkspaceSymbolParams` with rust-analyzer's choice of which symbols to search.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceSymbolSearchParams {
+    pub query: String,
+    pub search_scope: Option<WorkspaceSymbolSearchScope>,
+    pub search_kind: Option<WorkspaceSymbolSearchKind>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WorkspaceSymbolSearchScope {
+    Workspace,
+    WorkspaceAndDependencies,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WorkspaceSymbolSearchKind {
+    OnlyTypes,
+    AllSymbols,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewCrateGraphParams {
+    /// Include dependencies from crates.io and the sysroot, not just workspace members.
+    pub full: bool,
+}
+
+impl LspClient {
+    /// Switches the check run on save between `cargo check` and `cargo clippy`, taking
+    /// effect from the next save or workspace check.
+    pub async fn set_check_command(&self, command: CheckCommand) -> Result<()> {
+        self.update_configuration(|configuration| {
+            merge_settings(configuration, check_command_settings(command))
+        })
+        .await
+    }
+
+    /// Merges `settings`, a JSON object of rust-analyzer settings, into the current ones and
+    /// returns the result, which the server picks up without restarting. A `null` value
+    /// restores rust-analyzer's default for that setting.
+    pub async fn configure(&self, settings: Value) -> Result<Value> {
+        if !settings.is_object() {
+            return Err(anyhow!(
+                "Settings must be a JSON object, got `{}`",
+                settings
+            ));
+        }
+        let mut merged = Value::Null;
+        self.update_configuration(|configuration| {
+            merge_settings(configuration, settings);
+            merged = configuration.clone();
+        })
+        .await?;
+        Ok(merged)
+    }
+
+    pub async fn runnables(&self, uri: Uri, position: Option<Position>) -> Result<Vec<Runnable>> {
+        let params = RunnablesParams {
+            text_document: TextDocumentIdentifier { uri },
+            position,
+        };
+
+        let result = self
+            .request("experimental/runnables", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    }
+
+    pub async fn related_tests(&self, uri: Uri, position: Position) -> Result<Vec<TestInfo>> {
+        let params = TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri },
+            position,
+        };
+
+        let result = self
+            .request("rust-analyzer/relatedTests", serde_json::to_value(params)?)
+            .await?;
+        let tests: Option<Vec<TestInfo>> = serde_json::from_value(result).unwrap_or_default();
+        Ok(tests.unwrap_or_default())
+    }
+
+    /// The module declaration(s) (`mod foo;`) that include the item at `position`.
+    /// Returns `None` at a crate root, which has no parent.
+    pub async fn parent_module(
+        &self,
+        uri: Uri,
+        position: Position,
+    ) -> Result<Option<GotoDefinitionResponse>> {
+        let params = TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri },
+            position,
+        };
+
+        let result = self
+            .request("experimental/parentModule", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or(None))
+    }
+
+    /// The `Cargo.toml` of the package that owns the document.
+    pub async fn open_cargo_toml(&self, uri: Uri) -> Result<Option<Location>> {
+        let params = OpenCargoTomlParams {
+            text_document: TextDocumentIdentifier { uri },
+        };
+
+        let result = self
+            .request("experimental/openCargoToml", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or(None))
+    }
+
+    /// Edits to apply instead of inserting a plain newline, e.g. continuing a doc comment.
+    /// Returns `None` when a plain newline is fine.
+    pub async fn on_enter(
+        &self,
+        uri: Uri,
+        position: Position,
+    ) -> Result<Option<Vec<SnippetTextEdit>>> {
+        let params = TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri },
+            position,
+        };
+
+        let result = self
+            .request("experimental/onEnter", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or(None))
+    }
+
+    /// For each position, the position of the matching brace, or the position itself if
+    /// it isn't next to a brace.
+    pub async fn matching_brace(
+        &self,
+        uri: Uri,
+        positions: Vec<Position>,
+    ) -> Result<Vec<Position>> {
+        let params = MatchingBraceParams {
+            text_document: TextDocumentIdentifier { uri },
+            positions,
+        };
+
+        let result = self
+            .request("experimental/matchingBrace", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    }
+
+    /// Edits that join the lines covered by each range.
+    pub async fn join_lines(&self, uri: Uri, ranges: Vec<Range>) -> Result<Vec<TextEdit>> {
+        let params = JoinLinesParams {
+            text_document: TextDocumentIdentifier { uri },
+            ranges,
+        };
+
+        let result = self
+            .request("experimental/joinLines", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    }
+
+    /// Edits that swap the item (function, impl, statement, match arm...) at `range` with its
+    /// neighbour in `direction`. Empty if there is nothing to move.
+    pub async fn move_item(
+        &self,
+        uri: Uri,
+        range: Range,
+        direction: MoveItemDirection,
+    ) -> Result<Vec<SnippetTextEdit>> {
+        let params = MoveItemParams {
+            direction,
+            text_document: TextDocumentIdentifier { uri },
+            range,
+        };
+
+        let result = self
+            .request("experimental/moveItem", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    }
+
+    /// Runs a structural search and replace rule such as `foo($a) ==>> bar($a)`.
+    pub async fn ssr(
+        &self,
+        query: String,
+        parse_only: bool,
+        uri: Uri,
+        position: Position,
+    ) -> Result<Option<WorkspaceEdit>> {
+        let params = SsrParams {
+            query,
+            parse_only,
+            position: TextDocumentPositionParams {
+                text_document: TextDocumentIdentifier { uri },
+                position,
+            },
+            selections: vec![],
+        };
+
+        let result = self
+            .request("experimental/ssr", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or(None))
+    }
+
+    /// Text dump of the HIR for the function at `position`.
+    pub async fn view_hir(&self, uri: Uri, position: Position) -> Result<String> {
+        self.text_position_request("rust-analyzer/viewHir", uri, position)
+            .await
+    }
+
+    /// Text dump of the MIR for the function at `position`.
+    pub async fn view_mir(&self, uri: Uri, position: Position) -> Result<String> {
+        self.text_position_request("rust-analyzer/viewMir", uri, position)
+            .await
+    }
+
+    /// Text dump of the parsed syntax tree of the document, or of the part covering `range`.
+    /// Newer rust-analyzer versions replaced this with [`Self::view_syntax_tree`].
+    pub async fn syntax_tree(&self, uri: Uri, range: Option<Range>) -> Result<String> {
+        let params = SyntaxTreeParams {
+            text_document: TextDocumentIdentifier { uri },
+            range,
+        };
+
+        let result = self
+            .request("rust-analyzer/syntaxTree", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    }
+
+    /// The syntax tree of the document as JSON; see [`crate::syntax_tree`].
+    pub async fn view_syntax_tree(&self, uri: Uri) -> Result<String> {
+        let params = ViewSyntaxTreeParams {
+            text_document: TextDocumentIdentifier { uri },
+        };
+
+        let result = self
+            .request(
+                "rust-analyzer/viewSyntaxTree",
+                serde_json::to_value(params)?,
+            )
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    }
+
+    /// Text dump of the item tree of the document: the items, imports and macro calls
+    /// rust-analyzer records for it before name resolution.
+    pub async fn view_item_tree(&self, uri: Uri) -> Result<String> {
+        let params = ViewItemTreeParams {
+            text_document: TextDocumentIdentifier { uri },
+        };
+
+        let result = self
+            .request("rust-analyzer/viewItemTree", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    }
+
+    /// Runs the function at `position` in rust-analyzer's MIR interpreter. Fails with an
+    /// LSP error for functions that can't be interpreted, such as ones taking arguments.
+    pub async fn interpret_function(
+        &self,
+        uri: Uri,
+        position: Position,
+    ) -> Result<InterpretFunctionResponse> {
+        let params = InterpretFunctionParams {
+            text_document: TextDocumentIdentifier { uri },
+            position,
+        };
+
+        let result = self
+            .request(
+                "rust-analyzer/interpretFunction",
+                serde_json::to_value(params)?,
+            )
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    }
+
+    /// Symbols of every kind declared in the workspace's own crates whose name matches
+    /// `query`; an empty query matches them all. A plain `workspace/symbol` request only
+    /// finds types.
+    pub async fn search_workspace_symbols(
+        &self,
+        query: &str,
+    ) -> Result<Option<WorkspaceSymbolResponse>> {
+        let params = WorkspaceSymbolSearchParams {
+            query: query.to_string(),
+            search_scope: Some(WorkspaceSymbolSearchScope::Workspace),
+            search_kind: Some(WorkspaceSymbolSearchKind::AllSymbols),
+        };
+
+        let result = self
+            .request("workspace/symbol", serde_json::to_value(params)?)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or(None))
+    }
+
+    /// The crate dependency graph in Graphviz DOT format.
+    pub async fn view_crate_graph(&self, full: bool) -> Result<String> {
+        let params = ViewCrateGraphParams { full };
+
+        let result = self
+            .request(
+                "rust-analyzer/viewCrateGraph",
+                serde_json::to_value(params)?,
+            )
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    }
+
+    /// Text report of the server's internal state: loaded workspaces, caches, pending work.
+    pub async fn analyzer_status(&self) -> Result<String> {
+        let params = AnalyzerStatusParams::default();
+
+        let result = self
+            .request(
+                "rust-analyzer/analyzerStatus",
+                serde_json::to_value(params)?,
+            )
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    }
+
+    /// Text report of the server's memory usage, broken down by query.
+    pub async fn memory_usage(&self) -> Result<String> {
+        let result = self
+            .request("rust-analyzer/memoryUsage", Value::Null)
+            .await?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    }
+
+    /// Runs `cargo check` (or the configured check command) over the whole workspace
+    /// and waits for it to finish, so the published diagnostics are fresh.
+    pub async fn run_flycheck(&self, timeout: Duration) -> Result<()> {
+        let finished = self
+            .register_notification::<(), _>("$/progress".to_string(), |value: Value| {
+                Box::pin(async move {
+                    let is_flycheck = value
+                        .get("token")
+                        .and_then(|t| t.as_str())
+                        .is_some_and(|t| t.starts_with("rust-analyzer/flycheck/"));
+                    let is_end =
+                        value.pointer("/value/kind").and_then(|k| k.as_str()) == Some("end");
+                    Ok((is_flycheck && is_end).then_some(()))
+                })
+            })
+            .await;
+
+        self.notify(
+            "rust-analyzer/runFlycheck",
+            Some(serde_json::json!({ "textDocument": null })),
+        )
+        .await?;
+
+        tokio::time::timeout(timeout, finished)
+            .await
+            .map_err(|_| anyhow!("Timed out after {:?} waiting for cargo check", timeout))???;
+
+        // The server publishes the new diagnostics right after reporting the end of the
+        // check; a round-trip guarantees we've received them.
+        self.analyzer_status().await?;
+        Ok(())
+    }
+
+    /// Rebuilds proc macros and re-runs build scripts, then waits up to `timeout` for the
+    /// server to finish reloading. Returns whether it did.
+    pub async fn rebuild_proc_macros(&self, timeout: Duration) -> Result<bool> {
+        self.request_and_wait_ready("rust-analyzer/rebuildProcMacros", timeout)
+            .await
+    }
+
+    /// Reloads the workspace, picking up changes to its manifests such as new
+    /// dependencies, then waits up to `timeout` for the server to finish loading it.
+    /// Returns whether it did.
+    pub async fn reload_workspace(&self, timeout: Duration) -> Result<bool> {
+        self.request_and_wait_ready("rust-analyzer/reloadWorkspace", timeout)
+            .await
+    }
+
+    /// Sends `method` and waits up to `timeout` for the work it starts to finish.
+    async fn request_and_wait_ready(&self, method: &str, timeout: Duration) -> Result<bool> {
+        let mut ready_rx = self.ready_receiver();
+        ready_rx.borrow_and_update();
+        self.request(method, Value::Null).await?;
+
+        // The server answers before it starts; it reports itself busy right after, unless
+        // there is nothing to do.
+        let _ = tokio::time::timeout(REBUILD_START_WAIT, ready_rx.changed()).await;
+        let finished = tokio::time::timeout(timeout, ready_rx.wait_for(|ready| *ready))
+            .await
+            .is_ok();
+        Ok(finished)
+    }
+
+    /// Sends a request taking `TextDocumentPositionParams` and returning a plain string.
+    async fn text_position_request(
+        &self,
+        method: &str,
+        uri: Uri,
+        position: Position,
+    ) -> Result<String> {
+        let params = TextDocumentPositionParams {
+            text_document: TextDocumentIdentifier { uri },
+            position,
+        };
+
+        let result = self.request(method, serde_json::to_value(params)?).await?;
+        Ok(serde_json::from_value(result).unwrap_or_default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn settings_are_merged_into_defaults() {
+        let configuration = configuration(serde_json::json!({ "cargo": { "features": "all" } }));
+
+        assert_eq!(configuration["cargo"]["features"], "all");
+        assert_eq!(configuration["cargo"]["buildScripts"]["enable"], true);
+    }
+
+    #[test]
+    fn check_command_replaces_both_setting_names() {
+        let mut configuration = configuration(serde_json::json!({}));
+
+        merge_settings(
+            &mut configuration,
+            check_command_settings("Clippy".parse().unwrap()),
+        );
+
+        assert_eq!(configuration["check"]["command"], "clippy");
+        assert_eq!(configuration["checkOnSave"]["command"], "clippy");
+        assert_eq!(configuration["checkOnSave"]["enable"], true);
+        assert!("fmt".parse::<CheckCommand>().is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use lsp_types::Position;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// Responses to position-based requests, per document, kept until the document changes.
+///
+/// Only the queried document's changes invalidate its entries, so a response can go stale
+/// when an edit elsewhere changes what it refers to; callers needing exact answers can
+/// turn the cache off with a capacity of 0.
+#[derive(Debug, Default)]
+pub struct ResponseCache {
+    /// The most responses kept across all documents; 0 disables the cache.
+    capacity: usize,
+    /// Shared by requests running concurrently; only held while reading or updating.
+    entries: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    responses: HashMap<String, HashMap<(String, Position), Value>>,
+    /// Cached keys, oldest first, to evict once over capacity.
+    order: VecDeque<(String, String, Position)>,
+    /// How many times each document was invalidated, to recognize responses computed
+    /// from an older text.
+    generations: HashMap<String, u64>,
+}
+
+impl ResponseCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            ..Default::default()
+        }
+    }
+
+    /// The cache as seen by a request about `uri` starting now.
+    pub fn for_document(self: &Arc<Self>, uri: &str) -> DocumentCache {
+        let generation = self.entries().generations.get(uri).copied().unwrap_or(0);
+        DocumentCache {
+            cache: self.clone(),
+            uri: uri.to_string(),
+            generation,
+        }
+    }
+
+    /// The cached response to `method` at `position` in `uri`, if any.
+    fn get<T: DeserializeOwned>(&self, uri: &str, method: &str, position: Position) -> Option<T> {
+        let value = self
+            .entries()
+            .responses
+            .get(uri)?
+            .get(&(method.to_string(), position))?
+            .clone();
+        serde_json::from_value(value).ok()
+    }
+
+    /// Caches `response` to `method` at `position` in `uri`, evicting the oldest
+    /// responses past capacity. Nothing is cached if `uri` is no longer at `generation`.
+    fn insert<T: Serialize>(
+        &self,
+        uri: &str,
+        generation: u64,
+        method: &str,
+        position: Position,
+        response: &T,
+    ) {
+        if self.capacity == 0 {
+            return;
+        }
+        let Ok(value) = serde_json::to_value(response) else {
+            return;
+        };
+        let mut entries = self.entries();
+        if entries.generations.get(uri).copied().unwrap_or(0) != generation {
+            return;
+        }
+        let key = (method.to_string(), position);
+        let previous = entries
+            .responses
+            .entry(uri.to_string())
+            .or_default()
+            .insert(key, value);
+        if previous.is_none() {
+            entries
+                .order
+                .push_back((uri.to_string(), method.to_string(), position));
+        }
+        while entries.order.len() > self.capacity {
+            let (uri, method, position) = entries.order.pop_front().unwrap();
+            if let Some(responses) = entries.responses.get_mut(&uri) {
+                responses.remove(&(method, position));
+                if responses.is_empty() {
+                    entries.responses.remove(&uri);
+                }
+            }
+        }
+    }
+
+    /// Drops every response for `uri`, e.g. because its text changed.
+    pub fn invalidate(&self, uri: &str) {
+        let mut entries = self.entries();
+        *entries.generations.entry(uri.to_string()).or_default() += 1;
+        if entries.responses.remove(uri).is_some() {
+            entries.order.retain(|(cached, _, _)| cached != uri);
+        }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// The responses cached for one document, as seen by a request that started when the
+/// document had a given text. What the request caches is dropped if the document has
+/// changed since, since it may describe the old text.
+pub struct DocumentCache {
+    cache: Arc<ResponseCache>,
+    uri: String,
+    generation: u64,
+}
+
+impl DocumentCache {
+    /// The cached response to `method` at `position`, if any.
+    pub fn get<T: DeserializeOwned>(&self, method: &str, position: Position) -> Option<T> {
+        self.cache.get(&self.uri, method, position)
+    }
+
+    /// Caches `response` to `method` at `position`, unless the document changed since this
+    /// view was taken.
+    pub fn insert<T: Serialize>(&self, method: &str, position: Position, response: &T) {
+        self.cache
+            .insert(&self.uri, self.generation, method, position, response);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOVER: &str = "textDocument/hover";
+
+    #[test]
+    fn invalidation_is_per_document() {
+        let cache = Arc::new(ResponseCache::new(10));
+        let position = Position::new(1, 2);
+        cache
+            .for_document("file:///a.rs")
+            .insert(HOVER, position, &"a");
+        cache
+            .for_document("file:///b.rs")
+            .insert(HOVER, position, &"b");
+
+        cache.invalidate("file:///a.rs");
+
+        let a = cache.for_document("file:///a.rs");
+        let b = cache.for_document("file:///b.rs");
+        assert_eq!(a.get::<String>(HOVER, position), None);
+        assert_eq!(b.get::<String>(HOVER, position), Some("b".to_string()));
+    }
+
+    #[test]
+    fn oldest_responses_are_evicted() {
+        let cache = Arc::new(ResponseCache::new(2)).for_document("file:///a.rs");
+        for line in 0..3 {
+            cache.insert(HOVER, Position::new(line, 0), &line);
+        }
+
+        assert_eq!(cache.get::<u32>(HOVER, Position::new(0, 0)), None);
+        assert_eq!(cache.get::<u32>(HOVER, Position::new(2, 0)), Some(2));
+    }
+
+    #[test]
+    fn responses_to_an_older_text_are_not_cached() {
+        let cache = Arc::new(ResponseCache::new(10));
+        let position = Position::new(1, 2);
+        let before_edit = cache.for_document("file:///a.rs");
+
+        cache.invalidate("file:///a.rs");
+        before_edit.insert(HOVER, position, &"old");
+        assert_eq!(before_edit.get::<String>(HOVER, position), None);
+
+        let after_edit = cache.for_document("file:///a.rs");
+        after_edit.insert(HOVER, position, &"new");
+        assert_eq!(
+            after_edit.get::<String>(HOVER, position),
+            Some("new".to_string())
+        );
+    }
+
+    #[test]
+    fn zero_capacity_disables_cache() {
+        let cache = Arc::new(ResponseCache::new(0)).for_document("file:///a.rs");
+        cache.insert(HOVER, Position::new(0, 0), &1);
+        assert_eq!(cache.get::<u32>(HOVER, Position::new(0, 0)), None);
+    }
+}
@@ -0,0 +1,267 @@
+//! Running the commands rust-analyzer returns for its runnables.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::process::Command;
+
+use crate::ra_extensions::Runnable;
+
+/// The process a runnable describes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RunnableCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    pub environment: HashMap<String, String>,
+}
+
+impl RunnableCommand {
+    /// The command as it would be typed in a shell, for display.
+    pub fn display(&self) -> String {
+        std::iter::once(self.program.as_str())
+            .chain(self.args.iter().map(String::as_str))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+}
+
+/// `args` of a `cargo` runnable.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CargoArgs {
+    #[serde(default)]
+    cargo_args: Vec<String>,
+    /// Sent by older rust-analyzer versions, before `executableArgs`' separator.
+    #[serde(default)]
+    cargo_extra_args: Vec<String>,
+    #[serde(default)]
+    executable_args: Vec<String>,
+    /// Newer versions send `cwd`; older ones only the workspace root.
+    cwd: Option<PathBuf>,
+    workspace_root: Option<PathBuf>,
+    /// A replacement for `cargo` configured in rust-analyzer, like a wrapper script.
+    override_cargo: Option<String>,
+    #[serde(default)]
+    environment: HashMap<String, String>,
+}
+
+/// `args` of a `shell` runnable.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ShellArgs {
+    program: String,
+    #[serde(default)]
+    args: Vec<String>,
+    cwd: Option<PathBuf>,
+    #[serde(default)]
+    environment: HashMap<String, String>,
+}
+
+/// The command to run `runnable`, built only from what rust-analyzer returned.
+pub fn runnable_command(runnable: &Runnable) -> Result<RunnableCommand> {
+    match runnable.kind.as_str() {
+        "cargo" => {
+            let args: CargoArgs = serde_json::from_value(runnable.args.clone())
+                .map_err(|e| anyhow!("Unexpected cargo runnable arguments: {}", e))?;
+            let mut command_args = args.cargo_args;
+            command_args.extend(args.cargo_extra_args);
+            if !args.executable_args.is_empty() {
+                command_args.push("--".to_string());
+                command_args.extend(args.executable_args);
+            }
+            Ok(RunnableCommand {
+                program: args.override_cargo.unwrap_or_else(|| "cargo".to_string()),
+                args: command_args,
+                cwd: args.cwd.or(args.workspace_root),
+                environment: args.environment,
+            })
+        }
+        "shell" => {
+            let args: ShellArgs = serde_json::from_value(runnable.args.clone())
+                .map_err(|e| anyhow!("Unexpected shell runnable arguments: {}", e))?;
+            Ok(RunnableCommand {
+                program: args.program,
+                args: args.args,
+                cwd: args.cwd,
+                environment: args.environment,
+            })
+        }
+        kind => Err(anyhow!("Don't know how to run a `{}` runnable", kind)),
+    }
+}
+
+/// What running a command produced.
+#[derive(Debug, Serialize, JsonSchema)]
+pub struct RunOutput {
+    /// The command that was run.
+    pub command: String,
+    /// The exit code, or `None` if the process was killed by a signal or timed out.
+    pub exit_code: Option<i32>,
+    pub timed_out: bool,
+    /// The end of the standard output, cut to the output limit.
+    pub stdout: String,
+    /// The end of the standard error, cut to the output limit.
+    pub stderr: String,
+    /// Whether earlier output was dropped to stay within the limit, or later output was
+    /// left unread at the timeout.
+    pub truncated: bool,
+}
+
+/// Runs `command`, keeping the last `max_output_bytes` of each output stream. The process
+/// is killed if it hasn't finished after `timeout`.
+///
+/// Processes it started, like the test binary `cargo test` runs, can outlive it and keep
+/// its output open, so output is only read until the same deadline; what comes later is
+/// dropped and the output marked `truncated`.
+pub async fn run_command(
+    command: &RunnableCommand,
+    max_output_bytes: usize,
+    timeout: Duration,
+) -> Result<RunOutput> {
+    let mut process = Command::new(&command.program);
+    process
+        .args(&command.args)
+        .envs(&command.environment)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    if let Some(cwd) = &command.cwd {
+        process.current_dir(cwd);
+    }
+    let mut child = process
+        .spawn()
+        .map_err(|e| anyhow!("Failed to start `{}`: {}", command.program, e))?;
+    let deadline = tokio::time::Instant::now() + timeout;
+    let stdout = tokio::spawn(read_tail(child.stdout.take(), max_output_bytes, deadline));
+    let stderr = tokio::spawn(read_tail(child.stderr.take(), max_output_bytes, deadline));
+
+    let (exit_code, timed_out) = match tokio::time::timeout_at(deadline, child.wait()).await {
+        Ok(status) => (status?.code(), false),
+        Err(_) => {
+            child.kill().await?;
+            (None, true)
+        }
+    };
+    let (stdout, stdout_truncated) = stdout.await?;
+    let (stderr, stderr_truncated) = stderr.await?;
+    Ok(RunOutput {
+        command: command.display(),
+        exit_code,
+        timed_out,
+        stdout,
+        stderr,
+        truncated: stdout_truncated || stderr_truncated,
+    })
+}
+
+/// Reads `stream` to the end or until `deadline`, keeping only its last `max_bytes`.
+/// Returns the text kept and whether anything was dropped, or left unread at the deadline.
+async fn read_tail(
+    stream: Option<impl AsyncRead + Unpin>,
+    max_bytes: usize,
+    deadline: tokio::time::Instant,
+) -> (String, bool) {
+    let Some(mut stream) = stream else {
+        return (String::new(), false);
+    };
+    let mut kept = Vec::new();
+    let mut truncated = false;
+    let mut chunk = [0; 8192];
+    loop {
+        let read = match tokio::time::timeout_at(deadline, stream.read(&mut chunk)).await {
+            Ok(Ok(read)) if read > 0 => read,
+            Ok(_) => break,
+            Err(_) => {
+                truncated = true;
+                break;
+            }
+        };
+        kept.extend_from_slice(&chunk[..read]);
+        if kept.len() > max_bytes {
+            kept.drain(..kept.len() - max_bytes);
+            truncated = true;
+        }
+    }
+    (String::from_utf8_lossy(&kept).into_owned(), truncated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runnable(kind: &str, args: serde_json::Value) -> Runnable {
+        Runnable {
+            label: "test tests::it_works".to_string(),
+            location: None,
+            kind: kind.to_string(),
+            args,
+        }
+    }
+
+    #[test]
+    fn cargo_runnable_separates_executable_args() {
+        let runnable = runnable(
+            "cargo",
+            serde_json::json!({
+                "cargoArgs": ["test", "--package", "demo", "--lib"],
+                "executableArgs": ["tests::it_works", "--exact"],
+                "workspaceRoot": "/ws",
+            }),
+        );
+
+        let command = runnable_command(&runnable).unwrap();
+        assert_eq!(
+            command.display(),
+            "cargo test --package demo --lib -- tests::it_works --exact"
+        );
+        assert_eq!(command.cwd, Some(PathBuf::from("/ws")));
+    }
+
+    #[test]
+    fn unknown_runnable_kinds_are_refused() {
+        let runnable = runnable("debug", serde_json::json!({}));
+
+        assert!(runnable_command(&runnable).is_err());
+    }
+
+    #[tokio::test]
+    async fn output_keeps_the_tail() {
+        let deadline = tokio::time::Instant::now() + Duration::from_secs(10);
+        let (text, truncated) =
+            read_tail(Some(&b"first line\nlast line\n"[..]), 10, deadline).await;
+
+        assert_eq!(text, "last line\n");
+        assert!(truncated);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn timeout_holds_when_a_grandchild_keeps_the_output_open() {
+        let command = RunnableCommand {
+            program: "sh".to_string(),
+            args: vec![
+                "-c".to_string(),
+                "echo started; sleep 30 & wait".to_string(),
+            ],
+            cwd: None,
+            environment: HashMap::new(),
+        };
+
+        let started = std::time::Instant::now();
+        let output = run_command(&command, 1024, Duration::from_millis(500))
+            .await
+            .unwrap();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(output.timed_out);
+        assert!(output.truncated);
+        assert_eq!(output.stdout, "started\n");
+    }
+}
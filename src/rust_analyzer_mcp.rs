@@ -1,35 +1,523 @@
 use anyhow::anyhow;
-use lsp_types::{Position, TextDocumentIdentifier, TextDocumentPositionParams, Uri};
+use futures::StreamExt;
+use lsp_types::{
+    CodeActionContext, CodeActionKind, CodeActionOrCommand, CodeLens, CompletionContext,
+    CompletionResponse, CompletionTriggerKind, Diagnostic, DocumentChangeOperation,
+    DocumentChanges, DocumentDiagnosticReport, DocumentHighlightKind, DocumentLink,
+    DocumentSymbolResponse, FoldingRange, GotoDefinitionResponse, Hover, Location, NumberOrString,
+    OneOf, Position, Range, SelectionRange, ServerCapabilities, ServerInfo, SymbolKind,
+    TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentPositionParams, TextEdit,
+    TypeHierarchyItem, Uri, WorkspaceEdit, WorkspaceSymbolResponse,
+};
 use sacp::mcp_server::McpServer;
 use sacp::{Role, RunWithConnectionTo};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::sync::Mutex;
 
+use crate::completion::filter_completions;
+use crate::diagnostics::{
+    DiagnosticDetails, FileDiagnostics, QuickFix, WorkspaceDiagnosticsSummary, explain_error_code,
+    parse_error_code, parse_severity, plan_fixes, source_context, summarize_workspace_diagnostics,
+};
+use crate::document_source::{DocumentSource, FileSystemSource};
 use crate::failed_obligations::{
-    FailedObligationsState, handle_failed_obligations, handle_failed_obligations_goal,
+    FailedObligationsState, GoalTree, handle_failed_obligations, handle_failed_obligations_goal,
+};
+use crate::file_glob::{find_files, walk_files};
+use crate::hover::{HoverSummary, declared_name, qualified_path, summarize_hover, value_type};
+use crate::lsp_client::{
+    CAPABILITY_METHODS, DecodedSemanticToken, LspClient, LspError, Progress, ServerMessage,
+    SpawnFailed,
+};
+use crate::lsp_schema::{LocationSchema, PositionSchema, RangeSchema, TextEditSchema};
+use crate::pagination::{PageInfo, PageInputs};
+use crate::ra_extensions::{
+    CheckCommand, MoveItemDirection, Runnable, TestInfo, connect_rust_analyzer, start_rust_analyzer,
 };
-use crate::lsp_client::LspClient;
+use crate::response_cache::{DocumentCache, ResponseCache};
+use crate::runner::{RunOutput, run_command, runnable_command};
+use crate::snippets::PlainTextEdit;
+use crate::symbols::{
+    ApiSymbol, EnclosingItem, FlatSymbol, SymbolMatch, WorkspaceSymbol, api_symbols, doc_link_at,
+    enclosing_items, file_metrics, find_identifier_occurrences, find_symbols,
+    flatten_document_symbols, parse_symbol_kind, source_lines, workspace_symbols_of_kind,
+};
+use crate::syntax_tree::render_syntax_tree;
+use crate::text_edits::{PositionEncoding, check_position, try_apply_text_edits};
+use crate::tool_error::ToolError;
+use crate::tool_fn::DirectToolFn;
 
 pub type Result<T> = std::result::Result<T, sacp::Error>;
 
-pub struct BridgeState {
-    client: Option<LspClient>,
-    opened_documents: HashSet<String>,
+/// State for a single rust-analyzer instance serving one workspace root.
+pub struct WorkspaceBridge {
+    /// Shared with tool calls in flight, which run their requests without holding the
+    /// bridge lock.
+    client: Arc<LspClient>,
+    /// URIs of the documents open in the server, least recently used first.
+    opened_documents: VecDeque<String>,
     document_versions: HashMap<String, i32>,
+    /// The text last sent to the server for each open document.
+    document_texts: HashMap<String, String>,
+    /// Past this many open documents, the least recently used are closed.
+    max_open_documents: usize,
+    /// Hover and definition responses for documents that haven't changed since.
+    response_cache: Arc<ResponseCache>,
+    /// Where documents opened without explicit content are read from.
+    document_source: Arc<dyn DocumentSource>,
+}
+
+impl WorkspaceBridge {
+    /// Moves an open document to the most recently used end. Returns false if it isn't open.
+    fn mark_used(&mut self, uri: &str) -> bool {
+        let Some(index) = self.opened_documents.iter().position(|open| open == uri) else {
+            return false;
+        };
+        let uri = self.opened_documents.remove(index).unwrap();
+        self.opened_documents.push_back(uri);
+        true
+    }
+
+    /// Closes the least recently used documents until at most `max_open_documents` are open.
+    async fn close_excess_documents(&mut self) -> Result<()> {
+        while self.opened_documents.len() > self.max_open_documents {
+            let uri = self.opened_documents.pop_front().unwrap();
+            self.send_did_close(&uri).await?;
+        }
+        Ok(())
+    }
+
+    /// Closes `uri` in the server. Returns false if it wasn't open.
+    async fn close_document(&mut self, uri: &str) -> Result<bool> {
+        let Some(index) = self.opened_documents.iter().position(|open| open == uri) else {
+            return Ok(false);
+        };
+        self.opened_documents.remove(index);
+        self.send_did_close(uri).await?;
+        Ok(true)
+    }
+
+    /// Applies `edits` to the open document's text and sends the result to the server as
+    /// a single new version. Nothing is written to disk.
+    async fn apply_edits(&mut self, uri: &Uri, edits: &[TextEdit]) -> Result<()> {
+        let uri_str = uri.to_string();
+        let text = self
+            .document_texts
+            .get(&uri_str)
+            .ok_or_else(|| anyhow!("Document is not open: {}", uri_str))?;
+        let text = try_apply_text_edits(text, edits, self.client.position_encoding())?;
+        self.mark_used(&uri_str);
+        self.change_document(uri, text).await
+    }
+
+    /// Replaces the whole text of the open document `uri` with a `didChange` and a new
+    /// version.
+    async fn change_document(&mut self, uri: &Uri, text: String) -> Result<()> {
+        let uri_str = uri.to_string();
+        let version = self.document_versions.get(&uri_str).copied().unwrap_or(1) + 1;
+        let change = TextDocumentContentChangeEvent {
+            range: None,
+            range_length: None,
+            text: text.clone(),
+        };
+        self.client
+            .did_change(uri.clone(), version, vec![change])
+            .await
+            .map_err(|e| ToolError::request_failed("Failed to update document", e))?;
+        self.response_cache.invalidate(&uri_str);
+        self.document_versions.insert(uri_str.clone(), version);
+        self.document_texts.insert(uri_str, text);
+        Ok(())
+    }
+
+    async fn send_did_close(&mut self, uri: &str) -> Result<()> {
+        self.document_texts.remove(uri);
+        let uri = Uri::from_str(uri).map_err(|e| anyhow!("Invalid URI: {}", e))?;
+        self.client
+            .did_close(uri)
+            .await
+            .map_err(|e| ToolError::request_failed("Failed to close document", e))?;
+        Ok(())
+    }
+}
+
+/// All running rust-analyzer instances, keyed by canonical workspace root.
+///
+/// Servers are started lazily the first time a workspace is used and are kept
+/// alive for the lifetime of the bridge.
+#[derive(Default)]
+pub struct BridgeState {
+    default_workspace: Option<PathBuf>,
+    workspaces: HashMap<PathBuf, WorkspaceBridge>,
+    /// How long a tool call waits for a loading server; see [`DEFAULT_READY_TIMEOUT`].
+    ready_timeout: Option<Duration>,
+    /// How many documents each server keeps open; see [`DEFAULT_MAX_OPEN_DOCUMENTS`].
+    max_open_documents: Option<usize>,
+    /// How many responses each server's cache keeps; see [`DEFAULT_RESPONSE_CACHE_SIZE`].
+    response_cache_size: Option<usize>,
+    /// Cargo settings sent to each server when it starts.
+    cargo_settings: CargoSettings,
+    /// Address of a running rust-analyzer to connect to instead of starting one.
+    tcp_address: Option<String>,
+    /// Environment variables set for each server started, on top of our own.
+    env: HashMap<String, String>,
+    /// Where documents are read from; the filesystem if unset.
+    document_source: Option<Arc<dyn DocumentSource>>,
+    /// Folders besides its root that a workspace's server analyzes, by workspace root.
+    workspace_folders: HashMap<PathBuf, Vec<PathBuf>>,
+    /// Recent restarts of each workspace's server after it exited.
+    restarts: HashMap<PathBuf, Restarts>,
+}
+
+/// Restarts of one server after it exited, to back off when it keeps crashing.
+#[derive(Default)]
+struct Restarts {
+    /// Restarts since the server last stayed up for [`RESTART_RESET_AFTER`].
+    count: u32,
+    last: Option<tokio::time::Instant>,
+    /// When the next restart may start, set once the server is found to have exited.
+    next_attempt: Option<tokio::time::Instant>,
+}
+
+/// How rust-analyzer should configure cargo when loading a workspace.
+///
+/// These are sent when a server starts, so changing them only affects workspaces
+/// loaded afterwards.
+#[derive(Debug, Clone, Default)]
+pub struct CargoSettings {
+    /// Features to enable, in addition to the default ones.
+    pub features: Vec<String>,
+    /// Enable every feature; takes precedence over `features`.
+    pub all_features: bool,
+    /// Target triple to analyze for, instead of the host's.
+    pub target: Option<String>,
+    /// Directory for the builds rust-analyzer runs (checks, build scripts, proc macros),
+    /// so they don't lock or overwrite the user's own `target` directory.
+    pub target_dir: Option<String>,
+}
+
+impl CargoSettings {
+    /// The settings as rust-analyzer configuration, to merge into the defaults.
+    fn to_configuration(&self) -> serde_json::Value {
+        let mut cargo = serde_json::Map::new();
+        if self.all_features {
+            cargo.insert("features".to_string(), "all".into());
+        } else if !self.features.is_empty() {
+            cargo.insert("features".to_string(), self.features.clone().into());
+        }
+        if let Some(target) = &self.target {
+            cargo.insert("target".to_string(), target.clone().into());
+        }
+        if let Some(target_dir) = &self.target_dir {
+            cargo.insert("targetDir".to_string(), target_dir.clone().into());
+            // Servers predating `targetDir` still honor the variable.
+            cargo.insert(
+                "extraEnv".to_string(),
+                serde_json::json!({ "CARGO_TARGET_DIR": target_dir }),
+            );
+        }
+        serde_json::json!({ "cargo": cargo })
+    }
 }
 
 impl BridgeState {
     pub fn new() -> Self {
-        Self {
-            client: None,
-            opened_documents: HashSet::new(),
+        Self::default()
+    }
+
+    /// Sets how long tool calls wait for rust-analyzer to finish loading before
+    /// answering with a `loading` status instead.
+    pub fn set_ready_timeout(&mut self, timeout: Duration) {
+        self.ready_timeout = Some(timeout);
+    }
+
+    fn ready_timeout(&self) -> Duration {
+        self.ready_timeout.unwrap_or(DEFAULT_READY_TIMEOUT)
+    }
+
+    /// Sets how many documents each server keeps open before closing the least
+    /// recently used. Applies to servers started afterwards.
+    pub fn set_max_open_documents(&mut self, max: usize) {
+        self.max_open_documents = Some(max.max(1));
+    }
+
+    /// Sets how many hover and definition responses each server caches, with 0 turning
+    /// the cache off. Applies to servers started afterwards.
+    pub fn set_response_cache_size(&mut self, size: usize) {
+        self.response_cache_size = Some(size);
+    }
+
+    /// Sets the cargo features and target servers started afterwards analyze with.
+    pub fn set_cargo_settings(&mut self, cargo_settings: CargoSettings) {
+        self.cargo_settings = cargo_settings;
+    }
+
+    /// Connects to a rust-analyzer listening at `address` for workspaces used afterwards,
+    /// instead of starting a server process for each.
+    pub fn set_tcp_address(&mut self, address: String) {
+        self.tcp_address = Some(address);
+    }
+
+    /// Sets environment variables, such as `CARGO` or `CARGO_NET_OFFLINE`, for servers
+    /// started afterwards. They are added to the environment the servers inherit.
+    pub fn set_env(&mut self, env: HashMap<String, String>) {
+        self.env = env;
+    }
+
+    /// Reads documents that tools open without explicit content from `source` instead of
+    /// the filesystem. Servers started earlier keep opening documents from the old source.
+    pub fn set_document_source(&mut self, source: Arc<dyn DocumentSource>) {
+        self.document_source = Some(source);
+    }
+
+    /// Where documents are read from: the source set with [`Self::set_document_source`],
+    /// else the filesystem.
+    fn document_source(&self) -> Arc<dyn DocumentSource> {
+        self.document_source
+            .clone()
+            .unwrap_or_else(|| Arc::new(FileSystemSource))
+    }
+
+    /// Makes the server for `workspace` also analyze `folders`, from when it next starts.
+    pub fn set_workspace_folders(&mut self, workspace: &Path, folders: Vec<PathBuf>) {
+        let workspace = canonical_workspace(workspace);
+        let folders = folders
+            .iter()
+            .map(|folder| canonical_workspace(folder))
+            .filter(|folder| *folder != workspace)
+            .collect();
+        self.workspace_folders.insert(workspace, folders);
+    }
+
+    /// The folders `workspace`'s server analyzes besides its root.
+    fn extra_folders(&self, workspace: &Path) -> &[PathBuf] {
+        self.workspace_folders
+            .get(workspace)
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// Picks the workspace a request should be routed to.
+    ///
+    /// An explicit `workspace_path` always wins. Otherwise, if `file_path` lies
+    /// inside an already running workspace, or one of its other folders, the innermost
+    /// such folder's workspace is used. Failing that we fall back to the default
+    /// workspace, then the current directory.
+    fn resolve_workspace(&self, workspace_path: Option<&str>, file_path: Option<&Path>) -> PathBuf {
+        if let Some(workspace_path) = workspace_path {
+            return canonical_workspace(Path::new(workspace_path));
+        }
+
+        if let Some(file_path) = file_path
+            && let Some((_, root)) = self
+                .workspaces
+                .keys()
+                .flat_map(|root| {
+                    std::iter::once(root)
+                        .chain(self.extra_folders(root))
+                        .map(move |folder| (folder, root))
+                })
+                .filter(|(folder, _)| file_path.starts_with(folder))
+                .max_by_key(|(folder, _)| folder.components().count())
+        {
+            return root.clone();
+        }
+
+        self.default_workspace.clone().unwrap_or_else(|| {
+            canonical_workspace(&std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
+        })
+    }
+
+    /// A bridge with nothing open yet for the freshly started `client`.
+    fn new_workspace_bridge(&self, client: LspClient) -> WorkspaceBridge {
+        WorkspaceBridge {
+            client: Arc::new(client),
+            opened_documents: VecDeque::new(),
             document_versions: HashMap::new(),
+            document_texts: HashMap::new(),
+            max_open_documents: self
+                .max_open_documents
+                .unwrap_or(DEFAULT_MAX_OPEN_DOCUMENTS),
+            response_cache: Arc::new(ResponseCache::new(
+                self.response_cache_size
+                    .unwrap_or(DEFAULT_RESPONSE_CACHE_SIZE),
+            )),
+            document_source: self.document_source(),
+        }
+    }
+
+    /// If the server for `workspace` has exited, when it may be restarted: after a delay
+    /// doubling with each restart in a row, so a server that keeps crashing isn't
+    /// respawned in a tight loop. Fails once it has been restarted too often.
+    fn restart_at(&mut self, workspace: &Path) -> Result<Option<tokio::time::Instant>> {
+        if self
+            .workspaces
+            .get(workspace)
+            .is_none_or(|workspace_bridge| workspace_bridge.client.is_alive())
+        {
+            return Ok(None);
+        }
+        let restarts = self.restarts.entry(workspace.to_path_buf()).or_default();
+        if restarts
+            .last
+            .is_some_and(|last| last.elapsed() >= RESTART_RESET_AFTER)
+        {
+            restarts.count = 0;
+        }
+        if restarts.count >= MAX_RESTARTS {
+            return Err(ToolError::ServerNotReady(format!(
+                "rust-analyzer for {} exited after {} restarts in a row; not restarting it again \
+                 for {} seconds. See rust_analyzer_server_log for why",
+                workspace.display(),
+                restarts.count,
+                RESTART_RESET_AFTER.as_secs()
+            ))
+            .into());
+        }
+        let delay = RESTART_BACKOFF * 2u32.pow(restarts.count);
+        Ok(Some(*restarts.next_attempt.get_or_insert_with(|| {
+            tracing::warn!(?workspace, ?delay, "rust-analyzer exited, restarting it");
+            tokio::time::Instant::now() + delay
+        })))
+    }
+
+    /// Makes a tool's `file_path` absolute. Relative paths are resolved against
+    /// `workspace_path` if given, else the default workspace.
+    fn resolve_file_path(
+        &self,
+        workspace_path: Option<&str>,
+        file_path: &str,
+    ) -> anyhow::Result<PathBuf> {
+        let path = Path::new(file_path);
+        if path.is_absolute() {
+            return Ok(path.to_path_buf());
+        }
+        let root = match workspace_path {
+            Some(workspace_path) => canonical_workspace(Path::new(workspace_path)),
+            None => self.default_workspace.clone().ok_or_else(|| {
+                anyhow!(
+                    "Relative file path `{}` needs a workspace: pass `workspace_path` or set one \
+                     with rust_analyzer_set_workspace",
+                    file_path
+                )
+            })?,
+        };
+        Ok(root.join(path))
+    }
+
+    /// The canonical path of a tool's `file_path` and the workspace it's routed to. A path
+    /// that isn't on disk is kept as is if the document source serves it.
+    fn resolve_document(
+        &self,
+        workspace_path: Option<&str>,
+        file_path: &str,
+    ) -> anyhow::Result<(PathBuf, PathBuf)> {
+        let file_path = self.resolve_file_path(workspace_path, file_path)?;
+        let file_path = match std::fs::canonicalize(&file_path) {
+            Ok(canonical) => canonical,
+            Err(_) if self.document_source().read(&file_path).is_ok() => file_path,
+            Err(e) => {
+                return Err(ToolError::FileNotFound(format!("Invalid file path: {}", e)).into());
+            }
+        };
+        let workspace = self.resolve_workspace(workspace_path, Some(&file_path));
+        Ok((file_path, workspace))
+    }
+}
+
+/// Whether a workspace's rust-analyzer is usable yet, as reported to tools.
+#[derive(Debug, Clone, Serialize, JsonSchema)]
+pub struct ServerStatus {
+    /// `ready`, `loading`, or `not_started` if no server runs for the workspace yet.
+    pub status: &'static str,
+    pub workspace: PathBuf,
+    pub message: Option<String>,
+}
+
+impl ServerStatus {
+    fn loading(workspace: &Path, waited: Duration) -> Self {
+        Self {
+            status: "loading",
+            workspace: workspace.to_path_buf(),
+            message: Some(format!(
+                "rust-analyzer is still loading the workspace after {}s; retry later or poll \
+                 rust_analyzer_status",
+                waited.as_secs()
+            )),
+        }
+    }
+}
+
+impl From<ServerStatus> for String {
+    fn from(status: ServerStatus) -> Self {
+        serde_json::to_string(&status).unwrap_or_else(|_| status.status.to_string())
+    }
+}
+
+/// What a tool returns: the fields of its result, or a status saying why there is none.
+#[derive(Debug, Serialize, JsonSchema)]
+struct ToolOutput<T> {
+    /// Why there is no result: `loading` while rust-analyzer loads the workspace, or
+    /// `pending`/`no_results` when a navigation request comes back empty.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<String>,
+    /// The workspace that is still loading.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    workspace: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+    /// The LSP feature the tool needs, when the running server doesn't support it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unsupported: Option<String>,
+    // Last, so that where a result has a field of the same name, its schema wins.
+    #[serde(flatten)]
+    result: Option<T>,
+}
+
+impl<T> ToolOutput<T> {
+    fn ready(result: T) -> Self {
+        Self {
+            result: Some(result),
+            ..Self::empty()
+        }
+    }
+
+    fn empty() -> Self {
+        Self {
+            result: None,
+            status: None,
+            workspace: None,
+            message: None,
+            unsupported: None,
+        }
+    }
+
+    /// This output's status, for a tool returning another type, when it has no result.
+    fn without_result<U>(self) -> ToolOutput<U> {
+        ToolOutput {
+            status: self.status,
+            workspace: self.workspace,
+            message: self.message,
+            unsupported: self.unsupported,
+            result: None,
+        }
+    }
+}
+
+impl<T> From<ServerStatus> for ToolOutput<T> {
+    fn from(status: ServerStatus) -> Self {
+        Self {
+            status: Some(status.status.to_string()),
+            workspace: Some(status.workspace),
+            message: status.message,
+            ..Self::empty()
         }
     }
 }
@@ -39,477 +527,5708 @@ pub type BridgeType = Arc<Mutex<BridgeState>>;
 #[derive(Serialize, Deserialize, JsonSchema, Debug)]
 pub struct FilePositionInputs {
     pub file_path: String,
+    /// Line number, 0-based like LSP unless `one_based` is set.
     pub line: u32,
+    /// Column in the units the server negotiated (bytes for rust-analyzer), 0-based like LSP
+    /// unless `one_based` is set.
     pub character: u32,
+    /// If true, `line` and `character` are 1-based, as shown by editors and compiler messages.
+    #[serde(default)]
+    pub one_based: bool,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
+}
+
+impl FilePositionInputs {
+    /// The LSP position these inputs refer to.
+    pub fn position(&self) -> Position {
+        lsp_position(self.line, self.character, self.one_based)
+    }
+
+    /// Like [`Self::position`], but checked against the file's text; see [`check_positions`].
+    async fn checked_position(&self, bridge: &BridgeType) -> Result<Position> {
+        let (file_path, encoding, source) =
+            resolve_file_encoding(bridge, self.workspace_path.as_deref(), &self.file_path).await?;
+        let position = self.position();
+        check_positions(
+            &*source,
+            &file_path,
+            self.content.as_deref(),
+            &[position],
+            self.one_based,
+            encoding,
+        )?;
+        Ok(position)
+    }
+}
+
+/// Checks `positions` against the text of `file_path` as the caller sees it, so one past the
+/// end gets a clear error instead of an empty answer. A file that can't be read is left
+/// for the server to report.
+fn check_positions(
+    source: &dyn DocumentSource,
+    file_path: &Path,
+    content: Option<&str>,
+    positions: &[Position],
+    one_based: bool,
+    encoding: PositionEncoding,
+) -> anyhow::Result<()> {
+    let Ok(text) = document_text(source, file_path, content) else {
+        return Ok(());
+    };
+    for &position in positions {
+        check_position(&text, position, one_based, encoding)
+            .map_err(|e| ToolError::InvalidPosition(e.to_string()))?;
+    }
+    Ok(())
+}
+
+/// The text of a file as the caller sees it: the unsaved `content` if given, else what
+/// `source` has for it (the file on disk, by default).
+fn document_text(
+    source: &dyn DocumentSource,
+    file_path: &Path,
+    content: Option<&str>,
+) -> anyhow::Result<String> {
+    match content {
+        Some(content) => Ok(content.to_string()),
+        None => source.read(file_path).map_err(|e| {
+            ToolError::FileNotFound(format!("Failed to read {}: {}", file_path.display(), e)).into()
+        }),
+    }
+}
+
+fn lsp_position(line: u32, character: u32, one_based: bool) -> Position {
+    if one_based {
+        Position::new(line.saturating_sub(1), character.saturating_sub(1))
+    } else {
+        Position::new(line, character)
+    }
 }
 
 #[derive(Serialize, Deserialize, JsonSchema)]
-struct FileOnlyInputs {
+struct NavigationInputs {
+    pub file_path: String,
+    /// Line number, 0-based like LSP unless `one_based` is set. Required unless
+    /// `symbol_name` is given.
+    pub line: Option<u32>,
+    /// Column in the units the server negotiated (bytes for rust-analyzer), 0-based like LSP
+    /// unless `one_based` is set.
+    pub character: Option<u32>,
+    /// If true, `line` and `character` are 1-based, as shown by editors and compiler messages.
+    #[serde(default)]
+    pub one_based: bool,
+    /// Instead of a position, the identifier to look up; the request runs at its `occurrence`-th
+    /// appearance in the file.
+    pub symbol_name: Option<String>,
+    /// Which appearance of `symbol_name` to use, counting from 0 in file order.
+    #[serde(default)]
+    pub occurrence: usize,
+    /// If true, each returned location also includes the text of the line(s) it
+    /// points at and a 1-based position. This reads every target file.
+    #[serde(default)]
+    pub include_preview: bool,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
+}
+
+impl NavigationInputs {
+    /// The LSP position to run the request at, resolving `symbol_name` against `content` if
+    /// given, else the file's text in `source`. Columns count `encoding` units.
+    fn position(
+        &self,
+        source: &dyn DocumentSource,
+        file_path: &Path,
+        encoding: PositionEncoding,
+    ) -> anyhow::Result<Position> {
+        if let (Some(line), Some(character)) = (self.line, self.character) {
+            let position = lsp_position(line, character, self.one_based);
+            check_positions(
+                source,
+                file_path,
+                self.content.as_deref(),
+                &[position],
+                self.one_based,
+                encoding,
+            )?;
+            return Ok(position);
+        }
+        let Some(symbol_name) = &self.symbol_name else {
+            return Err(anyhow!(
+                "Either `line` and `character` or `symbol_name` is required"
+            ));
+        };
+
+        let text = document_text(source, file_path, self.content.as_deref())?;
+        let occurrences = find_identifier_occurrences(&text, symbol_name, encoding);
+        occurrences.get(self.occurrence).copied().ok_or_else(|| {
+            let available = occurrences
+                .iter()
+                .enumerate()
+                .map(|(i, p)| format!("{}: line {}, character {}", i, p.line, p.character))
+                .collect::<Vec<_>>();
+            anyhow!(
+                "Occurrence {} of `{}` not found; {} available (0-based positions): [{}]",
+                self.occurrence,
+                symbol_name,
+                occurrences.len(),
+                available.join("; ")
+            )
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct FileOptionalPositionInputs {
     pub file_path: String,
+    /// If given together with `character`, restricts the result to that position.
+    pub line: Option<u32>,
+    /// Column in the units the server negotiated (bytes for rust-analyzer), 0-based.
+    pub character: Option<u32>,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
 }
 
-/*
 #[derive(Serialize, Deserialize, JsonSchema)]
-struct RangeInputs {
+struct RunTestInputs {
     pub file_path: String,
-    pub line: u32,
-    pub character: u32,
-    pub end_line: u32,
-    pub end_character: u32,
+    /// The `label` of a runnable listed by `rust_analyzer_runnables`, like
+    /// `test tests::it_works`.
+    pub label: Option<String>,
+    /// Alternatively, a position inside the test or binary to run. 0-based unless
+    /// `one_based` is set.
+    pub line: Option<u32>,
+    /// Column in the units the server negotiated (bytes for rust-analyzer).
+    pub character: Option<u32>,
+    #[serde(default)]
+    pub one_based: bool,
+    /// Kill the command if it hasn't finished after this many seconds. Defaults to 300.
+    pub timeout_secs: Option<u64>,
+    /// Keep at most this many bytes of the end of stdout and of stderr. Defaults to 64 KiB.
+    pub max_output_bytes: Option<usize>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
 }
-*/
 
 #[derive(Serialize, Deserialize, JsonSchema)]
-struct WorkspaceInputs {
-    pub workspace_path: String,
+struct SymbolsInputs {
+    pub file_path: String,
+    /// If true, return a flat list of `{ name, kind, container_path, range }`
+    /// instead of the nested symbol tree.
+    #[serde(default)]
+    pub flatten: bool,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema)]
-pub struct GoalIndexInputs {
-    pub goal_index: Value,
+struct FindSymbolInputs {
+    pub file_path: String,
+    /// Symbol name, e.g. `greet`, or a container path such as `Person::greet`.
+    pub name: String,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, JsonSchema)]
-pub struct LspCallInputs {
-    pub method: String,
-    /// Optional params to pass to the LSP method. If omitted, `null` is sent.
-    pub params: Option<Value>,
-    /// If provided, sets the workspace root before making the call.
+struct PublicApiInputs {
+    pub file_path: String,
+    /// Also list private items, marked with `public: false`.
+    #[serde(default)]
+    pub include_private: bool,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
     pub workspace_path: Option<String>,
-    /// If true, send as a notification (no response expected). Defaults to false.
-    pub is_notification: Option<bool>,
 }
 
-pub const SERVER_ID: &str = "rust-analyzer";
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ReferencesInputs {
+    #[serde(flatten)]
+    pub navigation: NavigationInputs,
+    /// If true, references are grouped by file with per-file counts and a total, and each
+    /// is tagged as a read or a write where the server says. `include_preview` is ignored.
+    #[serde(default)]
+    pub group_by_file: bool,
+    /// Which references to return, ordered by file and position. Defaults to the first 500.
+    #[serde(flatten)]
+    pub page: PageInputs,
+}
 
-pub(crate) async fn ensure_bridge(bridge: &BridgeType, workspace_path: Option<&str>) -> Result<()> {
-    let mut bridge_guard = bridge.lock().await;
-    if bridge_guard.client.is_none() || workspace_path.is_some() {
-        let workspace = workspace_path
-            .map(PathBuf::from)
-            .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct DefinitionSourceInputs {
+    /// `include_preview` is ignored; the source is always returned.
+    #[serde(flatten)]
+    pub navigation: NavigationInputs,
+    /// Return at most this many lines of the definition. Defaults to 200.
+    pub max_lines: Option<usize>,
+}
 
-        tracing::debug!(?workspace);
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ListSymbolsByKindInputs {
+    /// The kind of symbol to list: "struct", "enum", "variant", "trait", "function",
+    /// "method", "field", "const", "type" or "module".
+    pub kind: String,
+    /// Only list symbols whose name fuzzily matches this. Defaults to every symbol.
+    pub query: Option<String>,
+    /// Which symbols to return. Defaults to the first 100.
+    #[serde(flatten)]
+    pub page: PageInputs,
+    /// Workspace root to search. Defaults to the current workspace.
+    pub workspace_path: Option<String>,
+}
 
-        let root_uri = Uri::from_str(&format!("file://{}", workspace.display()))
-            .map_err(|e| anyhow!("Invalid workspace path: {}", e))?;
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct TraitImplsInputs {
+    /// A file declaring or using the trait; the rest of the workspace is searched if it
+    /// doesn't declare it.
+    pub file_path: String,
+    /// The trait's name, e.g. `Display`.
+    pub name: String,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
+}
 
-        tracing::debug!(?root_uri);
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct FileOnlyInputs {
+    pub file_path: String,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
+}
 
-        let client = LspClient::new("rust-analyzer", &[], root_uri)
-            .await
-            .map_err(|e| anyhow!("Failed to start rust-analyzer: {}", e))?;
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct RangeInputs {
+    pub file_path: String,
+    /// Start line, 0-based like LSP unless `one_based` is set.
+    pub line: u32,
+    /// Start column in the units the server negotiated (bytes for rust-analyzer).
+    pub character: u32,
+    pub end_line: u32,
+    /// End column, in the same units as `character`.
+    pub end_character: u32,
+    /// If true, all lines and characters are 1-based, as shown by editors and compiler messages.
+    #[serde(default)]
+    pub one_based: bool,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
+}
 
-        wait_for_start(&client).await;
+impl RangeInputs {
+    /// The LSP range these inputs refer to.
+    fn range(&self) -> Range {
+        Range::new(
+            lsp_position(self.line, self.character, self.one_based),
+            lsp_position(self.end_line, self.end_character, self.one_based),
+        )
+    }
 
-        bridge_guard.client = Some(client);
-        bridge_guard.opened_documents.clear();
-        bridge_guard.document_versions.clear();
+    /// Like [`Self::range`], but checked against the file's text; see [`check_positions`].
+    async fn checked_range(&self, bridge: &BridgeType) -> Result<Range> {
+        let (file_path, encoding, source) =
+            resolve_file_encoding(bridge, self.workspace_path.as_deref(), &self.file_path).await?;
+        let range = self.range();
+        check_positions(
+            &*source,
+            &file_path,
+            self.content.as_deref(),
+            &[range.start, range.end],
+            self.one_based,
+            encoding,
+        )?;
+        Ok(range)
     }
-    Ok(())
 }
 
-pub(crate) async fn with_bridge<F, R>(
-    bridge: &BridgeType,
-    workspace_path: Option<&str>,
-    f: F,
-) -> Result<R>
-where
-    F: for<'a> AsyncFnOnce(&'a LspClient) -> Result<R>,
-{
-    ensure_bridge(bridge, workspace_path).await?;
-    let bridge_guard = bridge.lock().await;
-    f(bridge_guard.client.as_ref().unwrap()).await
+/// Like [`RangeInputs`], but the range may be left out to mean the whole file.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct OptionalRangeInputs {
+    pub file_path: String,
+    /// Start line, 0-based like LSP unless `one_based` is set. Leave out all four
+    /// range fields for the whole file.
+    pub line: Option<u32>,
+    /// Start column in the units the server negotiated (bytes for rust-analyzer).
+    pub character: Option<u32>,
+    pub end_line: Option<u32>,
+    /// End column, in the same units as `character`.
+    pub end_character: Option<u32>,
+    /// If true, all lines and characters are 1-based, as shown by editors and compiler messages.
+    #[serde(default)]
+    pub one_based: bool,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
 }
 
-pub async fn with_bridge_and_document<F, R>(
-    bridge: &BridgeType,
-    workspace_path: Option<&str>,
-    file_path: &str,
-    f: F,
-) -> Result<R>
-where
-    F: for<'a> AsyncFnOnce(&'a LspClient, Uri) -> Result<R>,
-{
-    ensure_bridge(bridge, workspace_path).await?;
-    let mut bridge_guard = bridge.lock().await;
-    let uri = ensure_document_open(&mut bridge_guard, file_path).await?;
-    f(bridge_guard.client.as_ref().unwrap(), uri).await
-}
-
-async fn wait_for_start(lsp: &LspClient) {
-    let _ = lsp
-        .subscribe_notification::<(), _>(
-            "experimental/serverStatus".to_string(),
-            |value: serde_json::Value| {
-                Box::pin(async move {
-                    let Some(q) = value.get("quiescent").and_then(|q| q.as_bool()) else {
-                        return Err(anyhow::anyhow!("quiescent not found or invalid"));
-                    };
-                    if q { Ok(Some(())) } else { Ok(None) }
-                })
-            },
-        )
-        .await;
+impl OptionalRangeInputs {
+    /// The LSP range these inputs refer to, or `None` for the whole file.
+    fn range(&self) -> anyhow::Result<Option<Range>> {
+        match (self.line, self.character, self.end_line, self.end_character) {
+            (Some(line), Some(character), Some(end_line), Some(end_character)) => {
+                Ok(Some(Range::new(
+                    lsp_position(line, character, self.one_based),
+                    lsp_position(end_line, end_character, self.one_based),
+                )))
+            }
+            (None, None, None, None) => Ok(None),
+            _ => Err(anyhow!(
+                "Give all of `line`, `character`, `end_line` and `end_character`, or none of \
+                 them for the whole file"
+            )),
+        }
+    }
 }
 
-fn file_path_to_uri(file_path: &str) -> anyhow::Result<Uri> {
-    if file_path.starts_with("file://") {
-        Uri::from_str(file_path).map_err(|e| anyhow!("Invalid URI: {}", e))
-    } else {
-        Uri::from_str(&format!("file://{}", file_path))
-            .map_err(|e| anyhow!("Invalid file path: {}", e))
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct MoveItemInputs {
+    #[serde(flatten)]
+    pub range: RangeInputs,
+    /// `up` or `down`.
+    pub direction: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct HoverInputs {
+    #[serde(flatten)]
+    pub position: FilePositionInputs,
+    /// Also return the signature, docs and defining crate parsed out of the hover markdown.
+    #[serde(default)]
+    pub structured: bool,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ExtractInputs {
+    #[serde(flatten)]
+    pub range: RangeInputs,
+    /// What to extract the selected code into: `function`, `variable` or `constant`.
+    pub extract_kind: String,
+    /// Write the extraction to the file instead of only returning the edits.
+    #[serde(default)]
+    pub apply: bool,
+}
+
+/// The title prefix of rust-analyzer's assist extracting into `kind`.
+fn extract_assist_title(kind: &str) -> anyhow::Result<&'static str> {
+    match kind.to_ascii_lowercase().as_str() {
+        "function" => Ok("Extract into function"),
+        "variable" => Ok("Extract into variable"),
+        "constant" => Ok("Extract into constant"),
+        _ => Err(anyhow!(
+            "Invalid extract kind `{}`: expected \"function\", \"variable\" or \"constant\"",
+            kind
+        )),
     }
 }
 
-async fn ensure_document_open(bridge_state: &mut BridgeState, file_path: &str) -> Result<Uri> {
-    let file_path = Path::new(file_path);
-    let file_path =
-        std::fs::canonicalize(file_path).map_err(|e| anyhow!("Invalid file path: {}", e))?;
-    let file_path = file_path
-        .to_str()
-        .ok_or_else(|| anyhow!("Invalid file path"))?;
-    let uri = file_path_to_uri(file_path)?;
-    let uri_str = uri.to_string();
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct AddImportInputs {
+    #[serde(flatten)]
+    pub position: FilePositionInputs,
+    /// Apply the import to the file instead of only returning the edits.
+    #[serde(default)]
+    pub apply: bool,
+    /// Which candidate to apply, as an index into the returned candidates. Needed when
+    /// there is more than one.
+    pub choice: Option<usize>,
+}
 
-    // Only open if not already opened
-    if !bridge_state.opened_documents.contains(&uri_str) {
-        if let Ok(content) = std::fs::read_to_string(file_path) {
-            if let Some(client) = &bridge_state.client {
-                let version = bridge_state
-                    .document_versions
-                    .get(&uri_str)
-                    .copied()
-                    .unwrap_or(1);
-                client
-                    .did_open(uri.clone(), "rust".to_string(), version, content)
-                    .await
-                    .map_err(|e| anyhow!("Failed to open document: {}", e))?;
-                bridge_state.opened_documents.insert(uri_str.clone());
-                bridge_state.document_versions.insert(uri_str, version);
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct FixPlanInputs {
+    pub file_path: String,
+    /// Write the planned fixes to the file instead of only returning them.
+    #[serde(default)]
+    pub apply: bool,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct CompletionInputs {
+    #[serde(flatten)]
+    pub position: FilePositionInputs,
+    /// How completion was triggered: `invoked` (explicitly requested, the default),
+    /// `trigger_character` (by typing `trigger_character`), or `incomplete` (re-requested
+    /// because an earlier list was incomplete).
+    pub trigger_kind: Option<String>,
+    /// The character just typed before the position. rust-analyzer triggers completion on
+    /// `.` (fields and methods), `:` (paths after `::`), `'` (lifetimes and labels) and `(`
+    /// (argument hints). Implies `trigger_kind: "trigger_character"` if that isn't set.
+    pub trigger_character: Option<String>,
+    /// Which completions to return, the best ranked first. Defaults to the first 200.
+    #[serde(flatten)]
+    pub page: PageInputs,
+    /// Same as `limit`, for older callers.
+    pub max_items: Option<usize>,
+    /// Only return completions whose label starts with this, ignoring case.
+    pub prefix_filter: Option<String>,
+}
+
+impl CompletionInputs {
+    /// The completion context to send with the request.
+    fn context(&self) -> anyhow::Result<CompletionContext> {
+        let trigger_kind = match self.trigger_kind.as_deref() {
+            None if self.trigger_character.is_some() => CompletionTriggerKind::TRIGGER_CHARACTER,
+            None | Some("invoked") => CompletionTriggerKind::INVOKED,
+            Some("trigger_character") => CompletionTriggerKind::TRIGGER_CHARACTER,
+            Some("incomplete") => CompletionTriggerKind::TRIGGER_FOR_INCOMPLETE_COMPLETIONS,
+            Some(other) => {
+                return Err(anyhow!(
+                    "Invalid trigger kind `{}`: expected \"invoked\", \"trigger_character\" or \
+                     \"incomplete\"",
+                    other
+                ));
             }
+        };
+        if trigger_kind == CompletionTriggerKind::TRIGGER_CHARACTER
+            && self.trigger_character.is_none()
+        {
+            return Err(anyhow!(
+                "`trigger_character` is required when `trigger_kind` is \"trigger_character\""
+            ));
         }
+        Ok(CompletionContext {
+            trigger_kind,
+            trigger_character: self.trigger_character.clone(),
+        })
     }
-
-    Ok(uri)
 }
 
-pub async fn build_server<Counterpart: Role>(
-    workspace_path: Option<String>,
-) -> Result<McpServer<Counterpart, impl RunWithConnectionTo<Counterpart>>> {
-    let bridge: BridgeType = Arc::new(Mutex::new(BridgeState::new()));
-    with_bridge(&bridge, workspace_path.as_deref(), async |_client| Ok(())).await?;
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct OnTypeFormattingInputs {
+    #[serde(flatten)]
+    pub position: FilePositionInputs,
+    /// The character that was typed, e.g. `.` or `=`.
+    pub ch: String,
+}
 
-    let failed_obligations_state = Arc::new(Mutex::new(FailedObligationsState::new()));
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ExplainDiagnosticInputs {
+    pub file_path: String,
+    /// Index into the file's diagnostics, ordered by position. Defaults to the first.
+    pub index: Option<usize>,
+    /// Alternatively, a position inside the diagnostic's range. 0-based unless `one_based` is set.
+    pub line: Option<u32>,
+    /// Column in the units the server negotiated (bytes for rust-analyzer).
+    pub character: Option<u32>,
+    /// If true, `line` and `character` are 1-based, as shown by editors and compiler messages.
+    #[serde(default)]
+    pub one_based: bool,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ExplainErrorCodeInputs {
+    /// A rustc error code like `E0425`, as found in a diagnostic's `code`.
+    pub code: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct DiagnosticsInputs {
+    pub file_path: String,
+    /// If true, each diagnostic also includes the source lines it covers with the
+    /// range underlined.
+    #[serde(default)]
+    pub include_context: bool,
+    /// Only return diagnostics at least this severe: "error", "warning", "information" or
+    /// "hint". The counts still cover every diagnostic.
+    pub min_severity: Option<String>,
+    /// Unsaved text to analyze instead of the file on disk. The server keeps seeing this
+    /// text for the file until different content is sent.
+    pub content: Option<String>,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ContentDiagnosticsInputs {
+    pub file_path: String,
+    /// The full proposed text of the file.
+    pub content: String,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct BatchRequest {
+    /// One of `hover`, `definition`, `declaration` or `references`.
+    pub tool: String,
+    pub file_path: String,
+    pub line: u32,
+    /// Column in the units the server negotiated (bytes for rust-analyzer).
+    pub character: u32,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct BatchInputs {
+    pub requests: Vec<BatchRequest>,
+    /// If true, every `line` and `character` is 1-based.
+    #[serde(default)]
+    pub one_based: bool,
+    /// Workspace root to route the requests to. Defaults to the workspace containing the
+    /// first file; all files must belong to the same workspace.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct DiagnosticsGlobInputs {
+    /// Glob relative to the workspace root, e.g. `src/**/*.rs`. `*` and `?` stay within a
+    /// path component and `**` spans any number of them.
+    pub pattern: String,
+    /// Which of the matching files to check, in path order. Defaults to the first 50.
+    #[serde(flatten)]
+    pub page: PageInputs,
+    /// Same as `limit`, for older callers.
+    pub max_files: Option<usize>,
+    /// Stop after this many seconds and return the files checked so far. Defaults to 30.
+    pub timeout_secs: Option<u64>,
+    /// Workspace root to search and query. Defaults to the current workspace.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ListFilesInputs {
+    /// Only list files under this directory, relative to the workspace root.
+    pub directory: Option<String>,
+    /// Return at most this many files, in path order. Defaults to 500.
+    pub max_files: Option<usize>,
+    /// Workspace root to list. Defaults to the current workspace.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct StatusInputs {
+    /// Workspace root to report on. Defaults to the default workspace.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct WorkspaceInputs {
+    pub workspace_path: String,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct WorkspaceFoldersInputs {
+    /// Folders for the workspace's rust-analyzer to also analyze, absolute or relative to
+    /// the workspace root.
+    #[serde(default)]
+    pub add: Vec<String>,
+    /// Folders to stop analyzing.
+    #[serde(default)]
+    pub remove: Vec<String>,
+    /// Workspace root whose server to change. Defaults to the current workspace.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct SsrInputs {
+    /// The rule, e.g. `foo($a, $b) ==>> bar($b, $a)`.
+    pub query: String,
+    /// If true, only check that the rule parses; no edits are produced.
+    #[serde(default)]
+    pub parse_only: bool,
+    /// A file in the workspace, used to resolve paths mentioned in the rule.
+    pub file_path: String,
+    /// Workspace root to route the request to. Defaults to the workspace containing the file.
+    pub workspace_path: Option<String>,
+}
+
+/// Inputs for tools that take no arguments beyond an optional workspace.
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct EmptyInputs {
+    /// Workspace root to query. Defaults to the current workspace.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ManifestEditInputs {
+    /// The manifest that was edited, e.g. `Cargo.toml` or `crates/core/Cargo.toml`.
+    /// Relative paths are resolved against the workspace.
+    pub manifest_path: String,
+    /// Workspace root to reload. Defaults to the workspace containing the manifest.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct SetCheckCommandInputs {
+    /// `check` or `clippy`.
+    pub command: String,
+    /// Workspace root to configure. Defaults to the current workspace.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct ConfigureInputs {
+    /// rust-analyzer settings to merge into the current ones, named without the
+    /// `rust-analyzer.` prefix, e.g. `{ "cargo": { "allTargets": true } }`. A `null` value
+    /// restores rust-analyzer's default for that setting.
+    pub settings: Value,
+    /// Workspace root to configure. Defaults to the current workspace.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+struct CrateGraphInputs {
+    /// Include dependencies from crates.io and the sysroot, not just workspace members.
+    #[serde(default)]
+    pub full: bool,
+    /// Truncate the graph to at most this many bytes; the full graph of a large
+    /// workspace can be megabytes.
+    pub max_bytes: Option<usize>,
+    /// Workspace root to query. Defaults to the current workspace.
+    pub workspace_path: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct GoalIndexInputs {
+    pub goal_index: Value,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema)]
+pub struct LspCallInputs {
+    pub method: String,
+    /// Optional params to pass to the LSP method. If omitted, `null` is sent.
+    pub params: Option<Value>,
+    /// If provided, sets the workspace root before making the call.
+    pub workspace_path: Option<String>,
+    /// If true, send as a notification (no response expected). Defaults to false.
+    pub is_notification: Option<bool>,
+}
+
+pub const SERVER_ID: &str = "rust-analyzer";
+
+/// How long a tool call waits for rust-analyzer to finish loading by default.
+const DEFAULT_READY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Environment variable overriding [`DEFAULT_READY_TIMEOUT`], in seconds.
+const READY_TIMEOUT_ENV: &str = "SYMPOSIUM_RUST_ANALYZER_READY_TIMEOUT";
+
+/// How long `rust_analyzer_ping` waits for a response.
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The rust-analyzer binary started for each workspace, looked up on `PATH`.
+const DEFAULT_RUST_ANALYZER_COMMAND: &str = "rust-analyzer";
+
+/// Environment variable overriding [`DEFAULT_RUST_ANALYZER_COMMAND`].
+const RUST_ANALYZER_PATH_ENV: &str = "RUST_ANALYZER_PATH";
+
+/// How many documents a server keeps open by default.
+const DEFAULT_MAX_OPEN_DOCUMENTS: usize = 50;
+
+/// Environment variable overriding [`DEFAULT_MAX_OPEN_DOCUMENTS`].
+const MAX_OPEN_DOCUMENTS_ENV: &str = "SYMPOSIUM_RUST_ANALYZER_MAX_OPEN_DOCUMENTS";
+
+/// How many hover and definition responses a server's cache keeps by default.
+const DEFAULT_RESPONSE_CACHE_SIZE: usize = 256;
+
+/// Environment variable overriding [`DEFAULT_RESPONSE_CACHE_SIZE`]; 0 disables the cache.
+const RESPONSE_CACHE_SIZE_ENV: &str = "SYMPOSIUM_RUST_ANALYZER_RESPONSE_CACHE_SIZE";
+
+/// How many requests of a batch are in flight at once.
+const BATCH_CONCURRENCY: usize = 8;
+
+/// How many files `rust_analyzer_list_files` returns by default.
+const DEFAULT_LIST_FILES_MAX: usize = 500;
+
+/// How many symbols `rust_analyzer_list_symbols_by_kind` returns by default.
+const DEFAULT_SYMBOLS_LIMIT: usize = 100;
+
+/// How many completions `rust_analyzer_completion` returns by default.
+const DEFAULT_COMPLETION_LIMIT: usize = 200;
+
+/// How many references `rust_analyzer_references` returns by default.
+const DEFAULT_REFERENCES_LIMIT: usize = 500;
+
+/// How many lines of source `rust_analyzer_definition_source` returns by default.
+const DEFAULT_DEFINITION_SOURCE_LINES: usize = 200;
+
+/// How many files `rust_analyzer_diagnostics_glob` checks by default.
+const DEFAULT_GLOB_MAX_FILES: usize = 50;
+
+/// How long `rust_analyzer_diagnostics_glob` gathers diagnostics by default.
+const DEFAULT_GLOB_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many times definition and references retry an empty result while the server
+/// is indexing, and how long each retry waits for indexing to finish.
+const NAVIGATION_RETRIES: u32 = 3;
+const NAVIGATION_RETRY_WAIT: Duration = Duration::from_secs(2);
+
+/// How long to wait for a workspace-wide `cargo check` to finish.
+const FLYCHECK_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How long to wait for proc macros and build scripts to be rebuilt.
+const REBUILD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// How many times in a row a crashed server is restarted before giving up, and the delay
+/// before the first restart, doubled for each following one.
+const MAX_RESTARTS: u32 = 3;
+const RESTART_BACKOFF: Duration = Duration::from_secs(1);
+
+/// A server that stays up this long after a restart is no longer considered crash looping.
+const RESTART_RESET_AFTER: Duration = Duration::from_secs(300);
+
+fn canonical_workspace(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The `file://` URI of a workspace folder.
+fn folder_uri(folder: &Path) -> anyhow::Result<Uri> {
+    Uri::from_str(&format!("file://{}", folder.display()))
+        .map_err(|e| anyhow!("Invalid workspace path: {}", e))
+}
+
+/// Returns the bridge for `workspace`, starting rust-analyzer for it if needed.
+///
+/// A server that exited is restarted once its backoff (see [`BridgeState::restart_at`])
+/// has passed; before that this fails with `server_not_ready`. [`wait_for_server`] sleeps
+/// through the backoff without holding the bridge lock.
+pub(crate) async fn ensure_bridge<'a>(
+    bridge_state: &'a mut BridgeState,
+    workspace: &Path,
+) -> Result<&'a mut WorkspaceBridge> {
+    let mut reopen = Vec::new();
+    if let Some(restart_at) = bridge_state.restart_at(workspace)? {
+        let now = tokio::time::Instant::now();
+        if restart_at > now {
+            return Err(ToolError::ServerNotReady(format!(
+                "rust-analyzer for {} exited and will be restarted in {} ms; retry then",
+                workspace.display(),
+                (restart_at - now).as_millis()
+            ))
+            .into());
+        }
+        // Keep settings changed at runtime, like the check command.
+        let configuration = bridge_state.workspaces[workspace]
+            .client
+            .configuration()
+            .await;
+        let started = start_client(bridge_state, workspace, Some(configuration)).await;
+        let restarts = bridge_state
+            .restarts
+            .entry(workspace.to_path_buf())
+            .or_default();
+        restarts.count += 1;
+        restarts.last = Some(now);
+        restarts.next_attempt = None;
+        // On failure the old bridge stays, so its documents are reopened by a later attempt.
+        let client = started?;
+
+        // Reopen what was open, so unsaved content sent earlier stays in effect.
+        let new = bridge_state.new_workspace_bridge(client);
+        let mut old = bridge_state
+            .workspaces
+            .insert(workspace.to_path_buf(), new)
+            .unwrap();
+        reopen = old
+            .opened_documents
+            .drain(..)
+            .filter_map(|uri| {
+                let text = old.document_texts.remove(&uri)?;
+                Some((uri, text))
+            })
+            .collect();
+    }
+    if !bridge_state.workspaces.contains_key(workspace) {
+        let client = start_client(bridge_state, workspace, None).await?;
+        let new = bridge_state.new_workspace_bridge(client);
+        bridge_state.workspaces.insert(workspace.to_path_buf(), new);
+    }
+    let workspace_bridge = bridge_state.workspaces.get_mut(workspace).unwrap();
+    for (uri, text) in reopen {
+        let uri = Uri::from_str(&uri).map_err(|e| anyhow!("Invalid URI: {}", e))?;
+        open_document(workspace_bridge, &uri, text).await?;
+    }
+    Ok(workspace_bridge)
+}
+
+/// Starts rust-analyzer for `workspace` and its other folders, or connects to the one at
+/// the configured TCP address. `configuration` replaces the settings derived from the
+/// cargo settings, e.g. to keep those of a server being restarted.
+async fn start_client(
+    bridge_state: &BridgeState,
+    workspace: &Path,
+    configuration: Option<Value>,
+) -> Result<LspClient> {
+    tracing::debug!(?workspace);
+
+    let roots = std::iter::once(workspace)
+        .chain(
+            bridge_state
+                .extra_folders(workspace)
+                .iter()
+                .map(PathBuf::as_path),
+        )
+        .map(folder_uri)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+
+    tracing::debug!(?roots);
+
+    let settings = configuration.unwrap_or_else(|| bridge_state.cargo_settings.to_configuration());
+    let client = match &bridge_state.tcp_address {
+        Some(address) => connect_rust_analyzer(address, roots, settings).await,
+        None => {
+            let command = std::env::var(RUST_ANALYZER_PATH_ENV)
+                .unwrap_or_else(|_| DEFAULT_RUST_ANALYZER_COMMAND.to_string());
+            start_rust_analyzer(&command, &bridge_state.env, roots, settings).await
+        }
+    }
+    .map_err(|e| {
+        let message = format!("Failed to start rust-analyzer: {}", e);
+        if e.is::<SpawnFailed>() {
+            ToolError::SpawnFailed(message)
+        } else {
+            ToolError::ServerNotReady(message)
+        }
+    })?;
+    Ok(client)
+}
+
+/// Makes `workspace_path` the default workspace, starting its server if needed.
+pub(crate) async fn set_default_workspace(bridge: &BridgeType, workspace_path: &str) -> Result<()> {
+    let mut bridge_guard = bridge.lock().await;
+    let workspace = bridge_guard.resolve_workspace(Some(workspace_path), None);
+    ensure_bridge(&mut bridge_guard, &workspace).await?;
+    bridge_guard.default_workspace = Some(workspace);
+    Ok(())
+}
+
+/// Adds folders to, and removes them from, those the server for a workspace analyzes
+/// besides its root. Relative folders are resolved against the root. Returns the root
+/// and the folders now analyzed with it.
+async fn change_workspace_folders(
+    bridge: &BridgeType,
+    workspace_path: Option<&str>,
+    add: &[String],
+    remove: &[String],
+) -> Result<(PathBuf, Vec<PathBuf>)> {
+    let mut bridge_guard = bridge.lock().await;
+    let workspace = bridge_guard.resolve_workspace(workspace_path, None);
+    let resolve = |folder: &String| canonical_workspace(&workspace.join(folder));
+    let added: Vec<PathBuf> = add.iter().map(resolve).collect();
+    let removed: Vec<PathBuf> = remove.iter().map(resolve).collect();
+    if let Some(missing) = added.iter().find(|folder| !folder.is_dir()) {
+        return Err(
+            ToolError::InvalidInput(format!("{} is not a directory", missing.display())).into(),
+        );
+    }
+    if removed.contains(&workspace) {
+        return Err(ToolError::InvalidInput(format!(
+            "{} is the workspace root and can't be removed",
+            workspace.display()
+        ))
+        .into());
+    }
+
+    let mut folders = bridge_guard.extra_folders(&workspace).to_vec();
+    let added: Vec<PathBuf> = added
+        .into_iter()
+        .filter(|folder| *folder != workspace && !folders.contains(folder))
+        .collect();
+    let removed: Vec<PathBuf> = removed
+        .into_iter()
+        .filter(|folder| folders.contains(folder))
+        .collect();
+    folders.retain(|folder| !removed.contains(folder));
+    folders.extend(added.iter().cloned());
+
+    let client = ensure_bridge(&mut bridge_guard, &workspace)
+        .await?
+        .client
+        .clone();
+    let uris = |folders: &[PathBuf]| {
+        folders
+            .iter()
+            .map(|folder| folder_uri(folder))
+            .collect::<anyhow::Result<Vec<_>>>()
+    };
+    client
+        .change_workspace_folders(&uris(&added)?, &uris(&removed)?)
+        .await
+        .map_err(|e| ToolError::request_failed("Failed to change workspace folders", e))?;
+    bridge_guard.set_workspace_folders(&workspace, folders.clone());
+    Ok((workspace, folders))
+}
+
+/// Starts the server for `workspace` if needed and waits, without holding the bridge
+/// lock, for it to finish loading, and for the backoff before restarting it if it exited.
+/// Returns a `loading` status if it doesn't in time.
+async fn wait_for_server(bridge: &BridgeType, workspace: &Path) -> Result<Option<ServerStatus>> {
+    let restart_at = bridge.lock().await.restart_at(workspace)?;
+    if let Some(restart_at) = restart_at {
+        tokio::time::sleep_until(restart_at).await;
+    }
+    let (mut ready, timeout) = {
+        let mut bridge_guard = bridge.lock().await;
+        let timeout = bridge_guard.ready_timeout();
+        let workspace_bridge = ensure_bridge(&mut bridge_guard, workspace).await?;
+        (workspace_bridge.client.ready_receiver(), timeout)
+    };
+    match tokio::time::timeout(timeout, ready.wait_for(|ready| *ready)).await {
+        Ok(_) => Ok(None),
+        Err(_) => Ok(Some(ServerStatus::loading(workspace, timeout))),
+    }
+}
+
+/// Runs `f` against the server for `workspace_path`. If the server is still loading
+/// after the ready timeout, `f` isn't run and a `loading` [`ServerStatus`] is returned.
+///
+/// `f` runs without the bridge lock, so calls to the same server proceed concurrently.
+pub(crate) async fn with_bridge<F, R>(
+    bridge: &BridgeType,
+    workspace_path: Option<&str>,
+    f: F,
+) -> Result<R>
+where
+    F: for<'a> AsyncFnOnce(&'a LspClient) -> Result<R>,
+    R: From<ServerStatus>,
+{
+    let workspace = bridge.lock().await.resolve_workspace(workspace_path, None);
+    if let Some(loading) = wait_for_server(bridge, &workspace).await? {
+        return Ok(loading.into());
+    }
+    let client = {
+        let mut bridge_guard = bridge.lock().await;
+        ensure_bridge(&mut bridge_guard, &workspace)
+            .await?
+            .client
+            .clone()
+    };
+    f(&client).await.map_err(ToolError::structured)
+}
+
+pub async fn with_bridge_and_document<F, R>(
+    bridge: &BridgeType,
+    workspace_path: Option<&str>,
+    file_path: &str,
+    f: F,
+) -> Result<R>
+where
+    F: for<'a> AsyncFnOnce(&'a LspClient, Uri) -> Result<R>,
+    R: From<ServerStatus>,
+{
+    with_bridge_and_document_content(bridge, workspace_path, file_path, None, f).await
+}
+
+/// Closes `file_path` in its workspace's server, returning whether it was open along with
+/// the resolved path. Never starts a server.
+pub async fn close_document(
+    bridge: &BridgeType,
+    workspace_path: Option<&str>,
+    file_path: &str,
+) -> Result<(bool, PathBuf)> {
+    let mut bridge_guard = bridge.lock().await;
+    let (file_path, workspace) = bridge_guard.resolve_document(workspace_path, file_path)?;
+    let Some(workspace_bridge) = bridge_guard.workspaces.get_mut(&workspace) else {
+        return Ok((false, file_path));
+    };
+    let uri = file_path_to_uri(
+        file_path
+            .to_str()
+            .ok_or_else(|| anyhow!("Invalid file path"))?,
+    )?;
+    let closed = workspace_bridge.close_document(&uri.to_string()).await?;
+    Ok((closed, file_path))
+}
+
+/// Applies `edits` to the in-memory text of `file_path`, opening it from disk first if
+/// needed, and sends the result to the server as one change. The file on disk is left
+/// alone, so later analysis sees the edited text until it's closed or replaced.
+pub async fn apply_document_edits(
+    bridge: &BridgeType,
+    workspace_path: Option<&str>,
+    file_path: &str,
+    edits: &[TextEdit],
+) -> Result<()> {
+    let mut bridge_guard = bridge.lock().await;
+    let (file_path, workspace) = bridge_guard.resolve_document(workspace_path, file_path)?;
+    let workspace_bridge = ensure_bridge(&mut bridge_guard, &workspace).await?;
+    let uri = ensure_document_open(workspace_bridge, &file_path, None).await?;
+    workspace_bridge.apply_edits(&uri, edits).await
+}
+
+/// Why `path` doesn't look like a Cargo manifest, if it doesn't.
+fn manifest_warning(path: &Path) -> Option<String> {
+    if path.file_name().is_some_and(|name| name == "Cargo.toml") {
+        if path.is_file() {
+            return None;
+        }
+        return Some(format!("{} doesn't exist", path.display()));
+    }
+    Some(format!(
+        "{} isn't a Cargo.toml; the workspace was reloaded anyway, but only manifest edits need \
+         a reload",
+        path.display()
+    ))
+}
+
+/// The absolute path a tool's `file_path` refers to; see [`BridgeState::resolve_file_path`].
+async fn resolve_file_path(
+    bridge: &BridgeType,
+    workspace_path: Option<&str>,
+    file_path: &str,
+) -> Result<PathBuf> {
+    Ok(bridge
+        .lock()
+        .await
+        .resolve_file_path(workspace_path, file_path)?)
+}
+
+/// Like [`resolve_file_path`], but also returns the position encoding negotiated by the
+/// server for the file's workspace, starting that server if it isn't running, and where
+/// its documents are read from.
+async fn resolve_file_encoding(
+    bridge: &BridgeType,
+    workspace_path: Option<&str>,
+    file_path: &str,
+) -> Result<(PathBuf, PositionEncoding, Arc<dyn DocumentSource>)> {
+    let mut bridge_guard = bridge.lock().await;
+    let (file_path, workspace) = bridge_guard.resolve_document(workspace_path, file_path)?;
+    let workspace_bridge = ensure_bridge(&mut bridge_guard, &workspace).await?;
+    Ok((
+        file_path,
+        workspace_bridge.client.position_encoding(),
+        workspace_bridge.document_source.clone(),
+    ))
+}
+
+/// Where documents are read from; see [`BridgeState::document_source`].
+async fn bridge_document_source(bridge: &BridgeType) -> Arc<dyn DocumentSource> {
+    bridge.lock().await.document_source()
+}
+
+/// Like [`with_bridge_and_document`], but if `content` is given the server analyzes that
+/// text for the file instead of what's on disk.
+///
+/// Without `content`, `f` runs without the bridge lock, so read-only queries proceed
+/// concurrently. With it, the lock is held until `f` is done, so no other call can change
+/// the document while `f` is looking at the text it just sent.
+pub async fn with_bridge_and_document_content<F, R>(
+    bridge: &BridgeType,
+    workspace_path: Option<&str>,
+    file_path: &str,
+    content: Option<String>,
+    f: F,
+) -> Result<R>
+where
+    F: for<'a> AsyncFnOnce(&'a LspClient, Uri) -> Result<R>,
+    R: From<ServerStatus>,
+{
+    let (file_path, workspace) = bridge
+        .lock()
+        .await
+        .resolve_document(workspace_path, file_path)?;
+    if let Some(loading) = wait_for_server(bridge, &workspace).await? {
+        return Ok(loading.into());
+    }
+    let exclusive = content.is_some();
+    let mut bridge_guard = bridge.lock().await;
+    let workspace_bridge = ensure_bridge(&mut bridge_guard, &workspace).await?;
+    let uri = ensure_document_open(workspace_bridge, &file_path, content).await?;
+    let client = workspace_bridge.client.clone();
+    if !exclusive {
+        drop(bridge_guard);
+    }
+    f(&client, uri).await.map_err(ToolError::structured)
+}
+
+/// Like [`with_bridge_and_document_content`], but `f` also gets the workspace's cached
+/// responses for the document, already invalidated if this call changed it.
+async fn with_bridge_and_cached_document<F, R>(
+    bridge: &BridgeType,
+    workspace_path: Option<&str>,
+    file_path: &str,
+    content: Option<String>,
+    f: F,
+) -> Result<R>
+where
+    F: for<'a, 'b> AsyncFnOnce(&'a LspClient, &'b DocumentCache, Uri) -> Result<R>,
+    R: From<ServerStatus>,
+{
+    let (file_path, workspace) = bridge
+        .lock()
+        .await
+        .resolve_document(workspace_path, file_path)?;
+    if let Some(loading) = wait_for_server(bridge, &workspace).await? {
+        return Ok(loading.into());
+    }
+    let exclusive = content.is_some();
+    let mut bridge_guard = bridge.lock().await;
+    let workspace_bridge = ensure_bridge(&mut bridge_guard, &workspace).await?;
+    let uri = ensure_document_open(workspace_bridge, &file_path, content).await?;
+    let client = workspace_bridge.client.clone();
+    let cache = workspace_bridge.response_cache.for_document(uri.as_str());
+    if !exclusive {
+        drop(bridge_guard);
+    }
+    f(&client, &cache, uri).await.map_err(ToolError::structured)
+}
+
+/// Runs `f` with `content` standing in for the text of `file_path`, then puts back what
+/// the server had before so later calls don't see the hypothetical buffer: the text an
+/// earlier call left open, or no open document at all. The bridge lock is held throughout,
+/// so no other call sees the hypothetical text either. The swap runs on a task of its own,
+/// so the old text is put back even if this call is dropped halfway.
+async fn with_hypothetical_content<F, Fut, R>(
+    bridge: &BridgeType,
+    workspace_path: Option<&str>,
+    file_path: &str,
+    content: String,
+    f: F,
+) -> Result<R>
+where
+    F: FnOnce(Arc<LspClient>, Uri) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<R>> + Send,
+    R: From<ServerStatus> + Send + 'static,
+{
+    let (file_path, workspace) = bridge
+        .lock()
+        .await
+        .resolve_document(workspace_path, file_path)?;
+    if let Some(loading) = wait_for_server(bridge, &workspace).await? {
+        return Ok(loading.into());
+    }
+    let bridge = bridge.clone();
+    let swap = tokio::spawn(async move {
+        let mut bridge_guard = bridge.lock().await;
+        let workspace_bridge = ensure_bridge(&mut bridge_guard, &workspace).await?;
+        let uri = file_path_to_uri(
+            file_path
+                .to_str()
+                .ok_or_else(|| anyhow!("Invalid file path"))?,
+        )?;
+        let uri_str = uri.to_string();
+        let previous = workspace_bridge
+            .opened_documents
+            .contains(&uri_str)
+            .then(|| workspace_bridge.document_texts.get(&uri_str).cloned())
+            .flatten();
+        let uri = ensure_document_open(workspace_bridge, &file_path, Some(content)).await?;
+
+        let result = f(workspace_bridge.client.clone(), uri.clone())
+            .await
+            .map_err(ToolError::structured);
+
+        let restored = match previous {
+            Some(text) => workspace_bridge.change_document(&uri, text).await,
+            None => workspace_bridge.close_document(&uri_str).await.map(|_| ()),
+        };
+        if let Err(error) = restored {
+            tracing::warn!(uri = uri_str, ?error, "failed to restore the document");
+        }
+        result
+    });
+    swap.await.map_err(sacp::util::internal_error)?
+}
+
+fn file_path_to_uri(file_path: &str) -> anyhow::Result<Uri> {
+    if file_path.starts_with("file://") {
+        Uri::from_str(file_path).map_err(|e| anyhow!("Invalid URI: {}", e))
+    } else {
+        Uri::from_str(&format!("file://{}", file_path))
+            .map_err(|e| anyhow!("Invalid file path: {}", e))
+    }
+}
+
+fn uri_to_path(uri: &Uri) -> Option<PathBuf> {
+    if uri.scheme().map(|scheme| scheme.as_str()) != Some("file") {
+        return None;
+    }
+    let path = uri.path().as_estr().decode().into_string().ok()?;
+    Some(PathBuf::from(path.as_ref()))
+}
+
+/// A location together with the source it points at.
+#[derive(Serialize, JsonSchema)]
+struct LocationPreview {
+    #[serde(flatten)]
+    #[schemars(with = "LocationSchema")]
+    location: Location,
+    /// 1-based line of the start of the range.
+    line: u32,
+    /// 1-based column of the start of the range.
+    column: u32,
+    /// The line(s) covered by the range, or `None` if the file couldn't be read.
+    text: Option<String>,
+}
+
+/// A type in a type hierarchy, reduced to what identifies it.
+#[derive(Serialize, JsonSchema)]
+struct TypeHierarchyEntry {
+    name: String,
+    /// The LSP `SymbolKind` number.
+    #[schemars(with = "i32")]
+    kind: SymbolKind,
+    detail: Option<String>,
+    /// Where the type's name is declared.
+    #[schemars(with = "LocationSchema")]
+    location: Location,
+}
+
+impl From<TypeHierarchyItem> for TypeHierarchyEntry {
+    fn from(item: TypeHierarchyItem) -> Self {
+        Self {
+            name: item.name,
+            kind: item.kind,
+            detail: item.detail,
+            location: Location::new(item.uri, item.selection_range),
+        }
+    }
+}
+
+/// A type with its direct supertypes and subtypes.
+#[derive(Serialize, JsonSchema)]
+struct TypeHierarchy {
+    #[serde(flatten)]
+    item: TypeHierarchyEntry,
+    supertypes: Vec<TypeHierarchyEntry>,
+    subtypes: Vec<TypeHierarchyEntry>,
+}
+
+/// A diagnostic together with what an agent needs to fix it.
+#[derive(Serialize, JsonSchema)]
+struct DiagnosticExplanation {
+    /// The LSP `Diagnostic`.
+    #[schemars(with = "Value")]
+    diagnostic: Diagnostic,
+    /// LSP `Hover` at the start of the diagnostic's range.
+    #[schemars(with = "Option<Value>")]
+    hover: Option<Hover>,
+    /// Fixes and refactorings offered for the diagnostic's range, with their edits.
+    #[schemars(with = "Vec<Value>")]
+    code_actions: Vec<CodeActionOrCommand>,
+    /// The `rustc --explain` text for the diagnostic's error code, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_code_explanation: Option<String>,
+}
+
+/// Result of `rust_analyzer_explain_error_code`.
+#[derive(Serialize, JsonSchema)]
+struct ErrorCodeExplanation {
+    code: String,
+    /// The explanation in markdown, with examples of the error and its fixes.
+    explanation: String,
+}
+
+/// A `{"unsupported": "<feature>"}` response if the server didn't advertise `method`,
+/// for tools to return instead of sending a request it can't handle.
+fn unsupported<T>(client: &LspClient, method: &str) -> Option<ToolOutput<T>> {
+    unsupported_feature(client, method).map(|feature| ToolOutput {
+        unsupported: Some(feature),
+        ..ToolOutput::empty()
+    })
+}
+
+/// The feature named in an [`unsupported`] response, if the server didn't advertise `method`.
+fn unsupported_feature(client: &LspClient, method: &str) -> Option<String> {
+    if client.supports(method) {
+        return None;
+    }
+    Some(
+        method
+            .strip_prefix("textDocument/")
+            .unwrap_or(method)
+            .to_string(),
+    )
+}
+
+/// What the running server supports, as reported by `rust_analyzer_capabilities`.
+#[derive(Serialize, JsonSchema)]
+struct CapabilitiesReport {
+    server_info: Option<VersionResult>,
+    /// Standard requests the server advertised.
+    supported: Vec<&'static str>,
+    /// Standard requests the server did not advertise; tools relying on them will fail.
+    unsupported: Vec<&'static str>,
+    /// The LSP `ServerCapabilities` the server sent.
+    #[schemars(with = "Value")]
+    capabilities: ServerCapabilities,
+}
+
+/// Cuts `text` to at most `max_bytes` on a line boundary, noting the original size in a
+/// trailing DOT comment.
+fn truncate_output(mut text: String, max_bytes: usize) -> String {
+    if text.len() <= max_bytes {
+        return text;
+    }
+    let total = text.len();
+    let mut end = max_bytes;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    let end = text[..end].rfind('\n').map_or(end, |newline| newline + 1);
+    text.truncate(end);
+    text.push_str(&format!("// truncated: {} of {} bytes shown\n", end, total));
+    text
+}
+
+/// Default for how long `rust_analyzer_run_test` lets a command run.
+const DEFAULT_RUN_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Default for how much of each output stream `rust_analyzer_run_test` keeps.
+const DEFAULT_RUN_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// Picks the runnable to run: the one labelled `label`, or else the innermost one of those
+/// found at a position.
+fn select_runnable(runnables: Vec<Runnable>, label: Option<&str>) -> anyhow::Result<Runnable> {
+    if let Some(label) = label {
+        let labels: Vec<_> = runnables.iter().map(|r| r.label.clone()).collect();
+        return runnables
+            .into_iter()
+            .find(|runnable| runnable.label == label)
+            .ok_or_else(|| {
+                anyhow!(
+                    "No runnable labelled `{}`; available: {}",
+                    label,
+                    labels.join(", ")
+                )
+            });
+    }
+    runnables
+        .into_iter()
+        .min_by_key(|runnable| {
+            runnable.location.as_ref().map_or(u32::MAX, |location| {
+                location.target_range.end.line - location.target_range.start.line
+            })
+        })
+        .ok_or_else(|| anyhow!("No runnable at that position"))
+}
+
+/// Result of `rust_analyzer_hover`.
+#[derive(Serialize, JsonSchema)]
+struct HoverResult {
+    /// The LSP `Hover`, or null if there is nothing to show at the position.
+    #[schemars(with = "Option<Value>")]
+    hover: Option<Hover>,
+    /// The parts extracted from the hover, when `structured` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<HoverSummary>,
+}
+
+/// Result of `rust_analyzer_definition` and `rust_analyzer_declaration`.
+#[derive(Serialize, JsonSchema)]
+struct GotoResult {
+    /// The LSP `GotoDefinitionResponse`: one location, several, or location links.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Value>")]
+    locations: Option<GotoDefinitionResponse>,
+    /// The locations with their source, when `include_preview` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previews: Option<Vec<LocationPreview>>,
+}
+
+/// Result of `rust_analyzer_definition_source`.
+#[derive(Serialize, JsonSchema)]
+struct DefinitionSourceResult {
+    /// Where the symbol is defined.
+    #[schemars(with = "LocationSchema")]
+    location: Location,
+    /// The innermost item containing the definition, usually the defined item itself;
+    /// null if there is none, in which case `source` is just the definition's lines.
+    item: Option<EnclosingItem>,
+    /// 1-based line of the target file that `source` starts at.
+    start_line: u32,
+    /// The item's source text.
+    source: String,
+    /// Whether `source` was cut off at `max_lines`.
+    truncated: bool,
+}
+
+/// Result of `rust_analyzer_references`.
+#[derive(Serialize, JsonSchema)]
+struct ReferencesResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Vec<LocationSchema>>")]
+    references: Option<Vec<Location>>,
+    /// The references with their source, when `include_preview` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    previews: Option<Vec<LocationPreview>>,
+    /// The references by file, when `group_by_file` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<Vec<FileReferences>>,
+    /// How many references there are across all files and pages.
+    #[serde(flatten)]
+    page: PageInfo,
+}
+
+impl ReferencesResult {
+    fn empty(page: PageInfo) -> Self {
+        Self {
+            references: None,
+            previews: None,
+            files: None,
+            page,
+        }
+    }
+}
+
+/// The references in one file.
+#[derive(Serialize, JsonSchema)]
+struct FileReferences {
+    uri: String,
+    count: usize,
+    references: Vec<CategorizedReference>,
+}
+
+/// A reference and whether it reads or writes the symbol.
+#[derive(Serialize, JsonSchema)]
+struct CategorizedReference {
+    #[schemars(with = "RangeSchema")]
+    range: Range,
+    /// `read` or `write`, from the document highlight at the reference; null when the
+    /// server doesn't tell.
+    access: Option<&'static str>,
+}
+
+/// Result of `rust_analyzer_diagnostics_glob`.
+#[derive(Serialize, JsonSchema)]
+struct GlobDiagnosticsResult {
+    /// Diagnostics of the files checked, keyed by URI; files without any are included.
+    #[serde(flatten)]
+    summary: WorkspaceDiagnosticsSummary,
+    /// How many files matched the pattern.
+    matched: usize,
+    /// How many were checked, fewer than `matched` past `limit` or the timeout.
+    checked: usize,
+    /// `total` is the same as `matched`; `has_more` is set when files were left unchecked
+    /// after this page, because of `limit` or the timeout.
+    #[serde(flatten)]
+    page: PageInfo,
+    /// Whether the timeout cut the check short.
+    timed_out: bool,
+}
+
+/// Result of `rust_analyzer_list_files`.
+#[derive(Serialize, JsonSchema)]
+struct ListFilesResult {
+    files: Vec<WorkspaceFile>,
+    /// How many `.rs` files there are, more than listed past `max_files`.
+    total: usize,
+}
+
+/// A Rust source file in the workspace.
+#[derive(Serialize, JsonSchema)]
+struct WorkspaceFile {
+    /// Relative to the workspace root.
+    path: String,
+    /// Size in bytes.
+    size: u64,
+}
+
+/// Result of `rust_analyzer_batch`, one entry per request in order.
+#[derive(Serialize, JsonSchema)]
+struct BatchResult {
+    results: Vec<BatchItemResult>,
+}
+
+/// Result of `rust_analyzer_type_hierarchy`, one entry per type at the position.
+#[derive(Serialize, JsonSchema)]
+struct TypeHierarchyResult {
+    hierarchies: Vec<TypeHierarchy>,
+}
+
+/// Result of `rust_analyzer_completion`.
+#[derive(Serialize, JsonSchema)]
+struct CompletionResult {
+    /// The LSP `CompletionResponse`: a list of items or a `CompletionList`, sorted by
+    /// `sortText`.
+    #[schemars(with = "Option<Value>")]
+    completions: Option<CompletionResponse>,
+    /// How many completions matched `prefix_filter`, across all pages.
+    #[serde(flatten)]
+    page: Option<PageInfo>,
+}
+
+/// Result of `rust_analyzer_symbols`.
+#[derive(Serialize, JsonSchema)]
+struct SymbolsResult {
+    /// The LSP `DocumentSymbolResponse`, unless `flatten` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Value>")]
+    symbols: Option<DocumentSymbolResponse>,
+    /// The symbols in document order, when `flatten` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    flat_symbols: Option<Vec<FlatSymbol>>,
+}
+
+/// An impl of a trait, with the type it's for.
+#[derive(Serialize, JsonSchema)]
+struct TraitImpl {
+    /// The implementing type, taken from the hover at the impl; null if there is none.
+    type_name: Option<String>,
+    #[schemars(with = "LocationSchema")]
+    location: Location,
+}
+
+/// Result of `rust_analyzer_trait_impls`.
+#[derive(Serialize, JsonSchema)]
+struct TraitImplsResult {
+    /// Where the trait's name is declared.
+    #[schemars(with = "LocationSchema")]
+    trait_location: Location,
+    impls: Vec<TraitImpl>,
+}
+
+/// Result of `rust_analyzer_list_symbols_by_kind`.
+#[derive(Serialize, JsonSchema)]
+struct ListSymbolsByKindResult {
+    /// One page of the matching symbols, ordered by file and position.
+    symbols: Vec<WorkspaceSymbol>,
+    #[serde(flatten)]
+    page: PageInfo,
+}
+
+/// An item of a file's API with its declaration.
+#[derive(Serialize, JsonSchema)]
+struct ApiItem {
+    /// The item's path within the file, e.g. `Person::new`.
+    path: String,
+    #[schemars(with = "i32")]
+    kind: SymbolKind,
+    /// The declaration rust-analyzer shows on hover, e.g. `pub fn greet(&self) -> String`;
+    /// null if the hover has none.
+    signature: Option<String>,
+    /// 1-based line of the item's name.
+    line: u32,
+    public: bool,
+}
+
+/// Result of `rust_analyzer_public_api`.
+#[derive(Serialize, JsonSchema)]
+struct PublicApiResult {
+    /// The file's items in document order.
+    items: Vec<ApiItem>,
+}
+
+/// Result of `rust_analyzer_find_symbol`.
+#[derive(Serialize, JsonSchema)]
+struct FindSymbolResult {
+    matches: Vec<SymbolMatch>,
+}
+
+/// Result of `rust_analyzer_set_check_command`.
+#[derive(Serialize, JsonSchema)]
+struct CheckCommandResult {
+    /// The command rust-analyzer now checks with.
+    command: String,
+}
+
+/// Result of `rust_analyzer_resolve_doc_link`.
+#[derive(Serialize, JsonSchema)]
+struct DocLinkResult {
+    /// The link's target as written, e.g. `Person::greet`.
+    target: String,
+    /// Where the target is declared; null if the link doesn't resolve.
+    #[schemars(with = "Option<LocationSchema>")]
+    location: Option<Location>,
+    /// The target's fully qualified path, e.g. `my_crate::Person::greet`; null if there is
+    /// none.
+    path: Option<String>,
+    /// Why the link doesn't resolve or has no path.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+/// Result of `rust_analyzer_configure`.
+#[derive(Serialize, JsonSchema)]
+struct ConfigureResult {
+    /// The settings rust-analyzer now runs with, defaults included.
+    configuration: Value,
+}
+
+/// Result of `rust_analyzer_symbol_path`.
+#[derive(Serialize, JsonSchema)]
+struct SymbolPathResult {
+    /// The fully qualified path, e.g. `my_crate::module::Type::method`; null if there is none.
+    path: Option<String>,
+    /// Why there is no path, e.g. because the symbol is a local variable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+/// Result of `rust_analyzer_type_at`.
+#[derive(Serialize, JsonSchema)]
+struct TypeAtResult {
+    /// The inferred type, e.g. `i32` or `Vec<Person>`; null if there is none.
+    #[serde(rename = "type")]
+    type_name: Option<String>,
+    /// Why there is no type, e.g. because the position is on a function name.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+/// Result of `rust_analyzer_enclosing_item`.
+#[derive(Serialize, JsonSchema)]
+struct EnclosingItemResult {
+    /// The items containing the position, outermost first; the last is the smallest.
+    items: Vec<EnclosingItem>,
+}
+
+/// Result of `rust_analyzer_folding_ranges`.
+#[derive(Serialize, JsonSchema)]
+struct FoldingRangesResult {
+    /// LSP `FoldingRange`s.
+    #[schemars(with = "Option<Vec<Value>>")]
+    ranges: Option<Vec<FoldingRange>>,
+}
+
+/// Result of `rust_analyzer_selection_range` and `rust_analyzer_linked_editing`.
+#[derive(Serialize, JsonSchema)]
+struct RangesResult {
+    #[schemars(with = "Vec<RangeSchema>")]
+    ranges: Vec<Range>,
+}
+
+/// Result of `rust_analyzer_semantic_tokens`.
+#[derive(Serialize, JsonSchema)]
+struct SemanticTokensResult {
+    tokens: Vec<DecodedSemanticToken>,
+}
+
+/// Result of `rust_analyzer_code_lens`.
+#[derive(Serialize, JsonSchema)]
+struct CodeLensResult {
+    /// LSP `CodeLens`es.
+    #[schemars(with = "Vec<Value>")]
+    lenses: Vec<CodeLens>,
+}
+
+/// Result of `rust_analyzer_document_links`.
+#[derive(Serialize, JsonSchema)]
+struct DocumentLinksResult {
+    /// LSP `DocumentLink`s.
+    #[schemars(with = "Vec<Value>")]
+    links: Vec<DocumentLink>,
+}
+
+/// Result of `rust_analyzer_failed_obligations`.
+#[derive(Serialize, JsonSchema)]
+struct FailedObligationsResult {
+    goals: Vec<GoalTree>,
+}
+
+/// Result of `rust_analyzer_failed_obligations_goal`.
+#[derive(Serialize, JsonSchema)]
+struct GoalResult {
+    /// The goal tree, or a list of them when several indices were given.
+    goals: Value,
+}
+
+/// Result of `rust_analyzer_runnables`.
+#[derive(Serialize, JsonSchema)]
+struct RunnablesResult {
+    runnables: Vec<Runnable>,
+}
+
+/// Result of `rust_analyzer_run_test`.
+#[derive(Serialize, JsonSchema)]
+struct RunTestResult {
+    /// The runnable that was run.
+    label: String,
+    #[serde(flatten)]
+    output: RunOutput,
+}
+
+/// Result of `rust_analyzer_related_tests`.
+#[derive(Serialize, JsonSchema)]
+struct RelatedTestsResult {
+    tests: Vec<TestInfo>,
+}
+
+/// Result of `rust_analyzer_open_cargo_toml`.
+#[derive(Serialize, JsonSchema)]
+struct CargoTomlResult {
+    /// The manifest, or null if the file isn't part of a package.
+    #[schemars(with = "Option<LocationSchema>")]
+    location: Option<Location>,
+}
+
+/// Result of the tools returning rust-analyzer's snippet edits as plain text edits.
+#[derive(Serialize, JsonSchema)]
+struct PlainEditsResult {
+    /// Null when the editor's default behaviour is fine.
+    edits: Option<Vec<PlainTextEdit>>,
+}
+
+/// Result of the tools returning LSP text edits.
+#[derive(Serialize, JsonSchema)]
+struct TextEditsResult {
+    #[schemars(with = "Option<Vec<TextEditSchema>>")]
+    edits: Option<Vec<TextEdit>>,
+}
+
+/// Result of `rust_analyzer_matching_brace`.
+#[derive(Serialize, JsonSchema)]
+struct MatchingBraceResult {
+    #[schemars(with = "Option<PositionSchema>")]
+    position: Option<Position>,
+}
+
+/// Result of `rust_analyzer_ssr`.
+#[derive(Serialize, JsonSchema)]
+struct SsrResult {
+    /// Whether the rule parsed, when `parse_only` is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    parsed: Option<bool>,
+    /// Why the rule didn't parse.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    /// The LSP `WorkspaceEdit` performing the replacement.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<Value>")]
+    edit: Option<WorkspaceEdit>,
+}
+
+/// Result of the tools returning a text dump, such as HIR or the crate graph.
+#[derive(Serialize, JsonSchema)]
+struct TextResult {
+    text: String,
+}
+
+/// Result of `rust_analyzer_interpret`.
+#[derive(Serialize, JsonSchema)]
+struct InterpretResult {
+    /// What the function printed, then its return value.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    /// Why the function couldn't be interpreted, e.g. because it takes arguments.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Result of `rust_analyzer_workspace_folders`.
+#[derive(Serialize, JsonSchema)]
+struct WorkspaceFoldersResult {
+    /// The workspace root, whose rust-analyzer analyzes the folders.
+    root: PathBuf,
+    /// The folders analyzed besides the root.
+    folders: Vec<PathBuf>,
+}
+
+/// Result of the tools that only report what they did.
+#[derive(Serialize, JsonSchema)]
+struct MessageResult {
+    message: String,
+}
+
+/// Result of `rust_analyzer_server_messages`.
+#[derive(Serialize, JsonSchema)]
+struct ServerMessagesResult {
+    messages: Vec<ServerMessage>,
+}
+
+/// Result of `rust_analyzer_progress`.
+#[derive(Serialize, JsonSchema)]
+struct ProgressResult {
+    /// `ready`, `loading`, or `not_started` if no server runs for the workspace yet.
+    status: &'static str,
+    /// Work in progress, such as indexing or a cargo check, oldest first.
+    progress: Vec<Progress>,
+}
+
+/// Result of `rust_analyzer_rebuild_proc_macros`.
+#[derive(Serialize, JsonSchema)]
+struct RebuildResult {
+    /// Whether the server was `ready` or `loading` when the rebuild was requested.
+    before: &'static str,
+    /// Its status once the rebuild finished, or `loading` if it was still going at the
+    /// time limit.
+    after: &'static str,
+}
+
+/// Result of `rust_analyzer_after_manifest_edit`.
+#[derive(Serialize, JsonSchema)]
+struct ManifestEditResult {
+    /// Whether rust-analyzer finished reloading within the time limit. If not, no check
+    /// was run and the diagnostics may predate the edit.
+    reloaded: bool,
+    #[serde(flatten)]
+    diagnostics: WorkspaceDiagnosticsSummary,
+    /// Set if `manifest_path` doesn't look like a Cargo manifest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    warning: Option<String>,
+}
+
+/// Result of `rust_analyzer_server_log`, oldest line first.
+#[derive(Serialize, JsonSchema)]
+struct ServerLogResult {
+    lines: Vec<String>,
+}
+
+/// Result of `rust_analyzer_version`: the `serverInfo` from the server's initialize result.
+#[derive(Serialize, JsonSchema)]
+struct VersionResult {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+}
+
+impl From<&ServerInfo> for VersionResult {
+    fn from(info: &ServerInfo) -> Self {
+        Self {
+            name: info.name.clone(),
+            version: info.version.clone(),
+        }
+    }
+}
+
+/// Result of `rust_analyzer_lsp_call`.
+#[derive(Serialize, JsonSchema)]
+struct LspCallResult {
+    /// The response to a request.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    result: Option<Value>,
+    /// Confirms a notification was sent.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+/// A `use` rust-analyzer can add for an unresolved name.
+#[derive(Serialize, JsonSchema)]
+struct ImportCandidate {
+    /// e.g. ``Import `std::collections::HashMap` ``.
+    label: String,
+    #[schemars(with = "Vec<TextEditSchema>")]
+    edits: Vec<TextEdit>,
+}
+
+/// Result of `rust_analyzer_extract`.
+#[derive(Serialize, JsonSchema)]
+struct ExtractResult {
+    /// The assist's title, e.g. `Extract into function`.
+    title: String,
+    #[schemars(with = "Vec<TextEditSchema>")]
+    edits: Vec<TextEdit>,
+    /// Whether the edits were written to the file.
+    applied: bool,
+}
+
+/// Result of `rust_analyzer_add_import`.
+#[derive(Serialize, JsonSchema)]
+struct AddImportResult {
+    candidates: Vec<ImportCandidate>,
+    /// The label of the candidate written to the file, if any.
+    applied: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+/// Result of `rust_analyzer_ping`.
+#[derive(Serialize, JsonSchema)]
+struct PingReport {
+    latency_ms: u128,
+    server_version: Option<String>,
+}
+
+/// The outcome of one request in a batch.
+#[derive(Serialize, JsonSchema)]
+enum BatchItemResult {
+    #[serde(rename = "result")]
+    Ok(Value),
+    #[serde(rename = "error")]
+    Err(String),
+    /// The LSP feature the request needs, when the running server doesn't support it.
+    #[serde(rename = "unsupported")]
+    Unsupported(String),
+}
+
+impl<T: Serialize> From<anyhow::Result<T>> for BatchItemResult {
+    fn from(result: anyhow::Result<T>) -> Self {
+        match result.and_then(|value| Ok(serde_json::to_value(value)?)) {
+            Ok(value) => BatchItemResult::Ok(value),
+            Err(e) => BatchItemResult::Err(e.to_string()),
+        }
+    }
+}
+
+/// Result of `rust_analyzer_describe`. Each part holds `result` or, if its request failed,
+/// `error`, so one failure doesn't hide the rest. A part the server doesn't support holds
+/// `unsupported` instead.
+#[derive(Serialize, JsonSchema)]
+struct DescribeResult {
+    /// The LSP `Hover`, or null if there is none.
+    hover: BatchItemResult,
+    /// Locations of the symbol's definition.
+    definition: BatchItemResult,
+    /// Locations of the definition of the symbol's type.
+    type_definition: BatchItemResult,
+    /// Locations of the impls of the type or trait, or of the trait method's impls.
+    implementations: BatchItemResult,
+}
+
+/// Runs the hover, definition, type definition and implementation requests for
+/// `rust_analyzer_describe` concurrently.
+async fn describe(client: &LspClient, uri: Uri, position: Position) -> DescribeResult {
+    let (hover, definition, type_definition, implementations) = futures::join!(
+        describe_part(
+            client,
+            "textDocument/hover",
+            client.hover(uri.clone(), position)
+        ),
+        describe_locations(
+            client,
+            "textDocument/definition",
+            client.goto_definition(uri.clone(), position),
+        ),
+        describe_locations(
+            client,
+            "textDocument/typeDefinition",
+            client.goto_type_definition(uri.clone(), position),
+        ),
+        describe_locations(
+            client,
+            "textDocument/implementation",
+            client.goto_implementation(uri, position),
+        ),
+    );
+    DescribeResult {
+        hover,
+        definition,
+        type_definition,
+        implementations,
+    }
+}
+
+/// The result of `request`, without sending it if the server lacks `method`.
+async fn describe_part<T: Serialize>(
+    client: &LspClient,
+    method: &str,
+    request: impl Future<Output = anyhow::Result<T>>,
+) -> BatchItemResult {
+    match unsupported_feature(client, method) {
+        Some(feature) => BatchItemResult::Unsupported(feature),
+        None => request.await.into(),
+    }
+}
+
+/// The locations `request` finds, without sending it if the server lacks `method`.
+async fn describe_locations(
+    client: &LspClient,
+    method: &str,
+    request: impl Future<Output = anyhow::Result<Option<GotoDefinitionResponse>>>,
+) -> BatchItemResult {
+    describe_part(client, method, async {
+        Ok(request
+            .await?
+            .map(goto_response_locations)
+            .unwrap_or_default())
+    })
+    .await
+}
+
+/// Sends the request or notification of `rust_analyzer_lsp_call` as given.
+async fn lsp_call(
+    client: &LspClient,
+    method: &str,
+    params: Option<Value>,
+    is_notification: bool,
+) -> Result<ToolOutput<LspCallResult>> {
+    if is_notification {
+        client
+            .notify(method, params)
+            .await
+            .map_err(|e| ToolError::request_failed("LSP notify failed", e))?;
+        Ok(ToolOutput::ready(LspCallResult {
+            result: None,
+            message: Some("Notification sent".to_string()),
+        }))
+    } else {
+        let params = params.unwrap_or(Value::Null);
+        let result = client
+            .request(method, params)
+            .await
+            .map_err(|e| ToolError::request_failed("LSP request failed", e))?;
+        Ok(ToolOutput::ready(LspCallResult {
+            result: Some(result),
+            message: None,
+        }))
+    }
+}
+
+/// Runs the requests of a batch concurrently against one workspace's server,
+/// reporting failures per request.
+async fn run_batch(bridge: &BridgeType, input: BatchInputs) -> Result<ToolOutput<BatchResult>> {
+    let workspace_path = input.workspace_path.as_deref();
+    let (documents, workspace) = {
+        let bridge_guard = bridge.lock().await;
+        let documents: Vec<_> = input
+            .requests
+            .iter()
+            .map(|request| bridge_guard.resolve_document(workspace_path, &request.file_path))
+            .collect();
+        let workspace = documents
+            .iter()
+            .find_map(|document| document.as_ref().ok())
+            .map(|(_, workspace)| workspace.clone())
+            .unwrap_or_else(|| bridge_guard.resolve_workspace(workspace_path, None));
+        (documents, workspace)
+    };
+    if let Some(loading) = wait_for_server(bridge, &workspace).await? {
+        return Ok(loading.into());
+    }
+
+    // The bridge lock is only held to open the documents, not while the requests run.
+    let (client, uris, texts) = {
+        let mut bridge_guard = bridge.lock().await;
+        let workspace_bridge = ensure_bridge(&mut bridge_guard, &workspace).await?;
+        let mut uris = Vec::with_capacity(documents.len());
+        let mut texts = HashMap::new();
+        for document in documents {
+            let uri = match document {
+                Ok((file_path, file_workspace)) if file_workspace == workspace => {
+                    ensure_document_open(workspace_bridge, &file_path, None)
+                        .await
+                        .map_err(|e| e.to_string())
+                }
+                Ok((file_path, file_workspace)) => Err(format!(
+                    "{} belongs to workspace {}, not {}",
+                    file_path.display(),
+                    file_workspace.display(),
+                    workspace.display()
+                )),
+                Err(e) => Err(e.to_string()),
+            };
+            if let Ok(uri) = &uri {
+                let uri = uri.to_string();
+                if let Some(text) = workspace_bridge.document_texts.get(&uri) {
+                    texts.insert(uri, text.clone());
+                }
+            }
+            uris.push(uri);
+        }
+        (workspace_bridge.client.clone(), uris, texts)
+    };
+
+    let (client, texts) = (&client, &texts);
+    let one_based = input.one_based;
+    let results: Vec<BatchItemResult> = futures::stream::iter(input.requests.into_iter().zip(uris))
+        .map(|(request, uri)| async move {
+            let result = async {
+                let uri = uri?;
+                // The same checks as the single-request tools.
+                let method = batch_method(&request.tool).map_err(|e| e.to_string())?;
+                if let Some(feature) = unsupported_feature(client, method) {
+                    return Ok(BatchItemResult::Unsupported(feature));
+                }
+                let position = lsp_position(request.line, request.character, one_based);
+                if let Some(text) = texts.get(uri.as_str()) {
+                    check_position(text, position, one_based, client.position_encoding())
+                        .map_err(|e| e.to_string())?;
+                }
+                run_batch_request(client, &request, uri, position)
+                    .await
+                    .map(BatchItemResult::Ok)
+                    .map_err(|e| e.to_string())
+            };
+            result.await.unwrap_or_else(BatchItemResult::Err)
+        })
+        .buffered(BATCH_CONCURRENCY)
+        .collect()
+        .await;
+    Ok(ToolOutput::ready(BatchResult { results }))
+}
+
+/// Opens the requested page of the files matching a glob one by one and collects their
+/// diagnostics, stopping at the page's end or once the timeout passes.
+async fn run_diagnostics_glob(
+    bridge: &BridgeType,
+    input: DiagnosticsGlobInputs,
+) -> Result<ToolOutput<GlobDiagnosticsResult>> {
+    let workspace = bridge
+        .lock()
+        .await
+        .resolve_workspace(input.workspace_path.as_deref(), None);
+    let files = find_files(&workspace, &input.pattern);
+    let offset = input.page.offset.unwrap_or(0);
+    let max_files = input
+        .page
+        .limit
+        .or(input.max_files)
+        .unwrap_or(DEFAULT_GLOB_MAX_FILES);
+    if let Some(loading) = wait_for_server(bridge, &workspace).await? {
+        return Ok(loading.into());
+    }
+    let timeout = input
+        .timeout_secs
+        .map_or(DEFAULT_GLOB_TIMEOUT, Duration::from_secs);
+    let deadline = tokio::time::Instant::now() + timeout;
+
+    let mut diagnostics = HashMap::new();
+    let mut timed_out = false;
+    for file_path in files.iter().skip(offset).take(max_files) {
+        // The bridge lock is only held to open each file, so other calls aren't held up
+        // while its diagnostics are awaited.
+        let (client, uri) = {
+            let mut bridge_guard = bridge.lock().await;
+            let workspace_bridge = ensure_bridge(&mut bridge_guard, &workspace).await?;
+            // Files already open keep their text, and pushed diagnostics come from the
+            // client's store, so checking the same files again is cheap.
+            let uri = ensure_document_open(workspace_bridge, file_path, None).await?;
+            (workspace_bridge.client.clone(), uri)
+        };
+        let file_diagnostics =
+            match tokio::time::timeout_at(deadline, client.document_diagnostics(uri.clone())).await
+            {
+                Ok(file_diagnostics) => file_diagnostics
+                    .map_err(|e| ToolError::request_failed("Diagnostics request failed", e))?,
+                Err(_) => {
+                    timed_out = true;
+                    break;
+                }
+            };
+        diagnostics.insert(uri.to_string(), file_diagnostics);
+    }
+
+    let page = PageInfo {
+        total: files.len(),
+        has_more: offset.saturating_add(diagnostics.len()) < files.len(),
+    };
+    Ok(ToolOutput::ready(GlobDiagnosticsResult {
+        matched: files.len(),
+        checked: diagnostics.len(),
+        page,
+        summary: summarize_workspace_diagnostics(diagnostics),
+        timed_out,
+    }))
+}
+
+/// The LSP method a batch request's `tool` sends.
+fn batch_method(tool: &str) -> anyhow::Result<&'static str> {
+    match tool {
+        "hover" => Ok("textDocument/hover"),
+        "definition" => Ok("textDocument/definition"),
+        "declaration" => Ok("textDocument/declaration"),
+        "references" => Ok("textDocument/references"),
+        tool => Err(anyhow!(
+            "Unknown batch tool `{}`: expected hover, definition, declaration or references",
+            tool
+        )),
+    }
+}
+
+async fn run_batch_request(
+    client: &LspClient,
+    request: &BatchRequest,
+    uri: Uri,
+    position: Position,
+) -> anyhow::Result<Value> {
+    let value = match batch_method(&request.tool)? {
+        "textDocument/hover" => serde_json::to_value(client.hover(uri, position).await?)?,
+        "textDocument/definition" => {
+            serde_json::to_value(client.goto_definition(uri, position).await?)?
+        }
+        "textDocument/declaration" => {
+            serde_json::to_value(client.go_to_declaration(uri, position).await?)?
+        }
+        _ => serde_json::to_value(client.find_references(uri, position, true).await?)?,
+    };
+    Ok(value)
+}
+
+/// Where the trait `name` is declared: in `uri` if it declares one, else anywhere in the
+/// workspace.
+async fn find_trait(client: &LspClient, uri: &Uri, name: &str) -> anyhow::Result<Option<Location>> {
+    let is_trait = |kind| kind == SymbolKind::INTERFACE;
+    let symbols = client
+        .document_symbols(uri.clone())
+        .await?
+        .map(flatten_document_symbols)
+        .unwrap_or_default();
+    if let Some(symbol) = symbols
+        .into_iter()
+        .find(|symbol| symbol.name == name && is_trait(symbol.kind))
+    {
+        return Ok(Some(Location::new(uri.clone(), symbol.selection_range)));
+    }
+
+    let location = match client.workspace_symbols(name).await? {
+        Some(WorkspaceSymbolResponse::Flat(symbols)) => symbols
+            .into_iter()
+            .find(|symbol| symbol.name == name && is_trait(symbol.kind))
+            .map(|symbol| symbol.location),
+        Some(WorkspaceSymbolResponse::Nested(symbols)) => symbols
+            .into_iter()
+            .filter(|symbol| symbol.name == name && is_trait(symbol.kind))
+            .find_map(|symbol| match symbol.location {
+                OneOf::Left(location) => Some(location),
+                OneOf::Right(_) => None,
+            }),
+        None => None,
+    };
+    Ok(location)
+}
+
+/// The signatures of a file's API items, from the hover at each item's name.
+async fn api_items(
+    client: &LspClient,
+    uri: &Uri,
+    symbols: Vec<ApiSymbol>,
+) -> anyhow::Result<Vec<ApiItem>> {
+    let mut items = Vec::with_capacity(symbols.len());
+    for ApiSymbol { symbol, public } in symbols {
+        let position = symbol.selection_range.start;
+        let signature = client
+            .hover(uri.clone(), position)
+            .await?
+            .and_then(|hover| summarize_hover(&hover).signature);
+        items.push(ApiItem {
+            path: symbol.container_path,
+            kind: symbol.kind,
+            signature,
+            line: position.line + 1,
+            public,
+        });
+    }
+    Ok(items)
+}
+
+/// The impls of the trait declared at `trait_location`, each with the name of its type.
+async fn trait_impls(
+    client: &LspClient,
+    trait_location: &Location,
+) -> anyhow::Result<Vec<TraitImpl>> {
+    let locations = client
+        .goto_implementation(trait_location.uri.clone(), trait_location.range.start)
+        .await?
+        .map(goto_response_locations)
+        .unwrap_or_default();
+    let mut impls = Vec::with_capacity(locations.len());
+    for location in locations {
+        // rust-analyzer points an impl at its self type, so the hover there describes it.
+        let hover = client
+            .hover(location.uri.clone(), location.range.start)
+            .await?;
+        let type_name = hover
+            .and_then(|hover| summarize_hover(&hover).signature)
+            .and_then(|signature| declared_name(&signature));
+        impls.push(TraitImpl {
+            type_name,
+            location,
+        });
+    }
+    Ok(impls)
+}
+
+/// Groups references by file, in URI order, tagging each with the kind of the document
+/// highlight at the same range.
+async fn group_references(
+    client: &LspClient,
+    references: Vec<Location>,
+) -> anyhow::Result<Vec<FileReferences>> {
+    let mut files: BTreeMap<String, (Uri, Vec<Range>)> = BTreeMap::new();
+    for location in references {
+        files
+            .entry(location.uri.to_string())
+            .or_insert_with(|| (location.uri, Vec::new()))
+            .1
+            .push(location.range);
+    }
+
+    let mut grouped = Vec::with_capacity(files.len());
+    for (uri_str, (uri, mut ranges)) in files {
+        ranges.sort_by_key(|range| (range.start.line, range.start.character));
+        // Highlighting any one reference highlights them all within its file.
+        let highlights = if client.supports("textDocument/documentHighlight") {
+            client
+                .document_highlight(uri, ranges[0].start)
+                .await?
+                .unwrap_or_default()
+        } else {
+            vec![]
+        };
+        let references: Vec<_> = ranges
+            .into_iter()
+            .map(|range| {
+                let kind = highlights
+                    .iter()
+                    .find(|highlight| highlight.range == range)
+                    .and_then(|highlight| highlight.kind);
+                let access = match kind {
+                    Some(DocumentHighlightKind::READ) => Some("read"),
+                    Some(DocumentHighlightKind::WRITE) => Some("write"),
+                    _ => None,
+                };
+                CategorizedReference { range, access }
+            })
+            .collect();
+        grouped.push(FileReferences {
+            uri: uri_str,
+            count: references.len(),
+            references,
+        });
+    }
+    Ok(grouped)
+}
+
+/// The auto-import fixes the server offers for the name at `position`, with their edits
+/// to `uri`.
+async fn import_candidates(
+    client: &LspClient,
+    uri: &Uri,
+    position: Position,
+) -> anyhow::Result<Vec<ImportCandidate>> {
+    let fixes = quick_fixes(client, uri, Range::new(position, position), vec![]).await?;
+    Ok(fixes
+        .into_iter()
+        .filter(|fix| fix.title.starts_with("Import "))
+        .map(|fix| ImportCandidate {
+            label: fix.title,
+            edits: fix.edits,
+        })
+        .collect())
+}
+
+/// The quick fix code actions for `range`, resolved so their edits are known.
+async fn quick_fixes(
+    client: &LspClient,
+    uri: &Uri,
+    range: Range,
+    diagnostics: Vec<Diagnostic>,
+) -> anyhow::Result<Vec<QuickFix>> {
+    resolved_code_actions(client, uri, range, diagnostics, CodeActionKind::QUICKFIX).await
+}
+
+/// The code actions of `kind` for `range`, resolved so their edits are known.
+async fn resolved_code_actions(
+    client: &LspClient,
+    uri: &Uri,
+    range: Range,
+    diagnostics: Vec<Diagnostic>,
+    kind: CodeActionKind,
+) -> anyhow::Result<Vec<QuickFix>> {
+    let context = CodeActionContext {
+        diagnostics,
+        only: Some(vec![kind]),
+        trigger_kind: None,
+    };
+    let actions = client
+        .code_actions(uri.clone(), range, context)
+        .await?
+        .unwrap_or_default();
+    let mut fixes = Vec::new();
+    for action in actions {
+        let CodeActionOrCommand::CodeAction(mut action) = action else {
+            continue;
+        };
+        if action.edit.is_none() && client.supports("codeAction/resolve") {
+            action = client.resolve_code_action(action).await?;
+        }
+        let Some(edit) = action.edit else {
+            continue;
+        };
+        let edits_other_files = edits_other_files(&edit, uri);
+        fixes.push(QuickFix {
+            title: action.title,
+            edits: workspace_edit_for(edit, uri),
+            edits_other_files,
+        });
+    }
+    Ok(fixes)
+}
+
+/// Whether a workspace edit changes anything besides `uri`.
+fn edits_other_files(edit: &WorkspaceEdit, uri: &Uri) -> bool {
+    let changes = edit
+        .changes
+        .iter()
+        .flat_map(|changes| changes.keys())
+        .any(|changed| changed != uri);
+    let document_changes = match &edit.document_changes {
+        Some(DocumentChanges::Edits(document_edits)) => document_edits
+            .iter()
+            .any(|document_edit| document_edit.text_document.uri != *uri),
+        Some(DocumentChanges::Operations(operations)) => {
+            operations.iter().any(|operation| match operation {
+                DocumentChangeOperation::Edit(document_edit) => {
+                    document_edit.text_document.uri != *uri
+                }
+                DocumentChangeOperation::Op(_) => true,
+            })
+        }
+        None => false,
+    };
+    changes || document_changes
+}
+
+/// Like [`with_bridge_and_document_content`], for tools that can write edits to the file:
+/// `f` returns its output along with the edits to write, if any. The edits are applied to
+/// the text the server computed them against, and the result is written to disk and sent
+/// to the server with the bridge locked, without suspending in between.
+///
+/// Without `content` the server's text must match the file on disk, or the edits would be
+/// written over text they weren't computed for; it may not if an earlier call passed
+/// `content` or the file changed since it was opened.
+async fn with_bridge_and_editable_document<F, R>(
+    bridge: &BridgeType,
+    workspace_path: Option<&str>,
+    file_path: &str,
+    content: Option<String>,
+    f: F,
+) -> Result<R>
+where
+    F: for<'a> AsyncFnOnce(&'a LspClient, Uri) -> Result<(R, Option<Vec<TextEdit>>)>,
+    R: From<ServerStatus>,
+{
+    let (file_path, workspace) = bridge
+        .lock()
+        .await
+        .resolve_document(workspace_path, file_path)?;
+    if let Some(loading) = wait_for_server(bridge, &workspace).await? {
+        return Ok(loading.into());
+    }
+    let exclusive = content.is_some();
+    let mut bridge_guard = bridge.lock().await;
+    let workspace_bridge = ensure_bridge(&mut bridge_guard, &workspace).await?;
+    let uri = ensure_document_open(workspace_bridge, &file_path, content).await?;
+    let uri_str = uri.to_string();
+    let version = workspace_bridge.document_versions.get(&uri_str).copied();
+    let client = workspace_bridge.client.clone();
+    let held_guard = if exclusive {
+        Some(bridge_guard)
+    } else {
+        drop(bridge_guard);
+        None
+    };
+
+    let (output, edits) = f(&client, uri.clone())
+        .await
+        .map_err(ToolError::structured)?;
+    let Some(edits) = edits else {
+        return Ok(output);
+    };
+
+    let mut bridge_guard = match held_guard {
+        Some(bridge_guard) => bridge_guard,
+        None => bridge.lock().await,
+    };
+    let workspace_bridge = ensure_bridge(&mut bridge_guard, &workspace).await?;
+    let unchanged = workspace_bridge.opened_documents.contains(&uri_str)
+        && workspace_bridge.document_versions.get(&uri_str).copied() == version;
+    let base = match workspace_bridge.document_texts.get(&uri_str) {
+        Some(text) if unchanged => text.clone(),
+        _ => {
+            return Err(ToolError::InvalidInput(format!(
+                "{} changed while the edits were computed; try again",
+                file_path.display()
+            ))
+            .into());
+        }
+    };
+    if !exclusive {
+        let on_disk = std::fs::read_to_string(&file_path).map_err(|e| {
+            ToolError::FileNotFound(format!("Failed to read {}: {}", file_path.display(), e))
+        })?;
+        if on_disk != base {
+            return Err(ToolError::InvalidInput(format!(
+                "The server's text for {} differs from the file on disk, e.g. because an \
+                 earlier call passed `content`; pass the text to edit as `content`",
+                file_path.display()
+            ))
+            .into());
+        }
+    }
+    let new_text = try_apply_text_edits(&base, &edits, client.position_encoding())?;
+    std::fs::write(&file_path, &new_text)
+        .map_err(|e| anyhow!("Failed to write {}: {}", file_path.display(), e))?;
+    workspace_bridge.change_document(&uri, new_text).await?;
+    Ok(output)
+}
+
+/// The edits a workspace edit makes to `uri`.
+fn workspace_edit_for(edit: WorkspaceEdit, uri: &Uri) -> Vec<TextEdit> {
+    let mut edits = edit
+        .changes
+        .and_then(|mut changes| changes.remove(uri))
+        .unwrap_or_default();
+    let document_edits = match edit.document_changes {
+        Some(DocumentChanges::Edits(document_edits)) => document_edits,
+        Some(DocumentChanges::Operations(operations)) => operations
+            .into_iter()
+            .filter_map(|operation| match operation {
+                DocumentChangeOperation::Edit(document_edit) => Some(document_edit),
+                DocumentChangeOperation::Op(_) => None,
+            })
+            .collect(),
+        None => vec![],
+    };
+    for document_edit in document_edits {
+        if document_edit.text_document.uri == *uri {
+            edits.extend(document_edit.edits.into_iter().map(|edit| match edit {
+                OneOf::Left(edit) => edit,
+                OneOf::Right(annotated) => annotated.text_edit,
+            }));
+        }
+    }
+    edits
+}
+
+/// Whether the server offers a quick fix code action for `diagnostic`.
+async fn has_quickfix(
+    client: &LspClient,
+    uri: &Uri,
+    diagnostic: &Diagnostic,
+) -> anyhow::Result<bool> {
+    if !client.supports("textDocument/codeAction") {
+        return Ok(false);
+    }
+    let context = CodeActionContext {
+        diagnostics: vec![diagnostic.clone()],
+        only: Some(vec![CodeActionKind::QUICKFIX]),
+        trigger_kind: None,
+    };
+    let actions = client
+        .code_actions(uri.clone(), diagnostic.range, context)
+        .await?;
+    Ok(actions.is_some_and(|actions| !actions.is_empty()))
+}
+
+/// Picks the diagnostic an explain request refers to, or explains why none matched.
+fn select_diagnostic(
+    diagnostics: &[Diagnostic],
+    input: &ExplainDiagnosticInputs,
+) -> anyhow::Result<Diagnostic> {
+    let selected = match (input.line, input.character) {
+        (Some(line), Some(character)) => {
+            let position = lsp_position(line, character, input.one_based);
+            diagnostics
+                .iter()
+                .find(|d| d.range.start <= position && position <= d.range.end)
+        }
+        _ => diagnostics.get(input.index.unwrap_or(0)),
+    };
+    selected.cloned().ok_or_else(|| {
+        let available = diagnostics
+            .iter()
+            .enumerate()
+            .map(|(i, d)| {
+                format!(
+                    "{}: line {}, character {}: {}",
+                    i, d.range.start.line, d.range.start.character, d.message
+                )
+            })
+            .collect::<Vec<_>>();
+        anyhow!(
+            "No matching diagnostic; the file has {} (0-based positions): [{}]",
+            diagnostics.len(),
+            available.join("; ")
+        )
+    })
+}
+
+/// Result of `rust_analyzer_parent_module`.
+#[derive(Serialize, JsonSchema)]
+struct ParentModuleResult {
+    #[schemars(with = "Vec<LocationSchema>")]
+    locations: Vec<Location>,
+    /// Set at a crate root, where `locations` can only point at the manifest.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    message: Option<String>,
+}
+
+/// Why a navigation request found nothing: `pending` if rust-analyzer was still
+/// indexing after the retries, `no_results` if it had finished.
+struct EmptyNavigation {
+    status: &'static str,
+    message: &'static str,
+}
+
+impl<T> From<EmptyNavigation> for ToolOutput<T> {
+    fn from(empty: EmptyNavigation) -> Self {
+        Self {
+            status: Some(empty.status.to_string()),
+            message: Some(empty.message.to_string()),
+            ..Self::empty()
+        }
+    }
+}
+
+/// Runs `request` and, while it comes back empty and the server isn't ready,
+/// waits for the server and retries up to [`NAVIGATION_RETRIES`] times.
+async fn retry_while_indexing<T, Fut>(
+    client: &LspClient,
+    request: impl Fn() -> Fut,
+    is_empty: impl Fn(&T) -> bool,
+) -> anyhow::Result<std::result::Result<T, EmptyNavigation>>
+where
+    Fut: Future<Output = anyhow::Result<Option<T>>>,
+{
+    let mut attempts = 0;
+    loop {
+        let result = request().await?.filter(|result| !is_empty(result));
+        if let Some(result) = result {
+            return Ok(Ok(result));
+        }
+        if client.is_ready() {
+            return Ok(Err(EmptyNavigation {
+                status: "no_results",
+                message: "rust-analyzer found nothing at this position",
+            }));
+        }
+        if attempts == NAVIGATION_RETRIES {
+            return Ok(Err(EmptyNavigation {
+                status: "pending",
+                message: "rust-analyzer is still indexing, so the result may be incomplete; \
+                          retry shortly",
+            }));
+        }
+        attempts += 1;
+        let _ = tokio::time::timeout(NAVIGATION_RETRY_WAIT, client.wait_server_ready()).await;
+    }
+}
+
+fn goto_response_is_empty(response: &GotoDefinitionResponse) -> bool {
+    match response {
+        GotoDefinitionResponse::Scalar(_) => false,
+        GotoDefinitionResponse::Array(locations) => locations.is_empty(),
+        GotoDefinitionResponse::Link(links) => links.is_empty(),
+    }
+}
+
+fn goto_response_locations(response: GotoDefinitionResponse) -> Vec<Location> {
+    match response {
+        GotoDefinitionResponse::Scalar(location) => vec![location],
+        GotoDefinitionResponse::Array(locations) => locations,
+        GotoDefinitionResponse::Link(links) => links
+            .into_iter()
+            .map(|link| Location::new(link.target_uri, link.target_selection_range))
+            .collect(),
+    }
+}
+
+/// The result of a definition or declaration request, with previews in place of the raw
+/// response if `include_preview` is set.
+fn goto_result(
+    response: GotoDefinitionResponse,
+    include_preview: bool,
+    source: &dyn DocumentSource,
+) -> GotoResult {
+    if include_preview {
+        GotoResult {
+            locations: None,
+            previews: Some(preview_locations(goto_response_locations(response), source)),
+        }
+    } else {
+        GotoResult {
+            locations: Some(response),
+            previews: None,
+        }
+    }
+}
+
+/// Previews of `locations`, with the text of their lines read from `source`.
+fn preview_locations(
+    locations: Vec<Location>,
+    source: &dyn DocumentSource,
+) -> Vec<LocationPreview> {
+    let mut files: HashMap<String, Option<String>> = HashMap::new();
+    locations
+        .into_iter()
+        .map(|location| {
+            let contents = files.entry(location.uri.to_string()).or_insert_with(|| {
+                uri_to_path(&location.uri).and_then(|path| source.read(&path).ok())
+            });
+            let start = location.range.start.line as usize;
+            let end = location.range.end.line as usize;
+            let text = contents.as_ref().map(|contents| {
+                contents
+                    .lines()
+                    .skip(start)
+                    .take(end.saturating_sub(start) + 1)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            });
+            LocationPreview {
+                line: location.range.start.line + 1,
+                column: location.range.start.character + 1,
+                location,
+                text,
+            }
+        })
+        .collect()
+}
+
+/// Makes sure the server has the document open, with `content` as its text if given.
+///
+/// Without `content` an already open document is left alone, so text sent by an earlier
+/// call stays in effect.
+async fn ensure_document_open(
+    workspace_bridge: &mut WorkspaceBridge,
+    file_path: &Path,
+    content: Option<String>,
+) -> Result<Uri> {
+    let file_path = file_path
+        .to_str()
+        .ok_or_else(|| anyhow!("Invalid file path"))?;
+    let uri = file_path_to_uri(file_path)?;
+    let uri_str = uri.to_string();
+    let opened = workspace_bridge.mark_used(&uri_str);
+
+    match content {
+        Some(content) if opened => workspace_bridge.change_document(&uri, content).await?,
+        Some(content) => open_document(workspace_bridge, &uri, content).await?,
+        None if !opened => {
+            // Only open if not already opened
+            if let Ok(content) = workspace_bridge.document_source.read(Path::new(file_path)) {
+                open_document(workspace_bridge, &uri, content).await?;
+            }
+        }
+        None => {}
+    }
+
+    workspace_bridge.close_excess_documents().await?;
+    Ok(uri)
+}
+
+/// Opens `uri` in the server with `content`. A document that is already open gets a
+/// `didChange` instead, since a second `didOpen` makes rust-analyzer warn and can leave
+/// it with a different idea of the text than ours.
+async fn open_document(
+    workspace_bridge: &mut WorkspaceBridge,
+    uri: &Uri,
+    content: String,
+) -> Result<()> {
+    let uri_str = uri.to_string();
+    if workspace_bridge.mark_used(&uri_str) {
+        return workspace_bridge.change_document(uri, content).await;
+    }
+    let version = workspace_bridge
+        .document_versions
+        .get(&uri_str)
+        .copied()
+        .unwrap_or(1);
+    workspace_bridge
+        .client
+        .did_open(uri.clone(), "rust".to_string(), version, content.clone())
+        .await
+        .map_err(|e| ToolError::request_failed("Failed to open document", e))?;
+    workspace_bridge.opened_documents.push_back(uri_str.clone());
+    workspace_bridge.response_cache.invalidate(&uri_str);
+    workspace_bridge
+        .document_versions
+        .insert(uri_str.clone(), version);
+    workspace_bridge.document_texts.insert(uri_str, content);
+    Ok(())
+}
+
+/// Flattens a `SelectionRange` parent chain into ranges ordered innermost to outermost.
+fn selection_range_chain(selection_range: SelectionRange) -> Vec<Range> {
+    let mut ranges = vec![selection_range.range];
+    let mut parent = selection_range.parent;
+    while let Some(next) = parent {
+        ranges.push(next.range);
+        parent = next.parent;
+    }
+    ranges
+}
+
+/// Parses the environment variable `name`, if set.
+fn env_setting<T>(name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(name) {
+        Ok(value) => Ok(Some(
+            value
+                .parse()
+                .map_err(|e| anyhow!("Invalid {}: {}", name, e))?,
+        )),
+        Err(_) => Ok(None),
+    }
+}
+
+pub async fn build_server<Counterpart: Role>(
+    workspace_path: Option<String>,
+    workspace_folders: Vec<PathBuf>,
+    cargo_settings: CargoSettings,
+    tcp_address: Option<String>,
+    env: HashMap<String, String>,
+    document_source: Option<Arc<dyn DocumentSource>>,
+) -> Result<McpServer<Counterpart, impl RunWithConnectionTo<Counterpart>>> {
+    let mut bridge_state = BridgeState::new();
+    bridge_state.set_cargo_settings(cargo_settings);
+    if let Some(tcp_address) = tcp_address {
+        bridge_state.set_tcp_address(tcp_address);
+    }
+    bridge_state.set_env(env);
+    if let Some(document_source) = document_source {
+        bridge_state.set_document_source(document_source);
+    }
+    if let Some(secs) = env_setting(READY_TIMEOUT_ENV)? {
+        bridge_state.set_ready_timeout(Duration::from_secs(secs));
+    }
+    if let Some(max) = env_setting(MAX_OPEN_DOCUMENTS_ENV)? {
+        bridge_state.set_max_open_documents(max);
+    }
+    if let Some(size) = env_setting(RESPONSE_CACHE_SIZE_ENV)? {
+        bridge_state.set_response_cache_size(size);
+    }
+    // The first folder is the root when no workspace is given; the others are analyzed by
+    // the same server.
+    let workspace_path = workspace_path.or_else(|| {
+        workspace_folders
+            .first()
+            .map(|folder| folder.display().to_string())
+    });
+    if let Some(workspace_path) = &workspace_path
+        && !workspace_folders.is_empty()
+    {
+        bridge_state.set_workspace_folders(Path::new(workspace_path), workspace_folders);
+    }
+    let bridge: BridgeType = Arc::new(Mutex::new(bridge_state));
+    match &workspace_path {
+        Some(workspace_path) => set_default_workspace(&bridge, workspace_path).await?,
+        None => {
+            let mut bridge_guard = bridge.lock().await;
+            let workspace = bridge_guard.resolve_workspace(None, None);
+            ensure_bridge(&mut bridge_guard, &workspace).await?;
+        }
+    }
+
+    let failed_obligations_state = Arc::new(Mutex::new(FailedObligationsState::new()));
     let server = McpServer::builder("rust-analyzer-mcp".to_string())
         .instructions(indoc::indoc! {"
             Rust analyzer LSP integration for code analysis, navigation, and diagnostics.
         "})
-        .tool_fn_mut(
+        .direct_tool_fn(
             "rust_analyzer_hover",
-            "Get hover information for a symbol at a specific position in a Rust file",
+            "Get hover information for a symbol at a specific position in a Rust file. Positions \
+             are 0-based unless `one_based` is set. Set `structured` to also get the signature, \
+             docs and defining crate as separate fields.",
+            {
+                let bridge = bridge.clone();
+                async move |input: HoverInputs, _mcp_cx| {
+                    let position = input.position.checked_position(&bridge).await?;
+                    with_bridge_and_cached_document(
+                        &bridge,
+                        input.position.workspace_path.as_deref(),
+                        &input.position.file_path,
+                        input.position.content.clone(),
+                        async move |client, cache, uri| {
+                            const METHOD: &str = "textDocument/hover";
+                            if let Some(unsupported) = unsupported(client, METHOD) {
+                                return Ok(unsupported);
+                            }
+                            let cached: Option<Hover> = cache.get(METHOD, position);
+                            let result = match cached {
+                                Some(hover) => Some(hover),
+                                None => {
+                                    let result =
+                                        client.hover(uri.clone(), position).await.map_err(|e| {
+                                            ToolError::request_failed("Hover request failed", e)
+                                        })?;
+                                    if let Some(hover) = &result {
+                                        cache.insert(METHOD, position, hover);
+                                    }
+                                    result
+                                }
+                            };
+                            let summary = if input.structured {
+                                result.as_ref().map(summarize_hover)
+                            } else {
+                                None
+                            };
+                            Ok(ToolOutput::ready(HoverResult {
+                                hover: result,
+                                summary,
+                            }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_definition",
+            "Go to definition of a symbol at a specific position, following re-exports to the \
+             underlying item. Positions are 0-based unless `one_based` is set. Instead of a \
+             position, you can pass `symbol_name` and optionally `occurrence`.",
+            {
+                let bridge = bridge.clone();
+                async move |input: NavigationInputs, _mcp_cx| {
+                    let include_preview = input.include_preview;
+                    let (file_path, encoding, source) = resolve_file_encoding(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                    )
+                    .await?;
+                    let position = input.position(&*source, &file_path, encoding)?;
+                    with_bridge_and_cached_document(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, cache, uri| {
+                            const METHOD: &str = "textDocument/definition";
+                            if let Some(unsupported) = unsupported(client, METHOD) {
+                                return Ok(unsupported);
+                            }
+                            let cached: Option<GotoDefinitionResponse> =
+                                cache.get(METHOD, position);
+                            let result = match cached {
+                                Some(result) => result,
+                                None => {
+                                    let result = retry_while_indexing(
+                                        client,
+                                        || client.goto_definition(uri.clone(), position),
+                                        goto_response_is_empty,
+                                    )
+                                    .await
+                                    .map_err(|e| {
+                                        ToolError::request_failed("Definition request failed", e)
+                                    })?;
+                                    match result {
+                                        Ok(result) => {
+                                            cache.insert(METHOD, position, &result);
+                                            result
+                                        }
+                                        Err(empty) => return Ok(empty.into()),
+                                    }
+                                }
+                            };
+                            Ok(ToolOutput::ready(goto_result(
+                                result,
+                                include_preview,
+                                &*source,
+                            )))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_definition_source",
+            "Go to definition of a symbol and return the source of the item defining it, such as \
+             a function's whole body, along with its location. Saves reading the target file \
+             after `rust_analyzer_definition`. At most `max_lines` lines are returned (200 by \
+             default). Positions are 0-based unless `one_based` is set. Instead of a position, \
+             you can pass `symbol_name` and optionally `occurrence`.",
+            {
+                let bridge = bridge.clone();
+                async move |input: DefinitionSourceInputs, _mcp_cx| {
+                    let navigation = input.navigation;
+                    let max_lines = input.max_lines.unwrap_or(DEFAULT_DEFINITION_SOURCE_LINES);
+                    let (file_path, encoding, source) = resolve_file_encoding(
+                        &bridge,
+                        navigation.workspace_path.as_deref(),
+                        &navigation.file_path,
+                    )
+                    .await?;
+                    let position = navigation.position(&*source, &file_path, encoding)?;
+                    let content = navigation.content.clone();
+                    with_bridge_and_document_content(
+                        &bridge,
+                        navigation.workspace_path.as_deref(),
+                        &navigation.file_path,
+                        navigation.content,
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/definition")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result = retry_while_indexing(
+                                client,
+                                || client.goto_definition(uri.clone(), position),
+                                goto_response_is_empty,
+                            )
+                            .await
+                            .map_err(|e| {
+                                ToolError::request_failed("Definition request failed", e)
+                            })?;
+                            let location = match result {
+                                Ok(result) => goto_response_locations(result).remove(0),
+                                Err(empty) => return Ok(empty.into()),
+                            };
+                            let text = match content {
+                                Some(content) if location.uri == uri => content,
+                                _ => uri_to_path(&location.uri)
+                                    .and_then(|path| source.read(&path).ok())
+                                    .ok_or_else(|| {
+                                        ToolError::FileNotFound(format!(
+                                            "Can't read the definition's file {}",
+                                            location.uri.as_str()
+                                        ))
+                                    })?,
+                            };
+                            let symbols = client
+                                .document_symbols(location.uri.clone())
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Document symbols request failed", e)
+                                })?;
+                            let item = symbols.and_then(|symbols| {
+                                enclosing_items(symbols, location.range.start).pop()
+                            });
+                            let range = item.as_ref().map_or(location.range, |item| item.range);
+                            let (source, truncated) = source_lines(&text, range, max_lines);
+                            Ok(ToolOutput::ready(DefinitionSourceResult {
+                                start_line: range.start.line + 1,
+                                location,
+                                item,
+                                source,
+                                truncated,
+                            }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_declaration",
+            "Go to the declaration of a symbol at a specific position. Unlike definition, this \
+             stops at re-exports (`pub use`) and `extern` declarations instead of jumping to the \
+             underlying item. Positions are 0-based unless `one_based` is set. Instead of a \
+             position, you can pass `symbol_name` and optionally `occurrence`.",
+            {
+                let bridge = bridge.clone();
+                async move |input: NavigationInputs, _mcp_cx| {
+                    let include_preview = input.include_preview;
+                    let (file_path, encoding, source) = resolve_file_encoding(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                    )
+                    .await?;
+                    let position = input.position(&*source, &file_path, encoding)?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/declaration")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result = retry_while_indexing(
+                                client,
+                                || client.go_to_declaration(uri.clone(), position),
+                                goto_response_is_empty,
+                            )
+                            .await
+                            .map_err(|e| {
+                                ToolError::request_failed("Declaration request failed", e)
+                            })?;
+                            let result = match result {
+                                Ok(result) => result,
+                                Err(empty) => return Ok(empty.into()),
+                            };
+                            Ok(ToolOutput::ready(goto_result(
+                                result,
+                                include_preview,
+                                &*source,
+                            )))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_references",
+            "Find all references to a symbol at a specific position. Positions are 0-based \
+             unless `one_based` is set. Instead of a position, you can pass `symbol_name` and \
+             optionally `occurrence`. Returns the first 500 by default; page through more with \
+             `offset` and `limit`.",
+            {
+                let bridge = bridge.clone();
+                async move |input: ReferencesInputs, _mcp_cx| {
+                    let group_by_file = input.group_by_file;
+                    let page = input.page;
+                    let input = input.navigation;
+                    let include_preview = input.include_preview;
+                    let (file_path, encoding, source) = resolve_file_encoding(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                    )
+                    .await?;
+                    let position = input.position(&*source, &file_path, encoding)?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/references")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result = retry_while_indexing(
+                                client,
+                                || client.find_references(uri.clone(), position, true),
+                                Vec::is_empty,
+                            )
+                            .await
+                            .map_err(|e| {
+                                ToolError::request_failed("References request failed", e)
+                            })?;
+                            let mut result = match result {
+                                Ok(result) => result,
+                                Err(empty) => return Ok(empty.into()),
+                            };
+                            result.sort_by_key(|location| {
+                                let start = location.range.start;
+                                (location.uri.to_string(), start.line, start.character)
+                            });
+                            let (result, page) = page.page(result, Some(DEFAULT_REFERENCES_LIMIT));
+                            let mut references = ReferencesResult::empty(page);
+                            if group_by_file {
+                                references.files =
+                                    Some(group_references(client, result).await.map_err(|e| {
+                                        ToolError::request_failed(
+                                            "Document highlight request failed",
+                                            e,
+                                        )
+                                    })?);
+                            } else if include_preview {
+                                references.previews = Some(preview_locations(result, &*source));
+                            } else {
+                                references.references = Some(result);
+                            }
+                            Ok(ToolOutput::ready(references))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_batch",
+            "Run many hover, definition, declaration or references lookups in one call. Takes a \
+             list of `{ tool, file_path, line, character }` requests, runs them concurrently and \
+             returns one `{ \"result\": ... }`, `{ \"error\": ... }` or `{ \"unsupported\": ... \
+             }` per request, in order. Each request is validated like the matching single-call \
+             tool. Positions are 0-based unless `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: BatchInputs, _mcp_cx| run_batch(&bridge, input).await
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_type_hierarchy",
+            "Get the direct supertypes and subtypes of the type or trait at a position, e.g. the \
+             types implementing a trait, with each item's name, kind and location. Positions are \
+             0-based unless `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/prepareTypeHierarchy")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let items = client
+                                .prepare_type_hierarchy(uri, position)
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Type hierarchy request failed", e)
+                                })?;
+                            let mut hierarchies = Vec::with_capacity(items.len());
+                            for item in items {
+                                let supertypes = client
+                                    .type_hierarchy_supertypes(item.clone())
+                                    .await
+                                    .map_err(|e| {
+                                        ToolError::request_failed("Supertypes request failed", e)
+                                    })?;
+                                let subtypes =
+                                    client.type_hierarchy_subtypes(item.clone()).await.map_err(
+                                        |e| ToolError::request_failed("Subtypes request failed", e),
+                                    )?;
+                                hierarchies.push(TypeHierarchy {
+                                    item: item.into(),
+                                    supertypes: supertypes.into_iter().map(Into::into).collect(),
+                                    subtypes: subtypes.into_iter().map(Into::into).collect(),
+                                });
+                            }
+                            Ok(ToolOutput::ready(TypeHierarchyResult { hierarchies }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_completion",
+            "Get code completions at a specific position. Positions are 0-based unless \
+             `one_based` is set. Right after typing `.`, `::` or `'`, pass the character as \
+             `trigger_character` to get member, path or lifetime completions. Returns the 200 \
+             best ranked by default; page through more with `offset` and `limit`.",
+            {
+                let bridge = bridge.clone();
+                async move |input: CompletionInputs, _mcp_cx| {
+                    let context = input.context().map_err(ToolError::invalid_input)?;
+                    let page = PageInputs {
+                        offset: input.page.offset,
+                        limit: input.page.limit.or(input.max_items),
+                    };
+                    let prefix_filter = input.prefix_filter;
+                    let input = input.position;
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/completion")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result =
+                                client
+                                    .completion(uri, position, context)
+                                    .await
+                                    .map_err(|e| {
+                                        ToolError::request_failed("Completion request failed", e)
+                                    })?;
+                            let (completions, page) = match result {
+                                Some(result) => {
+                                    let (result, page) = filter_completions(
+                                        result,
+                                        prefix_filter.as_deref(),
+                                        &page,
+                                        Some(DEFAULT_COMPLETION_LIMIT),
+                                    );
+                                    (Some(result), Some(page))
+                                }
+                                None => (None, None),
+                            };
+                            Ok(ToolOutput::ready(CompletionResult { completions, page }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_symbols",
+            "Get document symbols for a Rust file. Set `flatten` to get a flat list with \
+             container paths like `Type::method`",
+            {
+                let bridge = bridge.clone();
+                async move |input: SymbolsInputs, _mcp_cx| {
+                    let flatten = input.flatten;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/documentSymbol")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result = client.document_symbols(uri).await.map_err(|e| {
+                                ToolError::request_failed("Document symbols request failed", e)
+                            })?;
+                            let result = if flatten {
+                                SymbolsResult {
+                                    symbols: None,
+                                    flat_symbols: Some(
+                                        result.map(flatten_document_symbols).unwrap_or_default(),
+                                    ),
+                                }
+                            } else {
+                                SymbolsResult {
+                                    symbols: result,
+                                    flat_symbols: None,
+                                }
+                            };
+                            Ok(ToolOutput::ready(result))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_find_symbol",
+            "Find symbols by name (or `Type::method` path) in a Rust file, returning every match \
+             with its 1-based line/column and a short code snippet",
+            {
+                let bridge = bridge.clone();
+                async move |input: FindSymbolInputs, _mcp_cx| {
+                    let file_path = resolve_file_path(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                    )
+                    .await?;
+                    let source = bridge_document_source(&bridge).await;
+                    let text = document_text(&*source, &file_path, input.content.as_deref())?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/documentSymbol")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result = client.document_symbols(uri).await.map_err(|e| {
+                                ToolError::request_failed("Document symbols request failed", e)
+                            })?;
+                            let symbols = result.map(flatten_document_symbols).unwrap_or_default();
+                            let matches = find_symbols(symbols, &input.name, &text);
+                            Ok(ToolOutput::ready(FindSymbolResult { matches }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_list_symbols_by_kind",
+            "List the symbols of one kind across the workspace, e.g. every struct, trait or \
+             function, with their locations. `kind` is one of \"struct\", \"enum\", \"variant\", \
+             \"trait\", \"function\", \"method\", \"field\", \"const\", \"type\" or \"module\"; \
+             `query` narrows by name. Results are paged with `offset` and `limit` (default 100)",
+            {
+                let bridge = bridge.clone();
+                async move |input: ListSymbolsByKindInputs, _mcp_cx| {
+                    let kind = parse_symbol_kind(&input.kind).map_err(ToolError::invalid_input)?;
+                    with_bridge(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        async move |client| {
+                            if let Some(unsupported) = unsupported(client, "workspace/symbol") {
+                                return Ok(unsupported);
+                            }
+                            let response = client
+                                .search_workspace_symbols(
+                                    input.query.as_deref().unwrap_or_default(),
+                                )
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Workspace symbol request failed", e)
+                                })?;
+                            let symbols = response
+                                .map(|response| workspace_symbols_of_kind(response, kind))
+                                .unwrap_or_default();
+                            let (symbols, page) =
+                                input.page.page(symbols, Some(DEFAULT_SYMBOLS_LIMIT));
+                            Ok(ToolOutput::ready(ListSymbolsByKindResult { symbols, page }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_public_api",
+            "Summarize a Rust file's public API: its `pub` functions, types, public fields, \
+             traits and their items, each with its path like `Person::new`, its signature and \
+             its 1-based line. Set `include_private` to list private items too",
+            {
+                let bridge = bridge.clone();
+                async move |input: PublicApiInputs, _mcp_cx| {
+                    let (file_path, encoding, source) = resolve_file_encoding(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                    )
+                    .await?;
+                    let text = document_text(&*source, &file_path, input.content.as_deref())?;
+                    let include_private = input.include_private;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/documentSymbol")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result =
+                                client.document_symbols(uri.clone()).await.map_err(|e| {
+                                    ToolError::request_failed("Document symbols request failed", e)
+                                })?;
+                            let mut symbols = result
+                                .map(|result| api_symbols(result, &text, encoding))
+                                .unwrap_or_default();
+                            if !include_private {
+                                symbols.retain(|symbol| symbol.public);
+                            }
+                            let items = api_items(client, &uri, symbols).await.map_err(|e| {
+                                ToolError::request_failed("Hover request failed", e)
+                            })?;
+                            Ok(ToolOutput::ready(PublicApiResult { items }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_file_metrics",
+            "Get cheap structural metrics for a Rust file: how many functions, structs, enums, \
+             traits and impls it declares, its total and comment lines, and its longest function \
+             as a hint at where the complexity is. Useful for deciding which files to look at \
+             first",
+            {
+                let bridge = bridge.clone();
+                async move |input: FileOnlyInputs, _mcp_cx| {
+                    let file_path = resolve_file_path(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                    )
+                    .await?;
+                    let source = bridge_document_source(&bridge).await;
+                    let text = document_text(&*source, &file_path, input.content.as_deref())?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content,
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/documentSymbol")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result = client.document_symbols(uri).await.map_err(|e| {
+                                ToolError::request_failed("Document symbols request failed", e)
+                            })?;
+                            let symbols =
+                                result.unwrap_or(DocumentSymbolResponse::Nested(Vec::new()));
+                            Ok(ToolOutput::ready(file_metrics(symbols, &text)))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_symbol_path",
+            "Get the fully qualified path of the symbol at a position, e.g. \
+             `my_crate::module::Type::method`, for writing `use` declarations and doc links. The \
+             path is null, with a `reason`, for local variables and other symbols without one. \
+             Positions are 0-based unless `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) = unsupported(client, "textDocument/hover") {
+                                return Ok(unsupported);
+                            }
+                            let hover = client.hover(uri, position).await.map_err(|e| {
+                                ToolError::request_failed("Hover request failed", e)
+                            })?;
+                            let path = match hover {
+                                Some(hover) => qualified_path(&hover),
+                                None => Err("There is no symbol at this position".to_string()),
+                            };
+                            Ok(ToolOutput::ready(match path {
+                                Ok(path) => SymbolPathResult {
+                                    path: Some(path),
+                                    reason: None,
+                                },
+                                Err(reason) => SymbolPathResult {
+                                    path: None,
+                                    reason: Some(reason),
+                                },
+                            }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_resolve_doc_link",
+            "Resolve the intra-doc link at a position in a `///` or `//!` comment, like \
+             `[Person::greet]` or `[text](Person::greet)`, to where its target is declared and \
+             its fully qualified path. An unresolved link gets a null location and a `reason`, \
+             which helps check links while writing docs. Positions are 0-based unless \
+             `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let (file_path, encoding, source) = resolve_file_encoding(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                    )
+                    .await?;
+                    let position = input.position();
+                    check_positions(
+                        &*source,
+                        &file_path,
+                        input.content.as_deref(),
+                        &[position],
+                        input.one_based,
+                        encoding,
+                    )?;
+                    let text = document_text(&*source, &file_path, input.content.as_deref())?;
+                    let target = doc_link_at(&text, position, encoding).ok_or_else(|| {
+                        anyhow!(
+                            "There is no intra-doc link at {}:{} of {}",
+                            input.line,
+                            input.character,
+                            input.file_path
+                        )
+                    })?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/definition")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let location = client
+                                .goto_definition(uri.clone(), position)
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Definition request failed", e)
+                                })?
+                                .map(goto_response_locations)
+                                .and_then(|locations| locations.into_iter().next());
+                            let Some(location) = location else {
+                                return Ok(ToolOutput::ready(DocLinkResult {
+                                    reason: Some(format!(
+                                        "`{}` doesn't resolve to an item",
+                                        target
+                                    )),
+                                    target,
+                                    location: None,
+                                    path: None,
+                                }));
+                            };
+                            let hover = client.hover(uri, position).await.map_err(|e| {
+                                ToolError::request_failed("Hover request failed", e)
+                            })?;
+                            let path = match hover {
+                                Some(hover) => qualified_path(&hover),
+                                None => Err("rust-analyzer has no hover for the link".to_string()),
+                            };
+                            let (path, reason) = match path {
+                                Ok(path) => (Some(path), None),
+                                Err(reason) => (None, Some(reason)),
+                            };
+                            Ok(ToolOutput::ready(DocLinkResult {
+                                target,
+                                location: Some(location),
+                                path,
+                                reason,
+                            }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_type_at",
+            "Get just the inferred type of the variable, parameter, field, constant or literal \
+             at a position, e.g. `i32` for `sum` in `let sum = calculate_sum(&numbers)`. The \
+             type is null, with a `reason`, on functions, types and other symbols that aren't \
+             values. Positions are 0-based unless `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) = unsupported(client, "textDocument/hover") {
+                                return Ok(unsupported);
+                            }
+                            let hover = client.hover(uri, position).await.map_err(|e| {
+                                ToolError::request_failed("Hover request failed", e)
+                            })?;
+                            let type_name = match hover {
+                                Some(hover) => value_type(&hover),
+                                None => Err("There is no expression at this position".to_string()),
+                            };
+                            Ok(ToolOutput::ready(match type_name {
+                                Ok(type_name) => TypeAtResult {
+                                    type_name: Some(type_name),
+                                    reason: None,
+                                },
+                                Err(reason) => TypeAtResult {
+                                    type_name: None,
+                                    reason: Some(reason),
+                                },
+                            }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_enclosing_item",
+            "Get the items (impls, functions, structs, ...) that contain a position, outermost \
+             first, with each one's kind, full range and, for functions, signature. Positions \
+             are 0-based unless `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/documentSymbol")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result = client.document_symbols(uri).await.map_err(|e| {
+                                ToolError::request_failed("Document symbols request failed", e)
+                            })?;
+                            let items = result
+                                .map(|symbols| enclosing_items(symbols, position))
+                                .unwrap_or_default();
+                            Ok(ToolOutput::ready(EnclosingItemResult { items }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_describe",
+            "Get everything about the symbol at a position in one call: its hover, definition, \
+             type definition and implementations. The lookups run concurrently, and each part \
+             holds its `result`, its own `error`, or `unsupported` with the feature if the \
+             server lacks it. Positions are 0-based unless `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            Ok(ToolOutput::ready(describe(client, uri, position).await))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_trait_impls",
+            "Find every impl of a trait by name, with the implementing type's name and the \
+             impl's location. The trait may be declared in `file_path` or anywhere else in the \
+             workspace",
+            {
+                let bridge = bridge.clone();
+                async move |input: TraitImplsInputs, _mcp_cx| {
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/implementation")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let trait_location = find_trait(client, &uri, &input.name)
+                                .await
+                                .map_err(|e| ToolError::request_failed("Symbol request failed", e))?
+                                .ok_or_else(|| {
+                                    anyhow!(
+                                        "Trait `{}` not found in {} or the workspace",
+                                        input.name,
+                                        input.file_path
+                                    )
+                                })?;
+                            let impls =
+                                trait_impls(client, &trait_location).await.map_err(|e| {
+                                    ToolError::request_failed("Implementation request failed", e)
+                                })?;
+                            Ok(ToolOutput::ready(TraitImplsResult {
+                                trait_location,
+                                impls,
+                            }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_folding_ranges",
+            "Get folding ranges (imports, comments, regions, blocks) for a Rust file",
+            {
+                let bridge = bridge.clone();
+                async move |input: FileOnlyInputs, _mcp_cx| {
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/foldingRange")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result = client.folding_ranges(uri).await.map_err(|e| {
+                                ToolError::request_failed("Folding range request failed", e)
+                            })?;
+                            Ok(ToolOutput::ready(FoldingRangesResult { ranges: result }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_selection_range",
+            "Get the expanding selection ranges at a position, innermost (token) to outermost \
+             (item), e.g. to grab the enclosing expression or function. Positions are 0-based \
+             unless `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/selectionRange")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result = client
+                                .selection_ranges(uri, vec![position])
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Selection range request failed", e)
+                                })?;
+                            let ranges = result
+                                .and_then(|ranges| ranges.into_iter().next())
+                                .map(selection_range_chain)
+                                .unwrap_or_default();
+                            Ok(ToolOutput::ready(RangesResult { ranges }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_semantic_tokens",
+            "Get semantic tokens for a Rust file, with absolute 0-based positions and token \
+             type/modifier names (e.g. mutable, unsafe)",
+            {
+                let bridge = bridge.clone();
+                async move |input: FileOnlyInputs, _mcp_cx| {
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/semanticTokens/full")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result =
+                                client.decoded_semantic_tokens(uri).await.map_err(|e| {
+                                    ToolError::request_failed("Semantic tokens request failed", e)
+                                })?;
+                            Ok(ToolOutput::ready(SemanticTokensResult { tokens: result }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_workspace_diagnostics",
+            "Get diagnostics for every file in the workspace with per-file breakdown and \
+             error/warning/hint totals",
+            {
+                let bridge = bridge.clone();
+                async move |input: EmptyInputs, _mcp_cx| {
+                    with_bridge(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        async move |client| {
+                            let diagnostics =
+                                client.workspace_diagnostics().await.map_err(|e| {
+                                    ToolError::request_failed(
+                                        "Workspace diagnostics request failed",
+                                        e,
+                                    )
+                                })?;
+                            Ok(ToolOutput::ready(summarize_workspace_diagnostics(
+                                diagnostics,
+                            )))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_list_files",
+            "List the Rust source files of the workspace with their sizes, optionally under a \
+             subdirectory. Skips `target`, hidden directories and whatever `.gitignore` files \
+             exclude. Returns at most `max_files` (default 500) along with the total",
+            {
+                let bridge = bridge.clone();
+                async move |input: ListFilesInputs, _mcp_cx| {
+                    let workspace = bridge
+                        .lock()
+                        .await
+                        .resolve_workspace(input.workspace_path.as_deref(), None);
+                    let dir = match &input.directory {
+                        Some(directory) => {
+                            let dir = workspace.join(directory);
+                            if !canonical_workspace(&dir).starts_with(&workspace) {
+                                return Err(ToolError::InvalidInput(format!(
+                                    "{} is outside the workspace {}",
+                                    directory,
+                                    workspace.display()
+                                ))
+                                .into());
+                            }
+                            dir
+                        }
+                        None => workspace.clone(),
+                    };
+                    let mut files = Vec::new();
+                    walk_files(&workspace, &dir, &mut |_, relative, size| {
+                        if relative.ends_with(".rs") {
+                            files.push(WorkspaceFile {
+                                path: relative.to_string(),
+                                size,
+                            });
+                        }
+                    });
+                    files.sort_by(|a, b| a.path.cmp(&b.path));
+                    let total = files.len();
+                    files.truncate(input.max_files.unwrap_or(DEFAULT_LIST_FILES_MAX));
+                    Ok(ListFilesResult { files, total })
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_diagnostics_glob",
+            "Get diagnostics for the files matching a glob relative to the workspace, e.g. \
+             `src/**/*.rs`, with a per-file breakdown and totals. Checks at most `limit` files \
+             (default 50, starting at `offset`) and returns what it has after `timeout_secs` \
+             (default 30)",
+            {
+                let bridge = bridge.clone();
+                async move |input: DiagnosticsGlobInputs, _mcp_cx| {
+                    run_diagnostics_glob(&bridge, input).await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_code_lens",
+            "Get the code lenses for a Rust file (run/debug commands for tests and binaries, \
+             reference and implementation counts), resolved with their commands",
+            {
+                let bridge = bridge.clone();
+                async move |input: FileOnlyInputs, _mcp_cx| {
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) = unsupported(client, "textDocument/codeLens")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let lenses = client.code_lens(uri).await.map_err(|e| {
+                                ToolError::request_failed("Code lens request failed", e)
+                            })?;
+                            let mut resolved = Vec::with_capacity(lenses.len());
+                            for lens in lenses {
+                                if lens.command.is_some() || !client.supports("codeLens/resolve") {
+                                    resolved.push(lens);
+                                    continue;
+                                }
+                                let lens = client.resolve_code_lens(lens).await.map_err(|e| {
+                                    ToolError::request_failed("Code lens resolve request failed", e)
+                                })?;
+                                resolved.push(lens);
+                            }
+                            Ok(ToolOutput::ready(CodeLensResult { lenses: resolved }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_document_links",
+            "Get the links in a Rust file (e.g. `include_str!` paths and URLs in doc comments) \
+             with their targets and ranges",
+            {
+                let bridge = bridge.clone();
+                async move |input: FileOnlyInputs, _mcp_cx| {
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/documentLink")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let links = client.document_links(uri).await.map_err(|e| {
+                                ToolError::request_failed("Document links request failed", e)
+                            })?;
+                            let mut resolved = Vec::with_capacity(links.len());
+                            for link in links {
+                                if link.target.is_some() || !client.supports("documentLink/resolve")
+                                {
+                                    resolved.push(link);
+                                    continue;
+                                }
+                                let link =
+                                    client.resolve_document_link(link).await.map_err(|e| {
+                                        ToolError::request_failed(
+                                            "Document link resolve request failed",
+                                            e,
+                                        )
+                                    })?;
+                                resolved.push(link);
+                            }
+                            Ok(ToolOutput::ready(DocumentLinksResult { links: resolved }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_diagnostics_for_content",
+            "Check a proposed edit without writing it: analyze the given full text for a Rust \
+             file and return the diagnostics it would produce, then restore what the server saw \
+             before: unsaved text sent earlier with `content`, or the file on disk. Only \
+             rust-analyzer's own diagnostics are included, since cargo check needs the file \
+             saved",
+            {
+                let bridge = bridge.clone();
+                async move |input: ContentDiagnosticsInputs, _mcp_cx| {
+                    with_hypothetical_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content,
+                        |client, uri| async move {
+                            if let Some(unsupported) =
+                                unsupported(&client, "textDocument/diagnostic")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let report = client.diagnostics(uri).await.map_err(|e| {
+                                ToolError::request_failed("Diagnostics request failed", e)
+                            })?;
+                            let diagnostics = match report {
+                                Some(DocumentDiagnosticReport::Full(report)) => {
+                                    report.full_document_diagnostic_report.items
+                                }
+                                _ => vec![],
+                            };
+                            Ok(ToolOutput::ready(FileDiagnostics::new(diagnostics)))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_explain_diagnostic",
+            "Explain one diagnostic in a Rust file in a single call: the diagnostic itself, the \
+             hover at its start, and the code actions (with edits) offered to fix it. Select it \
+             by `index` or by a position inside it",
+            {
+                let bridge = bridge.clone();
+                async move |input: ExplainDiagnosticInputs, _mcp_cx| {
+                    let selection = &input;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            let diagnostics = client
+                                .document_diagnostics(uri.clone())
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Diagnostics request failed", e)
+                                })?;
+                            let diagnostic = select_diagnostic(&diagnostics, selection)?;
+
+                            let hover = client
+                                .hover(uri.clone(), diagnostic.range.start)
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Hover request failed", e)
+                                })?;
+
+                            // We don't advertise `resolveSupport`, so rust-analyzer fills in
+                            // each action's edit up front.
+                            let context = CodeActionContext {
+                                diagnostics: vec![diagnostic.clone()],
+                                only: None,
+                                trigger_kind: None,
+                            };
+                            let code_actions = client
+                                .code_actions(uri, diagnostic.range, context)
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Code actions request failed", e)
+                                })?
+                                .unwrap_or_default();
+
+                            let error_code_explanation = match &diagnostic.code {
+                                Some(NumberOrString::String(code))
+                                    if parse_error_code(code).is_ok() =>
+                                {
+                                    explain_error_code(code).await.ok()
+                                }
+                                _ => None,
+                            };
+
+                            Ok(ToolOutput::ready(DiagnosticExplanation {
+                                diagnostic,
+                                hover,
+                                code_actions,
+                                error_code_explanation,
+                            }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_explain_error_code",
+            "Get the long explanation of a rustc error code like `E0425`, as printed by `rustc \
+             --explain`, with examples of the error and how to fix it. Diagnostics carry the \
+             code in their `code` field",
+            async move |input: ExplainErrorCodeInputs, _mcp_cx| {
+                let code = parse_error_code(&input.code).map_err(ToolError::invalid_input)?;
+                let explanation = explain_error_code(&code).await?;
+                Ok(ErrorCodeExplanation { code, explanation })
+            },
+            sacp::tool_fn!(),
+        )
+        /*
+        .direct_tool_fn(
+            "rust_analyzer_format",
+            "Format a Rust document",
+            {
+                let bridge = bridge.clone();
+                async move |input: FileOnlyInputs, _mcp_cx| {
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            let result = client
+                                .format_document(uri)
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Format request failed", e)
+                                })?;
+                            Ok(serde_json::to_string(&result)?)
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_code_actions",
+            "Get available code actions for a range in a Rust file",
+            {
+                let bridge = bridge.clone();
+                async move |input: RangeInputs, _mcp_cx| {
+                    let range = input.checked_range(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            let context = CodeActionContext {
+                                diagnostics: vec![],
+                                only: None,
+                                trigger_kind: None,
+                            };
+                            let result = client
+                                .code_actions(uri, range, context)
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Code actions request failed", e)
+                                })?;
+                            Ok(serde_json::to_string(&result)?)
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        */
+        .direct_tool_fn(
+            "rust_analyzer_set_workspace",
+            "Set the workspace root for rust-analyzer",
+            {
+                let bridge = bridge.clone();
+                async move |input: WorkspaceInputs, _mcp_cx| {
+                    set_default_workspace(&bridge, &input.workspace_path).await?;
+                    Ok(MessageResult {
+                        message: "Workspace set successfully".to_string(),
+                    })
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_workspace_folders",
+            "Add or remove folders analyzed by the workspace's rust-analyzer besides its root, \
+             e.g. unrelated crates of a monorepo, without restarting it. Files in these folders \
+             are routed to that server. Returns the folders now analyzed; pass nothing to just \
+             list them",
+            {
+                let bridge = bridge.clone();
+                async move |input: WorkspaceFoldersInputs, _mcp_cx| {
+                    let (root, folders) = change_workspace_folders(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.add,
+                        &input.remove,
+                    )
+                    .await?;
+                    Ok(WorkspaceFoldersResult { root, folders })
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_diagnostics",
+            "Get diagnostics for a Rust file, both rust-analyzer's own and the latest cargo \
+             check results, with counts by severity. Each diagnostic carries its related \
+             locations (`relatedInformation`) and whether a quick fix is available \
+             (`has_quickfix`). Set `include_context` to also get the affected source lines with \
+             the range underlined",
+            {
+                let bridge = bridge.clone();
+                async move |input: DiagnosticsInputs, _mcp_cx| {
+                    let min_severity = input
+                        .min_severity
+                        .as_deref()
+                        .map(parse_severity)
+                        .transpose()
+                        .map_err(ToolError::invalid_input)?;
+                    let content = input.content.clone();
+                    let source = bridge_document_source(&bridge).await;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content,
+                        async move |client, uri| {
+                            let path = uri_to_path(&uri)
+                                .ok_or_else(|| anyhow!("Not a file URI: {}", uri.as_str()))?;
+                            let diagnostics = client
+                                .document_diagnostics(uri.clone())
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Diagnostics request failed", e)
+                                })?;
+                            let mut report = FileDiagnostics::new(diagnostics);
+                            if let Some(min_severity) = min_severity {
+                                report.retain_min_severity(min_severity);
+                            }
+                            let text = if input.include_context {
+                                Some(document_text(&*source, &path, content.as_deref())?)
+                            } else {
+                                None
+                            };
+
+                            let mut details = Vec::with_capacity(report.diagnostics.len());
+                            for diagnostic in report.diagnostics {
+                                let has_quickfix =
+                                    has_quickfix(client, &uri, &diagnostic).await.map_err(|e| {
+                                        ToolError::request_failed("Code actions request failed", e)
+                                    })?;
+                                details.push(DiagnosticDetails {
+                                    context: text.as_deref().map(|text| {
+                                        source_context(
+                                            text,
+                                            &diagnostic.range,
+                                            client.position_encoding(),
+                                        )
+                                    }),
+                                    diagnostic,
+                                    has_quickfix,
+                                });
+                            }
+                            Ok(ToolOutput::ready(FileDiagnostics {
+                                counts: report.counts,
+                                diagnostics: details,
+                            }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_failed_obligations",
+            "Get failed trait obligations for debugging (rust-analyzer specific). Positions are \
+             0-based unless `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                let state = failed_obligations_state.clone();
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let state = state.clone();
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            let args = TextDocumentPositionParams {
+                                text_document: TextDocumentIdentifier { uri },
+                                position,
+                            };
+
+                            let mut state = state.lock().await;
+                            let goals = handle_failed_obligations(client, &mut state, args).await?;
+
+                            Ok(ToolOutput::ready(FailedObligationsResult { goals }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_failed_obligations_goal",
+            "Explore nested goals in failed trait obligations (rust-analyzer specific)",
+            {
+                let bridge = bridge.clone();
+                let state = failed_obligations_state.clone();
+                async move |input: GoalIndexInputs, _mcp_cx| {
+                    let state = state.clone();
+                    with_bridge(&bridge, None, async move |client| {
+                        let mut state = state.lock().await;
+                        let goals =
+                            handle_failed_obligations_goal(client, &mut state, input).await?;
+
+                        Ok(ToolOutput::ready(GoalResult { goals }))
+                    })
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_runnables",
+            "List runnable tests, benches and binaries in a Rust file (optionally at a position) \
+             with the exact cargo arguments to run them",
+            {
+                let bridge = bridge.clone();
+                async move |input: FileOptionalPositionInputs, _mcp_cx| {
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            let position = input
+                                .line
+                                .zip(input.character)
+                                .map(|(line, character)| Position::new(line, character));
+                            let result = client.runnables(uri, position).await.map_err(|e| {
+                                ToolError::request_failed("Runnables request failed", e)
+                            })?;
+                            Ok(ToolOutput::ready(RunnablesResult { runnables: result }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_run_test",
+            "Run a test, bench or binary and return its exit code and the end of its output. \
+             Pick it by the `label` from `rust_analyzer_runnables`, or by a position inside it. \
+             Only the cargo commands rust-analyzer reports for the file are run, never arbitrary \
+             ones",
+            {
+                let bridge = bridge.clone();
+                async move |input: RunTestInputs, _mcp_cx| {
+                    let position = match (input.line, input.character) {
+                        (Some(line), Some(character)) => {
+                            Some(lsp_position(line, character, input.one_based))
+                        }
+                        _ if input.label.is_some() => None,
+                        _ => {
+                            return Err(
+                                anyhow!("Pass a `label` or a `line` and `character`").into()
+                            );
+                        }
+                    };
+                    // Find the runnable under the bridge lock, but run it outside, so other
+                    // tools aren't blocked while it runs.
+                    let found = with_bridge_and_document(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        async |client, uri| {
+                            let runnables = client
+                                .runnables(uri, position.filter(|_| input.label.is_none()))
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Runnables request failed", e)
+                                })?;
+                            Ok(ToolOutput::ready(select_runnable(
+                                runnables,
+                                input.label.as_deref(),
+                            )?))
+                        },
+                    )
+                    .await?;
+                    let Some(runnable) = found.result else {
+                        return Ok(found.without_result());
+                    };
+                    let command = runnable_command(&runnable)?;
+                    let output = run_command(
+                        &command,
+                        input.max_output_bytes.unwrap_or(DEFAULT_RUN_OUTPUT_BYTES),
+                        input
+                            .timeout_secs
+                            .map_or(DEFAULT_RUN_TIMEOUT, Duration::from_secs),
+                    )
+                    .await?;
+                    Ok(ToolOutput::ready(RunTestResult {
+                        label: runnable.label,
+                        output,
+                    }))
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_related_tests",
+            "Find tests related to the symbol at a position, to know which tests to run after \
+             editing it. Positions are 0-based unless `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            let result =
+                                client.related_tests(uri, position).await.map_err(|e| {
+                                    ToolError::request_failed("Related tests request failed", e)
+                                })?;
+                            Ok(ToolOutput::ready(RelatedTestsResult { tests: result }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_open_cargo_toml",
+            "Find the Cargo.toml of the package that contains a file, e.g. to add a dependency \
+             to the right manifest in a workspace with many",
+            {
+                let bridge = bridge.clone();
+                async move |input: FileOnlyInputs, _mcp_cx| {
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            let result = client.open_cargo_toml(uri).await.map_err(|e| {
+                                ToolError::request_failed("Open Cargo.toml request failed", e)
+                            })?;
+                            Ok(ToolOutput::ready(CargoTomlResult { location: result }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_add_import",
+            "Find the `use` declarations that would resolve an unresolved name at a position, \
+             returning each candidate's edits. Set `apply` to write the import to the file, with \
+             `choice` picking the candidate when there are several. Positions are 0-based unless \
+             `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: AddImportInputs, _mcp_cx| {
+                    let AddImportInputs {
+                        position: inputs,
+                        apply,
+                        choice,
+                    } = input;
+                    let position = inputs.checked_position(&bridge).await?;
+                    with_bridge_and_editable_document(
+                        &bridge,
+                        inputs.workspace_path.as_deref(),
+                        &inputs.file_path,
+                        inputs.content.clone(),
+                        async |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/codeAction")
+                            {
+                                return Ok((unsupported, None));
+                            }
+                            let candidates = import_candidates(client, &uri, position)
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Code actions request failed", e)
+                                })?;
+                            let mut result = AddImportResult {
+                                candidates,
+                                applied: None,
+                                message: None,
+                            };
+                            let mut edits = None;
+                            if result.candidates.is_empty() {
+                                result.message = Some(
+                                    "No imports found for the name at this position".to_string(),
+                                );
+                            } else if apply {
+                                let chosen = match (choice, result.candidates.len()) {
+                                    (Some(choice), _) => result.candidates.get(choice),
+                                    (None, 1) => result.candidates.first(),
+                                    (None, _) => None,
+                                };
+                                match chosen {
+                                    Some(candidate) => {
+                                        edits = Some(candidate.edits.clone());
+                                        result.applied = Some(candidate.label.clone());
+                                    }
+                                    None => {
+                                        result.message = Some(format!(
+                                            "Pass `choice` (0 to {}) to pick which import to apply",
+                                            result.candidates.len() - 1
+                                        ));
+                                    }
+                                }
+                            }
+                            Ok((ToolOutput::ready(result), edits))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_extract",
+            "Extract the code in a range into a new function, variable or constant \
+             (`extract_kind`) using rust-analyzer's assists, returning the edits. Set `apply` to \
+             write them to the file. Fails if the range can't be extracted, e.g. because it \
+             doesn't cover a whole expression or whole statements. Positions are 0-based unless \
+             `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: ExtractInputs, _mcp_cx| {
+                    let ExtractInputs {
+                        range: inputs,
+                        extract_kind,
+                        apply,
+                    } = input;
+                    let title =
+                        extract_assist_title(&extract_kind).map_err(ToolError::invalid_input)?;
+                    let range = inputs.checked_range(&bridge).await?;
+                    with_bridge_and_editable_document(
+                        &bridge,
+                        inputs.workspace_path.as_deref(),
+                        &inputs.file_path,
+                        inputs.content.clone(),
+                        async |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/codeAction")
+                            {
+                                return Ok((unsupported, None));
+                            }
+                            let actions = resolved_code_actions(
+                                client,
+                                &uri,
+                                range,
+                                vec![],
+                                CodeActionKind::REFACTOR_EXTRACT,
+                            )
+                            .await
+                            .map_err(|e| {
+                                ToolError::request_failed("Code actions request failed", e)
+                            })?;
+                            let Some(action) = actions
+                                .into_iter()
+                                .find(|action| action.title.starts_with(title))
+                            else {
+                                return Err(anyhow!(
+                                    "The selected range can't be extracted into a {}; select a \
+                                     whole expression or whole statements",
+                                    extract_kind.to_ascii_lowercase()
+                                )
+                                .into());
+                            };
+                            let edits = apply.then(|| action.edits.clone());
+                            Ok((
+                                ToolOutput::ready(ExtractResult {
+                                    title: action.title,
+                                    edits: action.edits,
+                                    applied: apply,
+                                }),
+                                edits,
+                            ))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_fix_plan",
+            "Collect the quick fixes for every diagnostic in a file into one plan of \
+             non-conflicting edits, errors first. Diagnostics whose fixes would overlap a \
+             planned edit or touch other files are listed as skipped. Set `apply` to write the \
+             plan to the file.",
+            {
+                let bridge = bridge.clone();
+                async move |input: FixPlanInputs, _mcp_cx| {
+                    with_bridge_and_editable_document(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async |client, uri| {
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/codeAction")
+                            {
+                                return Ok((unsupported, None));
+                            }
+                            let diagnostics = client
+                                .document_diagnostics(uri.clone())
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Diagnostics request failed", e)
+                                })?;
+                            let mut candidates = Vec::with_capacity(diagnostics.len());
+                            for diagnostic in diagnostics {
+                                let fixes = quick_fixes(
+                                    client,
+                                    &uri,
+                                    diagnostic.range,
+                                    vec![diagnostic.clone()],
+                                )
+                                .await
+                                .map_err(|e| {
+                                    ToolError::request_failed("Code actions request failed", e)
+                                })?;
+                                candidates.push((diagnostic, fixes));
+                            }
+                            let mut plan = plan_fixes(candidates);
+                            let mut edits = None;
+                            if input.apply && !plan.fixes.is_empty() {
+                                edits = Some(plan.edits());
+                                plan.applied = true;
+                            }
+                            Ok((ToolOutput::ready(plan), edits))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_parent_module",
+            "Find the module declaration (`mod foo;`) that includes the file or item at a \
+             position, to navigate up the module tree. Positions are 0-based unless `one_based` \
+             is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            let result =
+                                client.parent_module(uri, position).await.map_err(|e| {
+                                    ToolError::request_failed("Parent module request failed", e)
+                                })?;
+                            let locations = result.map(goto_response_locations).unwrap_or_default();
+                            // At a crate root rust-analyzer points at the package's Cargo.toml
+                            // instead, or returns nothing at all.
+                            let at_crate_root = locations.iter().all(|location| {
+                                location.uri.path().as_str().ends_with("/Cargo.toml")
+                            });
+                            let message = at_crate_root.then(|| {
+                                "No parent module: this is a crate root (e.g. main.rs or lib.rs)"
+                                    .to_string()
+                            });
+                            Ok(ToolOutput::ready(ParentModuleResult { locations, message }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_on_enter",
+            "Get the edits rust-analyzer makes when Enter is pressed at a position, e.g. \
+             continuing a `///` doc comment. Returns plain-text edits with the resulting cursor \
+             position, or null when a plain newline is fine. Positions are 0-based unless \
+             `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            let result = client.on_enter(uri, position).await.map_err(|e| {
+                                ToolError::request_failed("On enter request failed", e)
+                            })?;
+                            let edits = result.map(|edits| {
+                                edits
+                                    .into_iter()
+                                    .map(|edit| edit.into_plain(client.position_encoding()))
+                                    .collect::<Vec<_>>()
+                            });
+                            Ok(ToolOutput::ready(PlainEditsResult { edits }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_matching_brace",
+            "Find the brace, bracket or parenthesis matching the one at a position. Returns the \
+             matching position, or the input position if there is no brace there. Positions are \
+             0-based unless `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            let result =
+                                client
+                                    .matching_brace(uri, vec![position])
+                                    .await
+                                    .map_err(|e| {
+                                        ToolError::request_failed(
+                                            "Matching brace request failed",
+                                            e,
+                                        )
+                                    })?;
+                            Ok(ToolOutput::ready(MatchingBraceResult {
+                                position: result.into_iter().next(),
+                            }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_join_lines",
+            "Get the edits that join the lines in a range the way an editor's smart join does, \
+             e.g. removing braces around a single expression or merging use trees. Positions are \
+             0-based unless `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: RangeInputs, _mcp_cx| {
+                    let range = input.checked_range(&bridge).await?;
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            let result =
+                                client.join_lines(uri, vec![range]).await.map_err(|e| {
+                                    ToolError::request_failed("Join lines request failed", e)
+                                })?;
+                            Ok(ToolOutput::ready(TextEditsResult {
+                                edits: Some(result),
+                            }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_move_item",
+            "Get the edits that move the item (function, impl, field, statement, match arm...) \
+             in a range up or down past its neighbour. Returns an empty list if there is nothing \
+             to move. Positions are 0-based unless `one_based` is set.",
             {
                 let bridge = bridge.clone();
-                async move |input: FilePositionInputs, _mcp_cx| {
-                    with_bridge_and_document(
+                async move |input: MoveItemInputs, _mcp_cx| {
+                    let direction: MoveItemDirection =
+                        input.direction.parse().map_err(ToolError::invalid_input)?;
+                    let input = input.range;
+                    let range = input.checked_range(&bridge).await?;
+                    with_bridge_and_document_content(
                         &bridge,
-                        None,
+                        input.workspace_path.as_deref(),
                         &input.file_path,
+                        input.content.clone(),
                         async move |client, uri| {
-                            let position = Position::new(input.line, input.character);
-                            let result = client
-                                .hover(uri, position)
-                                .await
-                                .map_err(|e| anyhow!("Hover request failed: {}", e))?;
-                            Ok(serde_json::to_string(&result)?)
+                            let result =
+                                client.move_item(uri, range, direction).await.map_err(|e| {
+                                    ToolError::request_failed("Move item request failed", e)
+                                })?;
+                            let edits = result
+                                .into_iter()
+                                .map(|edit| edit.into_plain(client.position_encoding()))
+                                .collect::<Vec<_>>();
+                            Ok(ToolOutput::ready(PlainEditsResult { edits: Some(edits) }))
                         },
                     )
                     .await
                 }
             },
-            sacp::tool_fn_mut!(),
+            sacp::tool_fn!(),
         )
-        .tool_fn_mut(
-            "rust_analyzer_definition",
-            "Go to definition of a symbol at a specific position",
+        .direct_tool_fn(
+            "rust_analyzer_on_type_formatting",
+            "Get the edits rust-analyzer makes after a character is typed, such as indenting a \
+             method chain after `.` or adding `;` after `=`. The file text (or `content`) must \
+             already contain the character, and the position is just after it. Positions are \
+             0-based unless `one_based` is set.",
             {
                 let bridge = bridge.clone();
-                async move |input: FilePositionInputs, _mcp_cx| {
-                    with_bridge_and_document(
+                async move |input: OnTypeFormattingInputs, _mcp_cx| {
+                    let ch = input.ch;
+                    let input = input.position;
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
                         &bridge,
-                        None,
+                        input.workspace_path.as_deref(),
                         &input.file_path,
+                        input.content.clone(),
                         async move |client, uri| {
-                            let position = Position::new(input.line, input.character);
-                            let result = client
-                                .goto_definition(uri, position)
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/onTypeFormatting")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let triggers = client.on_type_formatting_triggers();
+                            if !triggers.contains(&ch) {
+                                return Err(ToolError::InvalidInput(format!(
+                                    "`{}` does not trigger on-type formatting; expected one of: {}",
+                                    ch,
+                                    triggers.join(" ")
+                                ))
+                                .into());
+                            }
+                            let edits = client
+                                .on_type_formatting(uri, position, &ch)
                                 .await
-                                .map_err(|e| anyhow!("Definition request failed: {}", e))?;
-                            Ok(serde_json::to_string(&result)?)
+                                .map_err(|e| {
+                                    ToolError::request_failed(
+                                        "On-type formatting request failed",
+                                        e,
+                                    )
+                                })?;
+                            Ok(ToolOutput::ready(TextEditsResult { edits: Some(edits) }))
                         },
                     )
                     .await
                 }
             },
-            sacp::tool_fn_mut!(),
+            sacp::tool_fn!(),
         )
-        .tool_fn_mut(
-            "rust_analyzer_references",
-            "Find all references to a symbol at a specific position",
+        .direct_tool_fn(
+            "rust_analyzer_linked_editing",
+            "Get the ranges that must change together with the identifier at a position, such as \
+             a lifetime or type parameter and its uses. Returns an empty list when there are \
+             none. Positions are 0-based unless `one_based` is set.",
             {
                 let bridge = bridge.clone();
                 async move |input: FilePositionInputs, _mcp_cx| {
-                    with_bridge_and_document(
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
                         &bridge,
-                        None,
+                        input.workspace_path.as_deref(),
                         &input.file_path,
+                        input.content.clone(),
                         async move |client, uri| {
-                            let position = Position::new(input.line, input.character);
-                            let result = client
-                                .find_references(uri, position, true)
-                                .await
-                                .map_err(|e| anyhow!("References request failed: {}", e))?;
-                            Ok(serde_json::to_string(&result)?)
+                            if let Some(unsupported) =
+                                unsupported(client, "textDocument/linkedEditingRange")
+                            {
+                                return Ok(unsupported);
+                            }
+                            let result =
+                                client
+                                    .linked_editing_range(uri, position)
+                                    .await
+                                    .map_err(|e| {
+                                        ToolError::request_failed(
+                                            "Linked editing range request failed",
+                                            e,
+                                        )
+                                    })?;
+                            let ranges = result.map(|linked| linked.ranges).unwrap_or_default();
+                            Ok(ToolOutput::ready(RangesResult { ranges }))
                         },
                     )
                     .await
                 }
             },
-            sacp::tool_fn_mut!(),
+            sacp::tool_fn!(),
         )
-        .tool_fn_mut(
-            "rust_analyzer_completion",
-            "Get code completions at a specific position",
+        .direct_tool_fn(
+            "rust_analyzer_ssr",
+            "Structural search and replace across the workspace using a rule like `foo($a) ==>> \
+             bar($a)`. Returns the resulting workspace edit without applying it, or with \
+             parse_only just validates the rule",
             {
                 let bridge = bridge.clone();
-                async move |input: FilePositionInputs, _mcp_cx| {
+                async move |input: SsrInputs, _mcp_cx| {
                     with_bridge_and_document(
                         &bridge,
-                        None,
+                        input.workspace_path.as_deref(),
                         &input.file_path,
                         async move |client, uri| {
-                            let position = Position::new(input.line, input.character);
                             let result = client
-                                .completion(uri, position)
-                                .await
-                                .map_err(|e| anyhow!("Completion request failed: {}", e))?;
-                            Ok(serde_json::to_string(&result)?)
+                                .ssr(input.query, input.parse_only, uri, Position::new(0, 0))
+                                .await;
+                            if input.parse_only {
+                                return Ok(ToolOutput::ready(SsrResult {
+                                    parsed: Some(result.is_ok()),
+                                    error: result.err().map(|e| e.to_string()),
+                                    edit: None,
+                                }));
+                            }
+                            let result = result
+                                .map_err(|e| ToolError::request_failed("SSR request failed", e))?;
+                            Ok(ToolOutput::ready(SsrResult {
+                                parsed: None,
+                                error: None,
+                                edit: result,
+                            }))
                         },
                     )
                     .await
                 }
             },
-            sacp::tool_fn_mut!(),
+            sacp::tool_fn!(),
         )
-        .tool_fn_mut(
-            "rust_analyzer_symbols",
-            "Get document symbols for a Rust file",
+        .direct_tool_fn(
+            "rust_analyzer_view_hir",
+            "Get a text dump of the HIR of the function at a position, useful when reasoning \
+             about type inference and lowering. Positions are 0-based unless `one_based` is set.",
             {
                 let bridge = bridge.clone();
-                async move |input: FileOnlyInputs, _mcp_cx| {
-                    with_bridge_and_document(
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
                         &bridge,
-                        None,
+                        input.workspace_path.as_deref(),
                         &input.file_path,
+                        input.content.clone(),
                         async move |client, uri| {
-                            let result = client
-                                .document_symbols(uri)
-                                .await
-                                .map_err(|e| anyhow!("Document symbols request failed: {}", e))?;
-                            Ok(serde_json::to_string(&result)?)
+                            let result = client.view_hir(uri, position).await.map_err(|e| {
+                                ToolError::request_failed("View HIR request failed", e)
+                            })?;
+                            Ok(ToolOutput::ready(TextResult { text: result }))
                         },
                     )
                     .await
                 }
             },
-            sacp::tool_fn_mut!(),
+            sacp::tool_fn!(),
         )
-        /*
-        .tool_fn_mut(
-            "rust_analyzer_format",
-            "Format a Rust document",
+        .direct_tool_fn(
+            "rust_analyzer_view_mir",
+            "Get a text dump of the MIR of the function at a position, useful when reasoning \
+             about type inference and lowering. Positions are 0-based unless `one_based` is set.",
             {
                 let bridge = bridge.clone();
-                async move |input: FileOnlyInputs, _mcp_cx| {
-                    with_bridge_and_document(
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
                         &bridge,
-                        None,
+                        input.workspace_path.as_deref(),
                         &input.file_path,
+                        input.content.clone(),
                         async move |client, uri| {
-                            let result = client
-                                .format_document(uri)
-                                .await
-                                .map_err(|e| anyhow!("Format request failed: {}", e))?;
-                            Ok(serde_json::to_string(&result)?)
+                            let result = client.view_mir(uri, position).await.map_err(|e| {
+                                ToolError::request_failed("View MIR request failed", e)
+                            })?;
+                            Ok(ToolOutput::ready(TextResult { text: result }))
                         },
                     )
                     .await
                 }
             },
-            sacp::tool_fn_mut!(),
+            sacp::tool_fn!(),
         )
-        .tool_fn_mut(
-            "rust_analyzer_code_actions",
-            "Get available code actions for a range in a Rust file",
+        .direct_tool_fn(
+            "rust_analyzer_interpret",
+            "Run the function at a position in rust-analyzer's MIR interpreter and return what \
+             it printed and returned, to explore const-evaluable code without compiling it. \
+             Functions taking arguments or using unsupported features give an `error` instead. \
+             Positions are 0-based unless `one_based` is set.",
             {
                 let bridge = bridge.clone();
-                async move |input: RangeInputs, _mcp_cx| {
-                    with_bridge_and_document(
+                async move |input: FilePositionInputs, _mcp_cx| {
+                    let position = input.checked_position(&bridge).await?;
+                    with_bridge_and_document_content(
                         &bridge,
-                        None,
+                        input.workspace_path.as_deref(),
                         &input.file_path,
+                        input.content.clone(),
                         async move |client, uri| {
-                            let range = Range::new(
-                                Position::new(input.line, input.character),
-                                Position::new(input.end_line, input.end_character),
-                            );
-                            let context = CodeActionContext {
-                                diagnostics: vec![],
-                                only: None,
-                                trigger_kind: None,
+                            let result = match client.interpret_function(uri, position).await {
+                                Ok(output) => InterpretResult {
+                                    output: Some(output),
+                                    error: None,
+                                },
+                                Err(e) if LspError::is_method_not_found(&e) => {
+                                    return Err(ToolError::Unsupported(
+                                        "This rust-analyzer version can't interpret functions"
+                                            .to_string(),
+                                    )
+                                    .into());
+                                }
+                                Err(e) => match e.downcast::<LspError>() {
+                                    Ok(e) => InterpretResult {
+                                        output: None,
+                                        error: Some(
+                                            e.error
+                                                .get("message")
+                                                .and_then(Value::as_str)
+                                                .map_or_else(|| e.to_string(), str::to_string),
+                                        ),
+                                    },
+                                    Err(e) => {
+                                        return Err(ToolError::request_failed(
+                                            "Interpret request failed",
+                                            e,
+                                        )
+                                        .into());
+                                    }
+                                },
                             };
-                            let result = client
-                                .code_actions(uri, range, context)
-                                .await
-                                .map_err(|e| anyhow!("Code actions request failed: {}", e))?;
-                            Ok(serde_json::to_string(&result)?)
+                            Ok(ToolOutput::ready(result))
                         },
                     )
                     .await
                 }
             },
-            sacp::tool_fn_mut!(),
+            sacp::tool_fn!(),
         )
-        */
-        .tool_fn_mut(
-            "rust_analyzer_set_workspace",
-            "Set the workspace root for rust-analyzer",
+        .direct_tool_fn(
+            "rust_analyzer_status",
+            "Report whether rust-analyzer is ready, still loading, or not started for a \
+             workspace, without waiting or triggering analysis. Poll this when a tool answers \
+             with status `loading`",
             {
                 let bridge = bridge.clone();
-                async move |input: WorkspaceInputs, _mcp_cx| {
-                    with_bridge(&bridge, Some(&input.workspace_path), async move |_client| {
-                        Ok("Workspace set successfully".to_string())
+                async move |input: StatusInputs, _mcp_cx| {
+                    let bridge_guard = bridge.lock().await;
+                    let workspace =
+                        bridge_guard.resolve_workspace(input.workspace_path.as_deref(), None);
+                    let status = match bridge_guard.workspaces.get(&workspace) {
+                        None => "not_started",
+                        Some(workspace_bridge) if workspace_bridge.client.is_ready() => "ready",
+                        Some(_) => "loading",
+                    };
+                    Ok(ServerStatus {
+                        status,
+                        workspace,
+                        message: None,
+                    })
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_progress",
+            "Report what rust-analyzer is working on, like indexing or cargo check, with each \
+             task's title, message and percentage, without waiting. Poll this while the server \
+             is loading to tell slow progress from a hang",
+            {
+                let bridge = bridge.clone();
+                async move |input: StatusInputs, _mcp_cx| {
+                    let client = {
+                        let bridge_guard = bridge.lock().await;
+                        let workspace =
+                            bridge_guard.resolve_workspace(input.workspace_path.as_deref(), None);
+                        bridge_guard
+                            .workspaces
+                            .get(&workspace)
+                            .map(|workspace_bridge| workspace_bridge.client.clone())
+                    };
+                    Ok(match client {
+                        None => ProgressResult {
+                            status: "not_started",
+                            progress: Vec::new(),
+                        },
+                        Some(client) => ProgressResult {
+                            status: if client.is_ready() {
+                                "ready"
+                            } else {
+                                "loading"
+                            },
+                            progress: client.progress().await,
+                        },
                     })
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_close_document",
+            "Close a document previously opened by other tools so rust-analyzer can free its \
+             memory. The server also closes the least recently used documents once too many are \
+             open",
+            {
+                let bridge = bridge.clone();
+                async move |input: FileOnlyInputs, _mcp_cx| {
+                    let (closed, file_path) =
+                        close_document(&bridge, input.workspace_path.as_deref(), &input.file_path)
+                            .await?;
+                    let message = if closed {
+                        format!("Closed {}", file_path.display())
+                    } else {
+                        format!("{} was not open", file_path.display())
+                    };
+                    Ok(MessageResult { message })
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_syntax_tree",
+            "Get the parsed syntax tree of a Rust file, or of the part covering a range, to \
+             understand how unusual syntax or macro input is parsed. Positions are 0-based \
+             unless `one_based` is set.",
+            {
+                let bridge = bridge.clone();
+                async move |input: OptionalRangeInputs, _mcp_cx| {
+                    let range = input.range()?;
+                    let (file_path, encoding, source) = resolve_file_encoding(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                    )
+                    .await?;
+                    let text = document_text(&*source, &file_path, input.content.as_deref())?;
+                    for position in range.iter().flat_map(|range| [range.start, range.end]) {
+                        check_position(&text, position, input.one_based, encoding)
+                            .map_err(|e| ToolError::InvalidPosition(e.to_string()))?;
+                    }
+                    with_bridge_and_document_content(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        &input.file_path,
+                        input.content.clone(),
+                        async move |client, uri| {
+                            let result = match client.syntax_tree(uri.clone(), range).await {
+                                Err(e) if LspError::is_method_not_found(&e) => {
+                                    let tree = client.view_syntax_tree(uri).await.map_err(|e| {
+                                        ToolError::request_failed(
+                                            "View syntax tree request failed",
+                                            e,
+                                        )
+                                    })?;
+                                    render_syntax_tree(
+                                        &tree,
+                                        &text,
+                                        range,
+                                        client.position_encoding(),
+                                    )
+                                }
+                                result => result,
+                            };
+                            let text = result.map_err(|e| {
+                                ToolError::request_failed("Syntax tree request failed", e)
+                            })?;
+                            Ok(ToolOutput::ready(TextResult { text }))
+                        },
+                    )
                     .await
                 }
             },
-            sacp::tool_fn_mut!(),
+            sacp::tool_fn!(),
         )
-        /*
-        .tool_fn_mut(
-            "rust_analyzer_diagnostics",
-            "Get diagnostics for a Rust file",
+        .direct_tool_fn(
+            "rust_analyzer_item_tree",
+            "Get a text dump of the item tree of a Rust file, showing the items, imports and \
+             macro calls rust-analyzer sees in it before name resolution. Useful when an item \
+             seems to be missing from a module",
             {
                 let bridge = bridge.clone();
                 async move |input: FileOnlyInputs, _mcp_cx| {
-                    with_bridge_and_document(
+                    with_bridge_and_document_content(
                         &bridge,
-                        None,
+                        input.workspace_path.as_deref(),
                         &input.file_path,
+                        input.content.clone(),
                         async move |client, uri| {
-                            let result = client
-                                .diagnostics(uri)
-                                .await
-                                .map_err(|e| anyhow!("Diagnostics request failed: {}", e))?;
-                            Ok(serde_json::to_string(&result)?)
+                            let text = client.view_item_tree(uri).await.map_err(|e| {
+                                ToolError::request_failed("View item tree request failed", e)
+                            })?;
+                            Ok(ToolOutput::ready(TextResult { text }))
                         },
                     )
                     .await
                 }
             },
-            sacp::tool_fn_mut!(),
+            sacp::tool_fn!(),
         )
-        */
-        .tool_fn_mut(
-            "rust_analyzer_failed_obligations",
-            "Get failed trait obligations for debugging (rust-analyzer specific)",
+        .direct_tool_fn(
+            "rust_analyzer_crate_graph",
+            "Get the crate dependency graph of the workspace in Graphviz DOT format, to \
+             understand its structure or spot dependency cycles. Set `full` to include crates.io \
+             and sysroot dependencies, and `max_bytes` to truncate large graphs",
             {
                 let bridge = bridge.clone();
-                let state = failed_obligations_state.clone();
-                async move |input: FilePositionInputs, _mcp_cx| {
-                    let mut bridge_guard = bridge.lock().await;
-                    let uri = ensure_document_open(&mut bridge_guard, &input.file_path).await?;
-                    let doc = TextDocumentIdentifier { uri };
-                    let position = Position::new(input.line, input.character);
-
-                    let args = TextDocumentPositionParams {
-                        text_document: doc,
-                        position,
+                async move |input: CrateGraphInputs, _mcp_cx| {
+                    with_bridge(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        async move |client| {
+                            let graph = client.view_crate_graph(input.full).await.map_err(|e| {
+                                ToolError::request_failed("View crate graph request failed", e)
+                            })?;
+                            let text = match input.max_bytes {
+                                Some(max_bytes) => truncate_output(graph, max_bytes),
+                                None => graph,
+                            };
+                            Ok(ToolOutput::ready(TextResult { text }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_analyzer_status",
+            "Get rust-analyzer's internal status report (loaded workspaces, caches, pending \
+             work) to debug slow or stuck analysis",
+            {
+                let bridge = bridge.clone();
+                async move |input: EmptyInputs, _mcp_cx| {
+                    with_bridge(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        async move |client| {
+                            let result = client.analyzer_status().await.map_err(|e| {
+                                ToolError::request_failed("Analyzer status request failed", e)
+                            })?;
+                            Ok(ToolOutput::ready(TextResult { text: result }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_memory_usage",
+            "Get rust-analyzer's memory usage report, broken down by query",
+            {
+                let bridge = bridge.clone();
+                async move |input: EmptyInputs, _mcp_cx| {
+                    with_bridge(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        async move |client| {
+                            let result = client.memory_usage().await.map_err(|e| {
+                                ToolError::request_failed("Memory usage request failed", e)
+                            })?;
+                            Ok(ToolOutput::ready(TextResult { text: result }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_check_workspace",
+            "Run cargo check over the workspace, wait for it to finish, and return the fresh \
+             diagnostics with totals and the number of files with errors",
+            {
+                let bridge = bridge.clone();
+                async move |input: EmptyInputs, _mcp_cx| {
+                    with_bridge(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        async move |client| {
+                            client.run_flycheck(FLYCHECK_TIMEOUT).await.map_err(|e| {
+                                ToolError::request_failed("Workspace check failed", e)
+                            })?;
+                            let diagnostics =
+                                client.workspace_diagnostics().await.map_err(|e| {
+                                    ToolError::request_failed(
+                                        "Workspace diagnostics request failed",
+                                        e,
+                                    )
+                                })?;
+                            Ok(ToolOutput::ready(summarize_workspace_diagnostics(
+                                diagnostics,
+                            )))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_rebuild_proc_macros",
+            "Rebuild proc macros and re-run build scripts, then wait for rust-analyzer to \
+             reload, so completion and hover see code generated by a macro or build script that \
+             just changed. Returns the server's readiness before and after",
+            {
+                let bridge = bridge.clone();
+                async move |input: EmptyInputs, _mcp_cx| {
+                    let client = {
+                        let mut bridge_guard = bridge.lock().await;
+                        let workspace =
+                            bridge_guard.resolve_workspace(input.workspace_path.as_deref(), None);
+                        ensure_bridge(&mut bridge_guard, &workspace)
+                            .await?
+                            .client
+                            .clone()
                     };
-
-                    let mut state = state.lock().await;
-                    use std::ops::DerefMut;
-                    let state = state.deref_mut();
-                    let result = handle_failed_obligations(
-                        bridge_guard.client.as_ref().unwrap(),
-                        state,
-                        args,
+                    let status = |ready| if ready { "ready" } else { "loading" };
+                    let before = status(client.is_ready());
+                    let finished =
+                        client
+                            .rebuild_proc_macros(REBUILD_TIMEOUT)
+                            .await
+                            .map_err(|e| {
+                                ToolError::request_failed("Rebuild proc macros request failed", e)
+                            })?;
+                    Ok(RebuildResult {
+                        before,
+                        after: status(finished),
+                    })
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_after_manifest_edit",
+            "Revalidate the workspace after editing a Cargo.toml, e.g. to add a dependency: \
+             reloads the workspace, waits for rust-analyzer to finish loading it, runs cargo \
+             check and returns the fresh workspace diagnostics. Warns if `manifest_path` isn't a \
+             Cargo.toml (rust-analyzer specific)",
+            {
+                let bridge = bridge.clone();
+                async move |input: ManifestEditInputs, _mcp_cx| {
+                    let (manifest_path, client) = {
+                        let mut bridge_guard = bridge.lock().await;
+                        let manifest_path = bridge_guard.resolve_file_path(
+                            input.workspace_path.as_deref(),
+                            &input.manifest_path,
+                        )?;
+                        let workspace = bridge_guard.resolve_workspace(
+                            input.workspace_path.as_deref(),
+                            Some(&manifest_path),
+                        );
+                        let client = ensure_bridge(&mut bridge_guard, &workspace)
+                            .await?
+                            .client
+                            .clone();
+                        (manifest_path, client)
+                    };
+                    let warning = manifest_warning(&manifest_path);
+                    let reloaded = client
+                        .reload_workspace(REBUILD_TIMEOUT)
+                        .await
+                        .map_err(|e| {
+                            ToolError::request_failed("Reload workspace request failed", e)
+                        })?;
+                    // A check started while the workspace is still loading would check the
+                    // old dependencies, if the server runs it at all.
+                    if reloaded {
+                        client
+                            .run_flycheck(FLYCHECK_TIMEOUT)
+                            .await
+                            .map_err(|e| ToolError::request_failed("Workspace check failed", e))?;
+                    }
+                    let diagnostics = client.workspace_diagnostics().await.map_err(|e| {
+                        ToolError::request_failed("Workspace diagnostics request failed", e)
+                    })?;
+                    Ok(ManifestEditResult {
+                        reloaded,
+                        diagnostics: summarize_workspace_diagnostics(diagnostics),
+                        warning,
+                    })
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_set_check_command",
+            "Switch the check rust-analyzer runs on save (and in rust_analyzer_check_workspace) \
+             between `check` and `clippy`, e.g. to get lint diagnostics, without restarting the \
+             server",
+            {
+                let bridge = bridge.clone();
+                async move |input: SetCheckCommandInputs, _mcp_cx| {
+                    let command: CheckCommand =
+                        input.command.parse().map_err(ToolError::invalid_input)?;
+                    with_bridge(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        async move |client| {
+                            client.set_check_command(command).await.map_err(|e| {
+                                ToolError::request_failed("Failed to update configuration", e)
+                            })?;
+                            Ok(ToolOutput::ready(CheckCommandResult {
+                                command: command.as_str().to_string(),
+                            }))
+                        },
                     )
-                    .await?;
-
-                    Ok(serde_json::to_string(&result).map_err(|e| anyhow::Error::new(e))?)
+                    .await
                 }
             },
-            sacp::tool_fn_mut!(),
+            sacp::tool_fn!(),
         )
-        .tool_fn_mut(
-            "rust_analyzer_failed_obligations_goal",
-            "Explore nested goals in failed trait obligations (rust-analyzer specific)",
+        .direct_tool_fn(
+            "rust_analyzer_configure",
+            "Change rust-analyzer settings at runtime, e.g. `{ \"cargo\": { \"allTargets\": true \
+             } }` to analyze tests and examples, without restarting the server. The settings are \
+             merged into the current ones, and the effective configuration is returned. Changes \
+             to cargo settings take effect once the workspace reloads (rust-analyzer specific)",
             {
                 let bridge = bridge.clone();
-                let state = failed_obligations_state.clone();
-                async move |input: GoalIndexInputs, _mcp_cx| {
-                    let bridge_guard = bridge.lock().await;
-                    let mut state = state.lock().await;
-                    use std::ops::DerefMut;
-                    let state = state.deref_mut();
-                    let result = handle_failed_obligations_goal(
-                        bridge_guard.client.as_ref().unwrap(),
-                        state,
-                        input,
+                async move |input: ConfigureInputs, _mcp_cx| {
+                    if !input.settings.is_object() {
+                        return Err(ToolError::InvalidInput(format!(
+                            "`settings` must be a JSON object, got `{}`",
+                            input.settings
+                        ))
+                        .into());
+                    }
+                    with_bridge(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        async move |client| {
+                            let configuration =
+                                client.configure(input.settings).await.map_err(|e| {
+                                    ToolError::request_failed("Failed to update configuration", e)
+                                })?;
+                            Ok(ToolOutput::ready(ConfigureResult { configuration }))
+                        },
                     )
-                    .await?;
-
-                    Ok(serde_json::to_string(&result).map_err(|e| anyhow::Error::new(e))?)
+                    .await
                 }
             },
-            sacp::tool_fn_mut!(),
+            sacp::tool_fn!(),
         )
-        .tool_fn_mut(
-            "rust_analyzer_lsp_call",
-            "Make an arbitrary LSP method call",
+        .direct_tool_fn(
+            "rust_analyzer_server_messages",
+            "Get recent window/showMessage and window/logMessage output from rust-analyzer, \
+             useful to diagnose why analysis produced no results",
             {
                 let bridge = bridge.clone();
-                async move |input: LspCallInputs, _mcp_cx| {
-                    let method = input.method.clone();
-                    let params = input.params;
-                    let is_notification = input.is_notification.unwrap_or(false);
-
+                async move |input: EmptyInputs, _mcp_cx| {
                     with_bridge(
                         &bridge,
                         input.workspace_path.as_deref(),
                         async move |client| {
-                            if is_notification {
-                                client
-                                    .notify(&method, params)
-                                    .await
-                                    .map_err(|e| anyhow!("LSP notify failed: {}", e))?;
-                                Ok("Notification sent".to_string())
-                            } else {
-                                let params = params.unwrap_or(Value::Null);
-                                let result = client
-                                    .request(&method, params)
-                                    .await
-                                    .map_err(|e| anyhow!("LSP request failed: {}", e))?;
-                                Ok(serde_json::to_string(&result)
-                                    .map_err(|e| anyhow::Error::new(e))?)
-                            }
+                            let messages = client.server_messages().await;
+                            Ok(ToolOutput::ready(ServerMessagesResult { messages }))
+                        },
+                    )
+                    .await
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_server_log",
+            "Get the last lines rust-analyzer wrote to stderr, including panics and startup \
+             errors. Returns immediately, even while the server is still loading",
+            {
+                let bridge = bridge.clone();
+                async move |input: EmptyInputs, _mcp_cx| {
+                    let mut bridge_guard = bridge.lock().await;
+                    let workspace =
+                        bridge_guard.resolve_workspace(input.workspace_path.as_deref(), None);
+                    let workspace_bridge = ensure_bridge(&mut bridge_guard, &workspace).await?;
+                    let lines = workspace_bridge.client.server_log().await;
+                    Ok(ServerLogResult { lines })
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_ping",
+            "Check that rust-analyzer is running and responding, returning the round-trip \
+             latency in milliseconds and the server version. Doesn't wait for the server to \
+             finish loading",
+            {
+                let bridge = bridge.clone();
+                async move |input: EmptyInputs, _mcp_cx| {
+                    let client = {
+                        let mut bridge_guard = bridge.lock().await;
+                        let workspace =
+                            bridge_guard.resolve_workspace(input.workspace_path.as_deref(), None);
+                        ensure_bridge(&mut bridge_guard, &workspace)
+                            .await?
+                            .client
+                            .clone()
+                    };
+                    let latency = client.ping(PING_TIMEOUT).await?;
+                    Ok(PingReport {
+                        latency_ms: latency.as_millis(),
+                        server_version: client.server_info().and_then(|info| info.version.clone()),
+                    })
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_version",
+            "Get the name and version rust-analyzer reported when it started. Newer extension \
+             requests may be missing from older versions. Doesn't wait for the server to finish \
+             loading",
+            {
+                let bridge = bridge.clone();
+                async move |input: EmptyInputs, _mcp_cx| {
+                    let mut bridge_guard = bridge.lock().await;
+                    let workspace =
+                        bridge_guard.resolve_workspace(input.workspace_path.as_deref(), None);
+                    let client = &ensure_bridge(&mut bridge_guard, &workspace).await?.client;
+                    let server_info = client
+                        .server_info()
+                        .ok_or_else(|| anyhow!("rust-analyzer did not report its version"))?;
+                    Ok(VersionResult::from(server_info))
+                }
+            },
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_capabilities",
+            "List which LSP features the running rust-analyzer supports, along with its version \
+             and the raw server capabilities",
+            {
+                let bridge = bridge.clone();
+                async move |input: EmptyInputs, _mcp_cx| {
+                    with_bridge(
+                        &bridge,
+                        input.workspace_path.as_deref(),
+                        async move |client| {
+                            let capabilities = client
+                                .capabilities()
+                                .ok_or_else(|| anyhow!("Server capabilities are not known yet"))?;
+                            let (supported, unsupported) = CAPABILITY_METHODS
+                                .iter()
+                                .partition(|method| client.supports(method));
+                            let report = CapabilitiesReport {
+                                server_info: client.server_info().map(VersionResult::from),
+                                supported,
+                                unsupported,
+                                capabilities: capabilities.clone(),
+                            };
+                            Ok(ToolOutput::ready(report))
                         },
                     )
                     .await
                 }
             },
-            sacp::tool_fn_mut!(),
+            sacp::tool_fn!(),
+        )
+        .direct_tool_fn(
+            "rust_analyzer_lsp_call",
+            "Make an arbitrary LSP method call",
+            {
+                let bridge = bridge.clone();
+                async move |input: LspCallInputs, _mcp_cx| {
+                    let is_notification = input.is_notification.unwrap_or(false);
+                    // Open the document the call is about, if any, so the server knows it.
+                    let document = input
+                        .params
+                        .as_ref()
+                        .and_then(|params| params.pointer("/textDocument/uri"))
+                        .and_then(Value::as_str)
+                        .and_then(|uri| Uri::from_str(uri).ok())
+                        .and_then(|uri| uri_to_path(&uri));
+                    match document {
+                        Some(file_path) => {
+                            with_bridge_and_document(
+                                &bridge,
+                                input.workspace_path.as_deref(),
+                                &file_path.to_string_lossy(),
+                                async move |client, _uri| {
+                                    lsp_call(client, &input.method, input.params, is_notification)
+                                        .await
+                                },
+                            )
+                            .await
+                        }
+                        None => {
+                            with_bridge(
+                                &bridge,
+                                input.workspace_path.as_deref(),
+                                async move |client| {
+                                    lsp_call(client, &input.method, input.params, is_notification)
+                                        .await
+                                },
+                            )
+                            .await
+                        }
+                    }
+                }
+            },
+            sacp::tool_fn!(),
         )
         .build();
 
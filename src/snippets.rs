@@ -0,0 +1,262 @@
+//! Snippet text edits, whose new text carries tabstops and placeholders like `${1:name}`,
+//! and their translation to plain edits that can be applied as is.
+
+use lsp_types::{InsertTextFormat, Position, Range};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::lsp_schema::{PositionSchema, RangeSchema};
+use crate::text_edits::PositionEncoding;
+
+/// A `TextEdit` whose `new_text` may be a snippet, with `$0` marking where the cursor goes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnippetTextEdit {
+    pub range: Range,
+    pub new_text: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub insert_text_format: Option<InsertTextFormat>,
+}
+
+/// A [`SnippetTextEdit`] with snippet syntax removed.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct PlainTextEdit {
+    #[schemars(with = "RangeSchema")]
+    pub range: Range,
+    pub new_text: String,
+    /// Where the snippet put the cursor, in document coordinates after applying the edit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schemars(with = "Option<PositionSchema>")]
+    pub cursor: Option<Position>,
+    /// The snippet's tabstops and placeholders, in the order they appear.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub placeholders: Vec<SnippetPlaceholder>,
+}
+
+/// A tabstop or placeholder of a snippet, located in the document after the edit.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct SnippetPlaceholder {
+    /// The tabstop number; `0` is the final cursor position.
+    pub index: u32,
+    /// The placeholder's default text, empty for a bare tabstop.
+    #[schemars(with = "RangeSchema")]
+    pub range: Range,
+}
+
+impl SnippetTextEdit {
+    /// Strips the snippet syntax, locating the cursor and placeholders with columns in
+    /// `encoding` units.
+    pub fn into_plain(self, encoding: PositionEncoding) -> PlainTextEdit {
+        if self.insert_text_format != Some(InsertTextFormat::SNIPPET) {
+            return PlainTextEdit {
+                range: self.range,
+                new_text: self.new_text,
+                cursor: None,
+                placeholders: vec![],
+            };
+        }
+
+        let (new_text, tabstops) = strip_snippet(&self.new_text);
+        let start = self.range.start;
+        let cursor = final_cursor(&tabstops)
+            .map(|tabstop| position_after(start, &new_text[..tabstop.start], encoding));
+        let placeholders = tabstops
+            .iter()
+            .map(|tabstop| SnippetPlaceholder {
+                index: tabstop.index,
+                range: Range::new(
+                    position_after(start, &new_text[..tabstop.start], encoding),
+                    position_after(start, &new_text[..tabstop.end], encoding),
+                ),
+            })
+            .collect();
+        PlainTextEdit {
+            range: self.range,
+            new_text,
+            cursor,
+            placeholders,
+        }
+    }
+}
+
+/// Rewrites the snippet edits anywhere in an LSP response, such as the text document edits
+/// of a code action's workspace edit, into plain `TextEdit`s.
+pub fn strip_snippet_edits(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            let is_snippet = object.get("insertTextFormat") == Some(&Value::from(2))
+                && object.contains_key("range");
+            if is_snippet && let Some(Value::String(new_text)) = object.get_mut("newText") {
+                *new_text = strip_snippet(new_text).0;
+                object.remove("insertTextFormat");
+            }
+            object.values_mut().for_each(strip_snippet_edits);
+        }
+        Value::Array(values) => values.iter_mut().for_each(strip_snippet_edits),
+        _ => {}
+    }
+}
+
+/// A tabstop found by [`strip_snippet`], as byte offsets into the plain text.
+#[derive(Debug, PartialEq)]
+struct Tabstop {
+    index: u32,
+    start: usize,
+    end: usize,
+}
+
+/// Removes tabstops (`$1`, `${2}`) and placeholders (`${1:text}`, keeping `text`) from a
+/// snippet, returning the plain text and where each tabstop ended up in it.
+fn strip_snippet(snippet: &str) -> (String, Vec<Tabstop>) {
+    let mut text = String::with_capacity(snippet.len());
+    let mut tabstops = Vec::new();
+    let mut chars = snippet.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if matches!(chars.peek(), Some('$' | '}' | '\\')) => {
+                text.push(chars.next().unwrap());
+            }
+            '$' if chars
+                .peek()
+                .is_some_and(|c| c.is_ascii_digit() || *c == '{') =>
+            {
+                let braced = chars.next_if_eq(&'{').is_some();
+                let mut index = String::new();
+                while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+                    index.push(digit);
+                }
+                let start = text.len();
+                if braced {
+                    // Keep a placeholder's default text; drop the closing brace.
+                    chars.next_if_eq(&':');
+                    for c in chars.by_ref() {
+                        if c == '}' {
+                            break;
+                        }
+                        text.push(c);
+                    }
+                }
+                tabstops.push(Tabstop {
+                    index: index.parse().unwrap_or(0),
+                    start,
+                    end: text.len(),
+                });
+            }
+            _ => text.push(c),
+        }
+    }
+    (text, tabstops)
+}
+
+/// Where the cursor ends up: at `$0` if present, otherwise at the first tabstop.
+fn final_cursor(tabstops: &[Tabstop]) -> Option<&Tabstop> {
+    tabstops
+        .iter()
+        .find(|tabstop| tabstop.index == 0)
+        .or_else(|| tabstops.iter().min_by_key(|tabstop| tabstop.index))
+}
+
+/// The document position reached by inserting `text` at `start`.
+fn position_after(start: Position, text: &str, encoding: PositionEncoding) -> Position {
+    let lines = text.matches('\n').count() as u32;
+    let last_line = text.rsplit('\n').next().unwrap_or_default();
+    let column = encoding.len(last_line) as u32;
+    if lines == 0 {
+        Position::new(start.line, start.character + column)
+    } else {
+        Position::new(start.line + lines, column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_snippet_keeps_placeholders_and_finds_cursor() {
+        let cursor = |snippet| {
+            let (text, tabstops) = strip_snippet(snippet);
+            (text, final_cursor(&tabstops).map(|tabstop| tabstop.start))
+        };
+        assert_eq!(cursor("\n/// $0"), ("\n/// ".to_string(), Some(5)));
+        assert_eq!(
+            cursor("fn ${1:name}() {\n    $0\n}"),
+            ("fn name() {\n    \n}".to_string(), Some(16))
+        );
+        assert_eq!(cursor("cost: \\$5"), ("cost: $5".to_string(), None));
+    }
+
+    #[test]
+    fn snippet_cursor_is_in_document_coordinates() {
+        let edit = SnippetTextEdit {
+            range: Range::new(Position::new(3, 10), Position::new(3, 10)),
+            new_text: "\n    /// $0".to_string(),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+        };
+        let plain = edit.into_plain(PositionEncoding::Utf16);
+        assert_eq!(plain.new_text, "\n    /// ");
+        assert_eq!(plain.cursor, Some(Position::new(4, 8)));
+    }
+
+    #[test]
+    fn generated_function_placeholders_are_located() {
+        // What rust-analyzer's "Generate function" assist inserts after line 9.
+        let edit = SnippetTextEdit {
+            range: Range::new(Position::new(9, 1), Position::new(9, 1)),
+            new_text: "\n\nfn ${1:compute}(${2:x}: i32) ${3:-> i32} {\n    ${0:todo!()}\n}"
+                .to_string(),
+            insert_text_format: Some(InsertTextFormat::SNIPPET),
+        };
+        let plain = edit.into_plain(PositionEncoding::Utf16);
+
+        assert_eq!(
+            plain.new_text,
+            "\n\nfn compute(x: i32) -> i32 {\n    todo!()\n}"
+        );
+        assert_eq!(plain.cursor, Some(Position::new(12, 4)));
+        let placeholders: Vec<_> = plain
+            .placeholders
+            .iter()
+            .map(|placeholder| (placeholder.index, placeholder.range))
+            .collect();
+        assert_eq!(
+            placeholders,
+            [
+                (1, Range::new(Position::new(11, 3), Position::new(11, 10))),
+                (2, Range::new(Position::new(11, 11), Position::new(11, 12))),
+                (3, Range::new(Position::new(11, 19), Position::new(11, 25))),
+                (0, Range::new(Position::new(12, 4), Position::new(12, 11))),
+            ]
+        );
+    }
+
+    #[test]
+    fn code_action_snippet_edits_become_plain() {
+        let mut action = serde_json::json!({
+            "title": "Generate function `compute`",
+            "edit": { "documentChanges": [{
+                "textDocument": { "uri": "file:///src/main.rs", "version": 1 },
+                "edits": [
+                    {
+                        "range": { "start": { "line": 9, "character": 1 }, "end": { "line": 9, "character": 1 } },
+                        "newText": "\n\nfn ${1:compute}() {\n    $0\n}",
+                        "insertTextFormat": 2,
+                    },
+                    {
+                        "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 0 } },
+                        "newText": "// costs $5\n",
+                    },
+                ],
+            }]},
+        });
+
+        strip_snippet_edits(&mut action);
+
+        let edits = &action["edit"]["documentChanges"][0]["edits"];
+        assert_eq!(edits[0]["newText"], "\n\nfn compute() {\n    \n}");
+        assert!(edits[0].get("insertTextFormat").is_none());
+        assert_eq!(edits[1]["newText"], "// costs $5\n");
+    }
+}
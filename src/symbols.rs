@@ -0,0 +1,826 @@
+use anyhow::{Result, anyhow};
+use lsp_types::{
+    DocumentSymbol, DocumentSymbolResponse, Location, OneOf, Position, Range, SymbolKind,
+    WorkspaceSymbolResponse,
+};
+use schemars::JsonSchema;
+use serde::Serialize;
+
+use crate::lsp_schema::{LocationSchema, RangeSchema};
+use crate::text_edits::{PositionEncoding, column_offset};
+
+/// A document symbol with its position in the tree spelled out as a path.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct FlatSymbol {
+    pub name: String,
+    /// The LSP `SymbolKind` number.
+    #[schemars(with = "i32")]
+    pub kind: SymbolKind,
+    /// Path of the enclosing items, e.g. `Database::add_user`. Impl blocks are
+    /// named after their self type, so methods read as `Type::method`.
+    pub container_path: String,
+    #[schemars(with = "RangeSchema")]
+    pub range: Range,
+    /// The range of the symbol's name, suitable for position-based requests.
+    #[schemars(with = "RangeSchema")]
+    pub selection_range: Range,
+}
+
+/// A symbol found by name, with a human-friendly position and the code around it.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct SymbolMatch {
+    pub name: String,
+    #[schemars(with = "i32")]
+    pub kind: SymbolKind,
+    pub container_path: String,
+    /// 1-based line of the symbol's name.
+    pub line: u32,
+    /// 1-based column of the symbol's name.
+    pub column: u32,
+    pub snippet: String,
+}
+
+/// An item containing a position, as found by [`enclosing_items`].
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct EnclosingItem {
+    pub name: String,
+    #[schemars(with = "i32")]
+    pub kind: SymbolKind,
+    /// What rust-analyzer shows next to the name; for functions, their signature.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// The full range of the item, including its body.
+    #[schemars(with = "RangeSchema")]
+    pub range: Range,
+}
+
+/// A symbol found by a workspace symbol search.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct WorkspaceSymbol {
+    pub name: String,
+    #[schemars(with = "i32")]
+    pub kind: SymbolKind,
+    /// The module or type the symbol is declared in, as rust-analyzer reports it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub container_name: Option<String>,
+    #[schemars(with = "LocationSchema")]
+    pub location: Location,
+}
+
+/// An item of a file's API, as found by [`api_symbols`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiSymbol {
+    pub symbol: FlatSymbol,
+    /// Whether code outside the crate can name the item, as far as this file tells.
+    pub public: bool,
+}
+
+/// Structural metrics of a file, as computed by [`file_metrics`].
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct FileMetrics {
+    /// Functions and methods, including those nested in other functions.
+    pub functions: usize,
+    pub structs: usize,
+    pub enums: usize,
+    pub traits: usize,
+    pub impls: usize,
+    pub total_lines: usize,
+    /// Lines holding nothing but a comment, doc comments included.
+    pub comment_lines: usize,
+    /// The function spanning the most lines; null if the file has none.
+    pub longest_function: Option<LongestFunction>,
+}
+
+/// The longest function of a file, a hint at where its complexity is.
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+pub struct LongestFunction {
+    /// Path of the function, e.g. `Database::add_user`.
+    pub name: String,
+    /// 1-based line the function starts at.
+    pub line: u32,
+    /// How many lines it spans, signature and doc comments included.
+    pub lines: u32,
+}
+
+/// Number of lines of source included in a [`SymbolMatch`] snippet.
+const SNIPPET_LINES: usize = 3;
+
+/// Flattens a document symbol response into a list in document order.
+pub fn flatten_document_symbols(response: DocumentSymbolResponse) -> Vec<FlatSymbol> {
+    let mut symbols = Vec::new();
+    match response {
+        DocumentSymbolResponse::Nested(nested) => {
+            for symbol in &nested {
+                flatten_into(symbol, &[], &mut symbols);
+            }
+        }
+        DocumentSymbolResponse::Flat(flat) => {
+            for symbol in flat {
+                let container = symbol.container_name.as_deref().map(container_segment);
+                let container_path = match container {
+                    Some(container) => format!("{}::{}", container, symbol.name),
+                    None => symbol.name.clone(),
+                };
+                symbols.push(FlatSymbol {
+                    name: symbol.name,
+                    kind: symbol.kind,
+                    container_path,
+                    range: symbol.location.range,
+                    selection_range: symbol.location.range,
+                });
+            }
+        }
+    }
+    symbols
+}
+
+/// The symbols whose range contains `position`, from the outermost to the innermost.
+pub fn enclosing_items(response: DocumentSymbolResponse, position: Position) -> Vec<EnclosingItem> {
+    let contains = |range: &Range| range.start <= position && position <= range.end;
+    let mut items = Vec::new();
+    match response {
+        DocumentSymbolResponse::Nested(nested) => {
+            let mut level = nested;
+            while let Some(symbol) = level.into_iter().find(|symbol| contains(&symbol.range)) {
+                items.push(EnclosingItem {
+                    name: symbol.name,
+                    kind: symbol.kind,
+                    detail: symbol.detail,
+                    range: symbol.range,
+                });
+                level = symbol.children.unwrap_or_default();
+            }
+        }
+        DocumentSymbolResponse::Flat(flat) => {
+            items.extend(
+                flat.into_iter()
+                    .filter(|symbol| contains(&symbol.location.range))
+                    .map(|symbol| EnclosingItem {
+                        name: symbol.name,
+                        kind: symbol.kind,
+                        detail: None,
+                        range: symbol.location.range,
+                    }),
+            );
+            // Flat responses aren't ordered by nesting; an outer item starts
+            // earlier or, starting at the same place, ends later.
+            items.sort_by(|a, b| {
+                a.range
+                    .start
+                    .cmp(&b.range.start)
+                    .then(b.range.end.cmp(&a.range.end))
+            });
+        }
+    }
+    items
+}
+
+/// The items making up a file's API, in document order: everything but impl blocks, the
+/// items of trait impls and what is declared inside function bodies.
+///
+/// An item is public if it is declared `pub` (not `pub(crate)` or the like) and its parent,
+/// if any, is public. Trait items and enum variants are public along with their parent.
+/// Visibility is read from `text` just before each item's name.
+pub fn api_symbols(
+    response: DocumentSymbolResponse,
+    text: &str,
+    encoding: PositionEncoding,
+) -> Vec<ApiSymbol> {
+    let lines: Vec<&str> = text.lines().collect();
+    let declared_pub = |position: Position| {
+        let Some(line) = lines.get(position.line as usize) else {
+            return false;
+        };
+        let before = &line[..column_offset(line, position.character, encoding)];
+        before.trim_start().starts_with("pub ")
+    };
+
+    let mut symbols = Vec::new();
+    match response {
+        DocumentSymbolResponse::Nested(nested) => {
+            for symbol in &nested {
+                collect_api(
+                    symbol,
+                    &[],
+                    Visibility::Own(true),
+                    &declared_pub,
+                    &mut symbols,
+                );
+            }
+        }
+        flat => {
+            for symbol in flatten_document_symbols(flat) {
+                if symbol.kind != SymbolKind::OBJECT {
+                    let public = declared_pub(symbol.selection_range.start);
+                    symbols.push(ApiSymbol { symbol, public });
+                }
+            }
+        }
+    }
+    symbols
+}
+
+/// Parses a symbol kind name as accepted by `rust_analyzer_list_symbols_by_kind`, mapped to
+/// the LSP kind rust-analyzer reports for it. Unions share `struct`'s kind, and statics
+/// share `const`'s.
+pub fn parse_symbol_kind(name: &str) -> Result<SymbolKind> {
+    match name.to_ascii_lowercase().as_str() {
+        "struct" | "union" => Ok(SymbolKind::STRUCT),
+        "enum" => Ok(SymbolKind::ENUM),
+        "variant" => Ok(SymbolKind::ENUM_MEMBER),
+        "trait" => Ok(SymbolKind::INTERFACE),
+        "function" | "fn" => Ok(SymbolKind::FUNCTION),
+        "method" => Ok(SymbolKind::METHOD),
+        "field" => Ok(SymbolKind::FIELD),
+        "const" | "constant" | "static" => Ok(SymbolKind::CONSTANT),
+        "type" | "type_alias" => Ok(SymbolKind::TYPE_PARAMETER),
+        "module" | "mod" => Ok(SymbolKind::MODULE),
+        _ => Err(anyhow!(
+            "Invalid symbol kind `{}`: expected \"struct\", \"enum\", \"variant\", \"trait\", \
+             \"function\", \"method\", \"field\", \"const\", \"type\" or \"module\"",
+            name
+        )),
+    }
+}
+
+/// The symbols of a workspace symbol response that have `kind`, ordered by file and
+/// position so that pages of them are stable.
+pub fn workspace_symbols_of_kind(
+    response: WorkspaceSymbolResponse,
+    kind: SymbolKind,
+) -> Vec<WorkspaceSymbol> {
+    let mut symbols: Vec<WorkspaceSymbol> = match response {
+        WorkspaceSymbolResponse::Flat(symbols) => symbols
+            .into_iter()
+            .map(|symbol| WorkspaceSymbol {
+                name: symbol.name,
+                kind: symbol.kind,
+                container_name: symbol.container_name,
+                location: symbol.location,
+            })
+            .collect(),
+        WorkspaceSymbolResponse::Nested(symbols) => symbols
+            .into_iter()
+            .filter_map(|symbol| match symbol.location {
+                OneOf::Left(location) => Some(WorkspaceSymbol {
+                    name: symbol.name,
+                    kind: symbol.kind,
+                    container_name: symbol.container_name,
+                    location,
+                }),
+                OneOf::Right(_) => None,
+            })
+            .collect(),
+    };
+    symbols.retain(|symbol| symbol.kind == kind);
+    symbols.sort_by_key(|symbol| {
+        let start = symbol.location.range.start;
+        (symbol.location.uri.to_string(), start.line, start.character)
+    });
+    symbols
+}
+
+/// Counts the items of a file from its document symbols, and its lines from `text`.
+pub fn file_metrics(response: DocumentSymbolResponse, text: &str) -> FileMetrics {
+    let symbols = flatten_document_symbols(response);
+    let count = |kinds: &[SymbolKind]| {
+        symbols
+            .iter()
+            .filter(|symbol| kinds.contains(&symbol.kind))
+            .count()
+    };
+    let longest_function = symbols
+        .iter()
+        .filter(|symbol| matches!(symbol.kind, SymbolKind::FUNCTION | SymbolKind::METHOD))
+        .map(|symbol| LongestFunction {
+            name: symbol.container_path.clone(),
+            line: symbol.range.start.line + 1,
+            lines: symbol.range.end.line - symbol.range.start.line + 1,
+        })
+        // `max_by_key` keeps the last of equals; reversed, the first function wins.
+        .rev()
+        .max_by_key(|function| function.lines);
+
+    let mut comment_lines = 0;
+    let mut in_block_comment = false;
+    for line in text.lines() {
+        let line = line.trim();
+        if in_block_comment {
+            comment_lines += 1;
+            in_block_comment = !line.contains("*/");
+        } else if line.starts_with("//") {
+            comment_lines += 1;
+        } else if let Some(rest) = line.strip_prefix("/*") {
+            comment_lines += 1;
+            in_block_comment = !rest.contains("*/");
+        }
+    }
+
+    FileMetrics {
+        functions: count(&[SymbolKind::FUNCTION, SymbolKind::METHOD]),
+        structs: count(&[SymbolKind::STRUCT]),
+        enums: count(&[SymbolKind::ENUM]),
+        traits: count(&[SymbolKind::INTERFACE]),
+        impls: count(&[SymbolKind::OBJECT]),
+        total_lines: text.lines().count(),
+        comment_lines,
+        longest_function,
+    }
+}
+
+/// Finds the symbols whose name or container path equals `name`, reading
+/// snippets from `text`, the current contents of the document.
+pub fn find_symbols(symbols: Vec<FlatSymbol>, name: &str, text: &str) -> Vec<SymbolMatch> {
+    let lines: Vec<&str> = text.lines().collect();
+    symbols
+        .into_iter()
+        .filter(|symbol| symbol.name == name || symbol.container_path == name)
+        .map(|symbol| {
+            let start = symbol.range.start.line as usize;
+            let end = (symbol.range.end.line as usize).min(start + SNIPPET_LINES - 1);
+            let snippet = lines
+                .get(start..=end.min(lines.len().saturating_sub(1)))
+                .unwrap_or_default()
+                .join("\n");
+            SymbolMatch {
+                name: symbol.name,
+                kind: symbol.kind,
+                container_path: symbol.container_path,
+                line: symbol.selection_range.start.line + 1,
+                column: symbol.selection_range.start.character + 1,
+                snippet,
+            }
+        })
+        .collect()
+}
+
+/// Positions of every whole-identifier appearance of `name` in `text`, in file order.
+/// Characters are counted in `encoding` units.
+pub fn find_identifier_occurrences(
+    text: &str,
+    name: &str,
+    encoding: PositionEncoding,
+) -> Vec<Position> {
+    let is_ident = |c: char| c == '_' || c.is_alphanumeric();
+    let mut positions = Vec::new();
+    if name.is_empty() {
+        return positions;
+    }
+    for (line, content) in text.lines().enumerate() {
+        for (start, _) in content.match_indices(name) {
+            let end = start + name.len();
+            let before = content[..start].chars().next_back();
+            let after = content[end..].chars().next();
+            if before.is_some_and(is_ident) || after.is_some_and(is_ident) {
+                continue;
+            }
+            let character = encoding.len(&content[..start]);
+            positions.push(Position::new(line as u32, character as u32));
+        }
+    }
+    positions
+}
+
+/// The target of the intra-doc link at `position` in a `///` or `//!` comment of `text`,
+/// e.g. `Person::greet` for ``[`Person::greet`]`` or `[greeting](Person::greet)`.
+/// Characters are counted in `encoding` units.
+pub fn doc_link_at(text: &str, position: Position, encoding: PositionEncoding) -> Option<String> {
+    let line = text.lines().nth(position.line as usize)?;
+    let comment = line.trim_start();
+    if !(comment.starts_with("///") || comment.starts_with("//!")) {
+        return None;
+    }
+    let column = column_offset(line, position.character, encoding);
+
+    let mut search_from = 0;
+    while let Some(open) = line[search_from..].find('[').map(|i| search_from + i) {
+        let close = open + line[open..].find(']')?;
+        // A `[text](target)` or `[text][target]` link points at its destination.
+        let (target, end) = match line[close + 1..].chars().next() {
+            Some(delimiter @ ('(' | '[')) => {
+                let closing = if delimiter == '(' { ')' } else { ']' };
+                match line[close + 1..].find(closing) {
+                    Some(i) => (&line[close + 2..close + 1 + i], close + 1 + i),
+                    None => (&line[open + 1..close], close),
+                }
+            }
+            _ => (&line[open + 1..close], close),
+        };
+        if (open..=end).contains(&column) {
+            let target = target.trim().trim_matches('`');
+            return (!target.is_empty()).then(|| target.to_string());
+        }
+        search_from = end + 1;
+    }
+    None
+}
+
+/// The whole lines of `text` that `range` spans, cut off after `max_lines` lines, and
+/// whether they were cut off.
+pub fn source_lines(text: &str, range: Range, max_lines: usize) -> (String, bool) {
+    let start = range.start.line as usize;
+    let spanned = (range.end.line as usize).saturating_sub(start) + 1;
+    let lines: Vec<&str> = text.lines().skip(start).take(spanned).collect();
+    let truncated = lines.len() > max_lines;
+    (lines[..lines.len().min(max_lines)].join("\n"), truncated)
+}
+
+fn flatten_into(symbol: &DocumentSymbol, parents: &[&str], out: &mut Vec<FlatSymbol>) {
+    let segment = container_segment(&symbol.name);
+    let mut path = parents.to_vec();
+    path.push(segment);
+
+    out.push(FlatSymbol {
+        name: symbol.name.clone(),
+        kind: symbol.kind,
+        container_path: path.join("::"),
+        range: symbol.range,
+        selection_range: symbol.selection_range,
+    });
+
+    for child in symbol.children.iter().flatten() {
+        flatten_into(child, &path, out);
+    }
+}
+
+/// How the children of an item get their visibility in [`collect_api`].
+#[derive(Clone, Copy)]
+enum Visibility {
+    /// From their own declaration, if their parent is public (given here).
+    Own(bool),
+    /// From their parent, like trait items and enum variants.
+    Inherited(bool),
+}
+
+fn collect_api(
+    symbol: &DocumentSymbol,
+    parents: &[&str],
+    visibility: Visibility,
+    declared_pub: &impl Fn(Position) -> bool,
+    out: &mut Vec<ApiSymbol>,
+) {
+    let segment = container_segment(&symbol.name);
+    let mut path = parents.to_vec();
+    path.push(segment);
+    let children = symbol.children.iter().flatten();
+
+    if symbol.kind == SymbolKind::OBJECT {
+        // Impl blocks have no visibility of their own, and the items of a trait impl are
+        // already listed with the trait.
+        if !symbol.name.contains(" for ") {
+            for child in children {
+                collect_api(child, &path, visibility, declared_pub, out);
+            }
+        }
+        return;
+    }
+
+    let public = match visibility {
+        Visibility::Own(parent_public) => {
+            parent_public && declared_pub(symbol.selection_range.start)
+        }
+        Visibility::Inherited(parent_public) => parent_public,
+    };
+    out.push(ApiSymbol {
+        symbol: FlatSymbol {
+            name: symbol.name.clone(),
+            kind: symbol.kind,
+            container_path: path.join("::"),
+            range: symbol.range,
+            selection_range: symbol.selection_range,
+        },
+        public,
+    });
+
+    let children_visibility = match symbol.kind {
+        SymbolKind::FUNCTION | SymbolKind::METHOD => return,
+        SymbolKind::INTERFACE | SymbolKind::ENUM => Visibility::Inherited(public),
+        _ => Visibility::Own(public),
+    };
+    for child in children {
+        collect_api(child, &path, children_visibility, declared_pub, out);
+    }
+}
+
+/// rust-analyzer names impl blocks `impl Type` or `impl Trait for Type`;
+/// reduce those to `Type` so they read naturally in a path.
+fn container_segment(name: &str) -> &str {
+    let Some(rest) = name
+        .strip_prefix("impl")
+        .filter(|rest| rest.starts_with([' ', '<']))
+    else {
+        return name;
+    };
+    let rest = match rest.rsplit_once(" for ") {
+        Some((_, self_ty)) => self_ty,
+        None => skip_generic_params(rest),
+    };
+    rest.trim()
+}
+
+/// Skips a leading `<...>` generic parameter list, if any.
+fn skip_generic_params(s: &str) -> &str {
+    if !s.starts_with('<') {
+        return s;
+    }
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' => {
+                depth -= 1;
+                if depth == 0 {
+                    return &s[i + 1..];
+                }
+            }
+            _ => {}
+        }
+    }
+    s
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn impl_names_become_self_type() {
+        assert_eq!(container_segment("impl Person"), "Person");
+        assert_eq!(container_segment("impl Displayable for Person"), "Person");
+        assert_eq!(container_segment("impl<T: Clone> Wrapper<T>"), "Wrapper<T>");
+        assert_eq!(container_segment("important"), "important");
+    }
+
+    #[test]
+    fn find_symbols_matches_name_or_path() {
+        let range = |line, character| Range {
+            start: Position { line, character },
+            end: Position { line, character },
+        };
+        let symbol = |name: &str, container_path: &str, line| FlatSymbol {
+            name: name.to_string(),
+            kind: SymbolKind::METHOD,
+            container_path: container_path.to_string(),
+            range: range(line, 4),
+            selection_range: range(line, 11),
+        };
+        let text = "impl A {\n    pub fn run() {}\n}\nimpl B {\n    pub fn run() {}\n}\n";
+        let symbols = vec![symbol("run", "A::run", 1), symbol("run", "B::run", 4)];
+
+        let all = find_symbols(symbols.clone(), "run", text);
+        assert_eq!(all.len(), 2);
+        assert_eq!((all[1].line, all[1].column), (5, 12));
+        assert_eq!(all[1].snippet, "    pub fn run() {}");
+
+        let one = find_symbols(symbols, "A::run", text);
+        assert_eq!(one.len(), 1);
+        assert_eq!(one[0].container_path, "A::run");
+    }
+
+    #[test]
+    fn enclosing_items_run_outermost_to_innermost() {
+        #[allow(deprecated)]
+        let symbol = |name: &str, kind, start, end, children| DocumentSymbol {
+            name: name.to_string(),
+            detail: None,
+            kind,
+            tags: None,
+            deprecated: None,
+            range: Range::new(Position::new(start, 0), Position::new(end, 1)),
+            selection_range: Range::new(Position::new(start, 0), Position::new(start, 1)),
+            children,
+        };
+        let response = DocumentSymbolResponse::Nested(vec![
+            symbol("Database", SymbolKind::STRUCT, 0, 2, None),
+            symbol(
+                "impl Database",
+                SymbolKind::OBJECT,
+                4,
+                12,
+                Some(vec![
+                    symbol("new", SymbolKind::METHOD, 5, 7, None),
+                    symbol("add_user", SymbolKind::METHOD, 8, 11, None),
+                ]),
+            ),
+        ]);
+
+        let names = |items: Vec<EnclosingItem>| -> Vec<String> {
+            items.into_iter().map(|item| item.name).collect()
+        };
+        assert_eq!(
+            names(enclosing_items(response.clone(), Position::new(9, 4))),
+            ["impl Database", "add_user"]
+        );
+        assert!(enclosing_items(response, Position::new(3, 0)).is_empty());
+    }
+
+    #[test]
+    fn api_symbols_follow_visibility() {
+        let text = "pub struct Person {\n    pub name: String,\n    age: u32,\n}\nimpl Person {\n    pub fn new() -> Self {\n        fn helper() {}\n    }\n    fn secret(&self) {}\n}\npub trait Displayable {\n    fn display(&self) -> String;\n}\nimpl Displayable for Person {\n    fn display(&self) -> String { todo!() }\n}\npub(crate) fn internal() {}\n";
+        #[allow(deprecated)]
+        let symbol = |name: &str, kind, line, character, children| DocumentSymbol {
+            name: name.to_string(),
+            detail: None,
+            kind,
+            tags: None,
+            deprecated: None,
+            range: Range::new(Position::new(line, 0), Position::new(line, 1)),
+            selection_range: Range::new(
+                Position::new(line, character),
+                Position::new(line, character + 1),
+            ),
+            children,
+        };
+        let response = DocumentSymbolResponse::Nested(vec![
+            symbol(
+                "Person",
+                SymbolKind::STRUCT,
+                0,
+                11,
+                Some(vec![
+                    symbol("name", SymbolKind::FIELD, 1, 8, None),
+                    symbol("age", SymbolKind::FIELD, 2, 4, None),
+                ]),
+            ),
+            symbol(
+                "impl Person",
+                SymbolKind::OBJECT,
+                4,
+                5,
+                Some(vec![
+                    symbol(
+                        "new",
+                        SymbolKind::METHOD,
+                        5,
+                        11,
+                        Some(vec![symbol("helper", SymbolKind::FUNCTION, 6, 11, None)]),
+                    ),
+                    symbol("secret", SymbolKind::METHOD, 8, 7, None),
+                ]),
+            ),
+            symbol(
+                "Displayable",
+                SymbolKind::INTERFACE,
+                10,
+                10,
+                Some(vec![symbol("display", SymbolKind::METHOD, 11, 7, None)]),
+            ),
+            symbol(
+                "impl Displayable for Person",
+                SymbolKind::OBJECT,
+                13,
+                5,
+                Some(vec![symbol("display", SymbolKind::METHOD, 14, 7, None)]),
+            ),
+            symbol("internal", SymbolKind::FUNCTION, 16, 14, None),
+        ]);
+
+        let items: Vec<(String, bool)> = api_symbols(response, text, PositionEncoding::Utf8)
+            .into_iter()
+            .map(|item| (item.symbol.container_path, item.public))
+            .collect();
+        let expected = [
+            ("Person", true),
+            ("Person::name", true),
+            ("Person::age", false),
+            ("Person::new", true),
+            ("Person::secret", false),
+            ("Displayable", true),
+            ("Displayable::display", true),
+            ("internal", false),
+        ]
+        .map(|(path, public)| (path.to_string(), public));
+        assert_eq!(items, expected);
+    }
+
+    #[test]
+    fn file_metrics_count_items_and_lines() {
+        let text = "//! Users.\n\n/// A user.\nstruct User;\n/* Impl\n   block */\nimpl User {\n    fn new() -> Self {\n        User\n    }\n    fn id(&self) {}\n}\n";
+        #[allow(deprecated)]
+        let symbol = |name: &str, kind, start, end, children| DocumentSymbol {
+            name: name.to_string(),
+            detail: None,
+            kind,
+            tags: None,
+            deprecated: None,
+            range: Range::new(Position::new(start, 0), Position::new(end, 1)),
+            selection_range: Range::new(Position::new(start, 0), Position::new(start, 1)),
+            children,
+        };
+        let response = DocumentSymbolResponse::Nested(vec![
+            symbol("User", SymbolKind::STRUCT, 2, 3, None),
+            symbol(
+                "impl User",
+                SymbolKind::OBJECT,
+                6,
+                11,
+                Some(vec![
+                    symbol("new", SymbolKind::METHOD, 7, 9, None),
+                    symbol("id", SymbolKind::METHOD, 10, 10, None),
+                ]),
+            ),
+        ]);
+
+        let metrics = file_metrics(response, text);
+        assert_eq!(
+            (
+                metrics.functions,
+                metrics.structs,
+                metrics.impls,
+                metrics.enums
+            ),
+            (2, 1, 1, 0)
+        );
+        assert_eq!((metrics.total_lines, metrics.comment_lines), (12, 4));
+        assert_eq!(
+            metrics.longest_function,
+            Some(LongestFunction {
+                name: "User::new".to_string(),
+                line: 8,
+                lines: 3,
+            })
+        );
+    }
+
+    #[test]
+    fn doc_links_are_found_around_the_position() {
+        let text =
+            "/// See [`Person::greet`] or [the database](Database).\n// [not] a doc comment\n";
+        let link =
+            |character| doc_link_at(text, Position::new(0, character), PositionEncoding::Utf8);
+
+        assert_eq!(link(12).as_deref(), Some("Person::greet"));
+        assert_eq!(link(8).as_deref(), Some("Person::greet"));
+        assert_eq!(link(32).as_deref(), Some("Database"));
+        assert_eq!(link(48).as_deref(), Some("Database"));
+        assert_eq!(link(4), None);
+        assert_eq!(
+            doc_link_at(text, Position::new(1, 4), PositionEncoding::Utf8),
+            None
+        );
+    }
+
+    #[test]
+    fn identifier_occurrences_respect_word_boundaries() {
+        let text = "let greet = greeting();\n// é greet\nself.greet()";
+        assert_eq!(
+            find_identifier_occurrences(text, "greet", PositionEncoding::Utf16),
+            vec![
+                Position::new(0, 4),
+                Position::new(1, 5),
+                Position::new(2, 5),
+            ]
+        );
+        // `é` is one UTF-16 unit but two bytes.
+        assert_eq!(
+            find_identifier_occurrences(text, "greet", PositionEncoding::Utf8)[1],
+            Position::new(1, 6)
+        );
+    }
+
+    #[test]
+    fn workspace_symbols_are_filtered_by_kind_and_ordered() {
+        #[allow(deprecated)]
+        let symbol = |name: &str, kind, file: &str, line| lsp_types::SymbolInformation {
+            name: name.to_string(),
+            kind,
+            tags: None,
+            deprecated: None,
+            location: Location::new(
+                file.parse().unwrap(),
+                Range::new(Position::new(line, 0), Position::new(line, 1)),
+            ),
+            container_name: None,
+        };
+        let response = WorkspaceSymbolResponse::Flat(vec![
+            symbol("Person", SymbolKind::STRUCT, "file:///src/main.rs", 20),
+            symbol("main", SymbolKind::FUNCTION, "file:///src/main.rs", 90),
+            symbol("Config", SymbolKind::STRUCT, "file:///src/lib.rs", 3),
+            symbol("Database", SymbolKind::STRUCT, "file:///src/main.rs", 4),
+        ]);
+
+        let kind = parse_symbol_kind("Struct").unwrap();
+        let names: Vec<String> = workspace_symbols_of_kind(response, kind)
+            .into_iter()
+            .map(|symbol| symbol.name)
+            .collect();
+        assert_eq!(names, ["Config", "Database", "Person"]);
+        assert_eq!(parse_symbol_kind("fn").unwrap(), SymbolKind::FUNCTION);
+        assert!(parse_symbol_kind("closure").is_err());
+    }
+
+    #[test]
+    fn source_lines_cover_the_range_up_to_a_limit() {
+        let text = "fn a() {}\nfn sum(a: i32) -> i32 {\n    a\n}\nfn b() {}\n";
+        let range = Range::new(Position::new(1, 0), Position::new(3, 1));
+
+        assert_eq!(
+            source_lines(text, range, 10),
+            ("fn sum(a: i32) -> i32 {\n    a\n}".to_string(), false)
+        );
+        assert_eq!(
+            source_lines(text, range, 2),
+            ("fn sum(a: i32) -> i32 {\n    a".to_string(), true)
+        );
+    }
+}
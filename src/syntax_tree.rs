@@ -0,0 +1,102 @@
+//! Rendering of the JSON syntax tree from `rust-analyzer/viewSyntaxTree` as the indented
+//! text the older `rust-analyzer/syntaxTree` request returned.
+
+use anyhow::{Result, anyhow};
+use lsp_types::Range;
+use serde::Deserialize;
+
+use crate::text_edits::{PositionEncoding, byte_offset};
+
+/// A node or token of the tree. Positions are `[byte offset, line, column]`.
+#[derive(Debug, Deserialize)]
+struct SyntaxElement {
+    #[serde(rename = "type")]
+    element_type: String,
+    kind: String,
+    start: (usize, u32, u32),
+    end: (usize, u32, u32),
+    #[serde(default)]
+    children: Vec<SyntaxElement>,
+}
+
+/// Renders `tree`, the JSON returned for the document `text`, one element per line. With
+/// a `range`, whose columns count `encoding` units, only the smallest node covering it is
+/// rendered.
+pub fn render_syntax_tree(
+    tree: &str,
+    text: &str,
+    range: Option<Range>,
+    encoding: PositionEncoding,
+) -> Result<String> {
+    let root: SyntaxElement =
+        serde_json::from_str(tree).map_err(|e| anyhow!("Invalid syntax tree: {}", e))?;
+    let mut node = &root;
+    if let Some(range) = range {
+        let start = byte_offset(text, range.start, encoding);
+        let end = byte_offset(text, range.end, encoding);
+        while let Some(child) = node.children.iter().find(|child| {
+            child.element_type == "Node" && child.start.0 <= start && end <= child.end.0
+        }) {
+            node = child;
+        }
+    }
+    let mut out = String::new();
+    render_into(node, text, 0, &mut out);
+    Ok(out)
+}
+
+fn render_into(element: &SyntaxElement, text: &str, depth: usize, out: &mut String) {
+    let (start, end) = (element.start.0, element.end.0);
+    out.push_str(&format!(
+        "{:indent$}{}@{}..{}",
+        "",
+        element.kind,
+        start,
+        end,
+        indent = depth * 2
+    ));
+    if element.element_type == "Token" {
+        out.push_str(&format!(" {:?}", text.get(start..end).unwrap_or_default()));
+    }
+    out.push('\n');
+    for child in &element.children {
+        render_into(child, text, depth + 1, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::Position;
+
+    const TEXT: &str = "fn f() { g(1) }";
+
+    /// The tree rust-analyzer gives for [`TEXT`], minus the whitespace around the call.
+    const TREE: &str = r#"{"type":"Node","kind":"SOURCE_FILE","start":[0,0,0],"end":[15,0,15],"children":[
+        {"type":"Node","kind":"FN","start":[0,0,0],"end":[15,0,15],"children":[
+            {"type":"Token","kind":"FN_KW","start":[0,0,0],"end":[2,0,2]},
+            {"type":"Node","kind":"BLOCK_EXPR","start":[7,0,7],"end":[15,0,15],"children":[
+                {"type":"Node","kind":"CALL_EXPR","start":[9,0,9],"end":[13,0,13],"children":[
+                    {"type":"Token","kind":"IDENT","start":[9,0,9],"end":[10,0,10]}
+                ]}
+            ]}
+        ]}
+    ]}"#;
+
+    #[test]
+    fn whole_file_is_rendered_with_token_text() {
+        let rendered = render_syntax_tree(TREE, TEXT, None, PositionEncoding::Utf16).unwrap();
+
+        assert!(rendered.starts_with("SOURCE_FILE@0..15\n  FN@0..15\n    FN_KW@0..2 \"fn\"\n"));
+    }
+
+    #[test]
+    fn range_selects_covering_node() {
+        let range = Range::new(Position::new(0, 10), Position::new(0, 12));
+
+        let rendered =
+            render_syntax_tree(TREE, TEXT, Some(range), PositionEncoding::Utf16).unwrap();
+
+        assert_eq!(rendered, "CALL_EXPR@9..13\n  IDENT@9..10 \"g\"\n");
+    }
+}
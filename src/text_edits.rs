@@ -0,0 +1,254 @@
+//! Applying LSP text edits to document text, and converting LSP positions to offsets in it.
+
+use anyhow::anyhow;
+use lsp_types::{Position, PositionEncodingKind, TextEdit};
+
+/// What the `character` of an LSP position counts, as agreed with the server at initialize.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PositionEncoding {
+    /// Bytes, so a character is simply a byte offset into the line.
+    Utf8,
+    /// UTF-16 code units, the LSP default.
+    #[default]
+    Utf16,
+}
+
+impl PositionEncoding {
+    /// The encoding a server chose; servers that don't say use UTF-16.
+    pub fn from_kind(kind: Option<&PositionEncodingKind>) -> Self {
+        match kind {
+            Some(kind) if *kind == PositionEncodingKind::UTF8 => Self::Utf8,
+            _ => Self::Utf16,
+        }
+    }
+
+    /// The length of `text` in this encoding's units.
+    pub fn len(self, text: &str) -> usize {
+        match self {
+            Self::Utf8 => text.len(),
+            Self::Utf16 => text.encode_utf16().count(),
+        }
+    }
+
+    /// What a column counts in this encoding, for messages.
+    pub fn unit(self) -> &'static str {
+        match self {
+            Self::Utf8 => "bytes",
+            Self::Utf16 => "UTF-16 code units",
+        }
+    }
+}
+
+/// The byte offset of an LSP position whose character counts `encoding` units. Positions
+/// past the end of a line or of the text are clamped to it.
+pub fn byte_offset(text: &str, position: Position, encoding: PositionEncoding) -> usize {
+    let mut offset = 0;
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        if index == position.line as usize {
+            let line = line.trim_end_matches('\n');
+            return offset + column_offset(line, position.character, encoding);
+        }
+        offset += line.len();
+    }
+    text.len()
+}
+
+/// The byte offset of column `character` in `line`, clamped to the line's end.
+pub fn column_offset(line: &str, character: u32, encoding: PositionEncoding) -> usize {
+    let character = character as usize;
+    match encoding {
+        PositionEncoding::Utf8 => {
+            let mut column = character.min(line.len());
+            while !line.is_char_boundary(column) {
+                column -= 1;
+            }
+            column
+        }
+        PositionEncoding::Utf16 => {
+            let mut units = 0;
+            for (byte, c) in line.char_indices() {
+                if units >= character {
+                    return byte;
+                }
+                units += c.len_utf16();
+            }
+            line.len()
+        }
+    }
+}
+
+/// Checks that `position` lies within `text`, so a request isn't sent for a spot that
+/// doesn't exist. The error counts lines and characters from 1 if `one_based` is set, to
+/// match what the caller passed.
+pub fn check_position(
+    text: &str,
+    position: Position,
+    one_based: bool,
+    encoding: PositionEncoding,
+) -> anyhow::Result<()> {
+    let base = u32::from(one_based);
+    let lines: Vec<&str> = text.split('\n').collect();
+    let Some(line) = lines.get(position.line as usize) else {
+        return Err(anyhow!(
+            "line {} out of range; the file has {} lines",
+            position.line + base,
+            text.lines().count()
+        ));
+    };
+    let length = encoding.len(line.trim_end_matches('\r')) as u32;
+    if position.character > length {
+        return Err(anyhow!(
+            "character {} out of range; line {} is {} {} long",
+            position.character + base,
+            position.line + base,
+            length,
+            encoding.unit()
+        ));
+    }
+    Ok(())
+}
+
+/// Applies `edits` to `text`. As in LSP, all ranges refer to the original text. Fails
+/// instead of producing garbled text when two edits overlap; edits that only touch, or
+/// insert at the same position, are fine.
+pub fn try_apply_text_edits(
+    text: &str,
+    edits: &[TextEdit],
+    encoding: PositionEncoding,
+) -> anyhow::Result<String> {
+    let edits = edit_offsets(text, edits, encoding);
+    if let Some(pair) = edits.windows(2).find(|pair| pair[0].1 > pair[1].0) {
+        anyhow::bail!(
+            "Overlapping edits at bytes {}..{} and {}..{}",
+            pair[0].0,
+            pair[0].1,
+            pair[1].0,
+            pair[1].1
+        );
+    }
+    Ok(splice(text, &edits))
+}
+
+/// The byte ranges and replacements of `edits`, sorted by range. Edits inserting at the
+/// same position keep their order.
+fn edit_offsets<'a>(
+    text: &str,
+    edits: &'a [TextEdit],
+    encoding: PositionEncoding,
+) -> Vec<(usize, usize, &'a str)> {
+    let mut edits: Vec<_> = edits
+        .iter()
+        .map(|edit| {
+            let start = byte_offset(text, edit.range.start, encoding);
+            let end = byte_offset(text, edit.range.end, encoding).max(start);
+            (start, end, edit.new_text.as_str())
+        })
+        .collect();
+    edits.sort_by_key(|&(start, end, _)| (start, end));
+    edits
+}
+
+fn splice(text: &str, edits: &[(usize, usize, &str)]) -> String {
+    // Apply back to front so earlier offsets stay valid.
+    let mut result = text.to_string();
+    for &(start, end, new_text) in edits.iter().rev() {
+        result.replace_range(start..end, new_text);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::Range;
+
+    fn edit(start: (u32, u32), end: (u32, u32), new_text: &str) -> TextEdit {
+        TextEdit {
+            range: Range::new(Position::new(start.0, start.1), Position::new(end.0, end.1)),
+            new_text: new_text.to_string(),
+        }
+    }
+
+    #[test]
+    fn positions_count_utf16_units() {
+        const UTF16: PositionEncoding = PositionEncoding::Utf16;
+        assert_eq!(byte_offset("é\nab", Position::new(0, 1), UTF16), 2);
+        assert_eq!(byte_offset("é\nab", Position::new(1, 1), UTF16), 4);
+        assert_eq!(byte_offset("é\nab", Position::new(0, 9), UTF16), 2);
+    }
+
+    #[test]
+    fn utf8_positions_are_byte_columns() {
+        const UTF8: PositionEncoding = PositionEncoding::Utf8;
+        assert_eq!(byte_offset("é\nab", Position::new(0, 2), UTF8), 2);
+        assert_eq!(byte_offset("é\nab", Position::new(1, 1), UTF8), 4);
+        // Inside a character: back to its start.
+        assert_eq!(byte_offset("é\nab", Position::new(0, 1), UTF8), 0);
+        assert_eq!(PositionEncoding::Utf8.len("é"), 2);
+        assert_eq!(PositionEncoding::Utf16.len("é"), 1);
+    }
+
+    #[test]
+    fn positions_past_the_text_are_reported() {
+        let text = "fn main() {\n    é\n}\n";
+
+        assert!(check_position(text, Position::new(1, 5), false, PositionEncoding::Utf16).is_ok());
+        assert!(check_position(text, Position::new(3, 0), false, PositionEncoding::Utf16).is_ok());
+        assert_eq!(
+            check_position(text, Position::new(499, 0), true, PositionEncoding::Utf16)
+                .unwrap_err()
+                .to_string(),
+            "line 500 out of range; the file has 3 lines"
+        );
+        assert_eq!(
+            check_position(text, Position::new(1, 6), false, PositionEncoding::Utf16)
+                .unwrap_err()
+                .to_string(),
+            "character 6 out of range; line 1 is 5 UTF-16 code units long"
+        );
+        assert_eq!(
+            check_position("é\n", Position::new(0, 3), false, PositionEncoding::Utf8)
+                .unwrap_err()
+                .to_string(),
+            "character 3 out of range; line 0 is 2 bytes long"
+        );
+    }
+
+    #[test]
+    fn edits_refer_to_the_original_text() {
+        let text = "fn main() {\n    let map = HashMap::new();\n}\n";
+        let edits = [
+            edit((1, 14), (1, 21), "BTreeMap"),
+            edit((0, 0), (0, 0), "use std::collections::BTreeMap;\n\n"),
+        ];
+
+        assert_eq!(
+            try_apply_text_edits(text, &edits, PositionEncoding::Utf16).unwrap(),
+            "use std::collections::BTreeMap;\n\nfn main() {\n    let map = BTreeMap::new();\n}\n"
+        );
+    }
+
+    #[test]
+    fn overlapping_edits_are_rejected() {
+        let text = "let value = 1;\n";
+        let edits = [edit((0, 4), (0, 9), "x"), edit((0, 8), (0, 13), "2")];
+
+        assert!(try_apply_text_edits(text, &edits, PositionEncoding::Utf16).is_err());
+    }
+
+    #[test]
+    fn adjacent_edits_are_applied() {
+        let text = "let value = 1;\n";
+        let edits = [
+            edit((0, 12), (0, 13), "2"),
+            edit((0, 4), (0, 9), "x"),
+            edit((0, 9), (0, 9), ": i32"),
+            edit((0, 9), (0, 11), " ="),
+        ];
+
+        assert_eq!(
+            try_apply_text_edits(text, &edits, PositionEncoding::Utf16).unwrap(),
+            "let x: i32 = 2;\n"
+        );
+    }
+}
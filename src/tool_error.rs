@@ -0,0 +1,179 @@
+//! Errors the tools return, tagged with a kind that callers can branch on instead of
+//! matching on messages.
+
+use serde_json::Value;
+
+use crate::lsp_client::{LspError, ServerExited, Unsupported};
+
+/// Why a tool failed. Sent to the client as a JSON-RPC error whose `data` is
+/// `{ "error_kind": ..., "message": ... }`, with `error_kind` one of the names below.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolError {
+    /// `file_not_found`: a file the tool was given doesn't exist or can't be read.
+    FileNotFound(String),
+    /// `server_not_ready`: rust-analyzer exited or failed to initialize; retrying
+    /// later may succeed.
+    ServerNotReady(String),
+    /// `spawn_failed`: the rust-analyzer binary couldn't be started, e.g. because it isn't
+    /// installed or the configured path is wrong. Retrying won't help until that is fixed.
+    SpawnFailed(String),
+    /// `unsupported`: the server doesn't implement a request the tool needs.
+    Unsupported(String),
+    /// `invalid_position`: a position or range lies outside the document.
+    InvalidPosition(String),
+    /// `invalid_input`: another input is malformed, such as an unknown option name.
+    InvalidInput(String),
+    /// `request_failed`: rust-analyzer answered a request with an error.
+    RequestFailed(String),
+    /// `internal`: anything else.
+    Internal(String),
+}
+
+impl ToolError {
+    /// The `error_kind` sent to the client.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::FileNotFound(_) => "file_not_found",
+            Self::ServerNotReady(_) => "server_not_ready",
+            Self::SpawnFailed(_) => "spawn_failed",
+            Self::Unsupported(_) => "unsupported",
+            Self::InvalidPosition(_) => "invalid_position",
+            Self::InvalidInput(_) => "invalid_input",
+            Self::RequestFailed(_) => "request_failed",
+            Self::Internal(_) => "internal",
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        match self {
+            Self::FileNotFound(message)
+            | Self::ServerNotReady(message)
+            | Self::SpawnFailed(message)
+            | Self::Unsupported(message)
+            | Self::InvalidPosition(message)
+            | Self::InvalidInput(message)
+            | Self::RequestFailed(message)
+            | Self::Internal(message) => message,
+        }
+    }
+
+    /// An input the tool couldn't make sense of, with `error` saying why.
+    pub fn invalid_input(error: impl std::fmt::Display) -> Self {
+        Self::InvalidInput(error.to_string())
+    }
+
+    /// A request to the server that failed with `error`, described by `context` such as
+    /// "Hover request failed". The kind comes from the error: the server having exited,
+    /// not knowing the method, or an error it answered with.
+    pub fn request_failed(context: &str, error: anyhow::Error) -> Self {
+        if let Some(data) = error
+            .downcast_ref::<sacp::Error>()
+            .and_then(|error| error.data.as_ref())
+            && let Some(inner) = Self::from_data(data)
+        {
+            return Self::with_kind(inner.kind(), format!("{}: {}", context, inner.message()));
+        }
+        let message = format!("{}: {}", context, error);
+        if error.is::<ServerExited>() {
+            Self::ServerNotReady(message)
+        } else if error.is::<Unsupported>() || LspError::is_method_not_found(&error) {
+            Self::Unsupported(message)
+        } else if error.is::<LspError>() {
+            Self::RequestFailed(message)
+        } else {
+            Self::Internal(message)
+        }
+    }
+
+    /// Gives an error that doesn't say what kind it is the kind `internal`; errors already
+    /// converted from a [`ToolError`] are returned as they are.
+    pub fn structured(error: sacp::Error) -> sacp::Error {
+        if error.data.as_ref().and_then(Self::from_data).is_some() {
+            return error;
+        }
+        let message = match &error.data {
+            Some(Value::String(data)) => data.clone(),
+            _ => error.message.clone(),
+        };
+        Self::Internal(message).into()
+    }
+
+    /// The error a JSON-RPC error's data describes, if it came from a [`ToolError`].
+    fn from_data(data: &Value) -> Option<Self> {
+        let kind = data.get("error_kind")?.as_str()?;
+        let message = data.get("message")?.as_str()?;
+        Some(Self::with_kind(kind, message.to_string()))
+    }
+
+    fn with_kind(kind: &str, message: String) -> Self {
+        match kind {
+            "file_not_found" => Self::FileNotFound(message),
+            "server_not_ready" => Self::ServerNotReady(message),
+            "spawn_failed" => Self::SpawnFailed(message),
+            "unsupported" => Self::Unsupported(message),
+            "invalid_position" => Self::InvalidPosition(message),
+            "invalid_input" => Self::InvalidInput(message),
+            "request_failed" => Self::RequestFailed(message),
+            _ => Self::Internal(message),
+        }
+    }
+}
+
+impl std::fmt::Display for ToolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.message())
+    }
+}
+
+impl From<ToolError> for sacp::Error {
+    fn from(error: ToolError) -> Self {
+        let base = match error {
+            ToolError::InvalidPosition(_) | ToolError::InvalidInput(_) => {
+                sacp::Error::invalid_params()
+            }
+            _ => sacp::Error::internal_error(),
+        };
+        base.data(serde_json::json!({
+            "error_kind": error.kind(),
+            "message": error.message(),
+        }))
+    }
+}
+
+/// Carries the error through `anyhow` as the [`sacp::Error`] it becomes, which converting
+/// back with `?` recovers.
+impl From<ToolError> for anyhow::Error {
+    fn from(error: ToolError) -> Self {
+        anyhow::Error::new(sacp::Error::from(error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn errors_keep_their_kind_through_anyhow() {
+        let error: anyhow::Error = ToolError::InvalidPosition("line 9 out of range".into()).into();
+        let error = sacp::Error::from(error);
+        assert_eq!(
+            error.data,
+            Some(serde_json::json!({
+                "error_kind": "invalid_position",
+                "message": "line 9 out of range",
+            }))
+        );
+
+        let exited = ToolError::request_failed(
+            "Hover request failed",
+            ServerExited("rust-analyzer".into()).into(),
+        );
+        assert_eq!(
+            exited,
+            ToolError::ServerNotReady("Hover request failed: rust-analyzer exited".into())
+        );
+
+        let plain = ToolError::structured(sacp::Error::from(anyhow::anyhow!("boom")));
+        assert_eq!(plain.data.unwrap()["error_kind"], "internal");
+    }
+}
@@ -0,0 +1,271 @@
+//! Tools served straight from an async closure.
+//!
+//! `McpServerBuilder::tool_fn_mut` runs every tool in a responder task of its own and nests
+//! the responders one level per tool. With as many tools as this server has, polling that
+//! nest overflows the default 2 MiB thread stack of unoptimized builds. Tools registered
+//! with [`DirectToolFn::direct_tool_fn`] add no responder: each call runs on a tokio task
+//! of its own, so calls run concurrently and a call always runs to completion, even when
+//! the MCP layer stops waiting for it. Tools that change rust-analyzer's state and put it
+//! back afterwards rely on that.
+
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use sacp::mcp_server::{McpConnectionTo, McpServerBuilder, McpTool};
+use sacp::{BoxFuture, Role, RunWithConnectionTo};
+use schemars::JsonSchema;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+/// Registers a tool implemented by an async closure without a responder task.
+pub trait DirectToolFn<Counterpart: Role>: Sized {
+    /// Like `tool_fn`, but the tool is called directly rather than through a responder.
+    /// Calls run concurrently, each on its own task, and are never interrupted. Pass
+    /// `sacp::tool_fn!()` as `to_future`.
+    fn direct_tool_fn<P, Ret, F>(
+        self,
+        name: impl ToString,
+        description: impl ToString,
+        func: F,
+        to_future: impl for<'a> Fn(
+            &'a F,
+            P,
+            McpConnectionTo<Counterpart>,
+        ) -> BoxFuture<'a, Result<Ret, sacp::Error>>
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self
+    where
+        P: JsonSchema + DeserializeOwned + Send + 'static,
+        Ret: JsonSchema + Serialize + Send + 'static,
+        F: AsyncFn(P, McpConnectionTo<Counterpart>) -> Result<Ret, sacp::Error>
+            + Send
+            + Sync
+            + 'static;
+}
+
+impl<Counterpart, Responder> DirectToolFn<Counterpart> for McpServerBuilder<Counterpart, Responder>
+where
+    Counterpart: Role,
+    Responder: RunWithConnectionTo<Counterpart>,
+{
+    fn direct_tool_fn<P, Ret, F>(
+        self,
+        name: impl ToString,
+        description: impl ToString,
+        func: F,
+        to_future: impl for<'a> Fn(
+            &'a F,
+            P,
+            McpConnectionTo<Counterpart>,
+        ) -> BoxFuture<'a, Result<Ret, sacp::Error>>
+        + Send
+        + Sync
+        + 'static,
+    ) -> Self
+    where
+        P: JsonSchema + DeserializeOwned + Send + 'static,
+        Ret: JsonSchema + Serialize + Send + 'static,
+        F: AsyncFn(P, McpConnectionTo<Counterpart>) -> Result<Ret, sacp::Error>
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.tool(FnTool {
+            name: name.to_string(),
+            description: description.to_string(),
+            func: Arc::new(func),
+            to_future: Arc::new(to_future),
+            phantom: PhantomData,
+        })
+    }
+}
+
+struct FnTool<F, ToFuture, P, Ret> {
+    name: String,
+    description: String,
+    func: Arc<F>,
+    to_future: Arc<ToFuture>,
+    phantom: PhantomData<fn(P) -> Ret>,
+}
+
+impl<Counterpart, F, ToFuture, P, Ret> McpTool<Counterpart> for FnTool<F, ToFuture, P, Ret>
+where
+    Counterpart: Role,
+    F: Send + Sync + 'static,
+    ToFuture: for<'a> Fn(
+            &'a F,
+            P,
+            McpConnectionTo<Counterpart>,
+        ) -> BoxFuture<'a, Result<Ret, sacp::Error>>
+        + Send
+        + Sync
+        + 'static,
+    P: JsonSchema + DeserializeOwned + Send + 'static,
+    Ret: JsonSchema + Serialize + Send + 'static,
+{
+    type Input = P;
+    type Output = Ret;
+
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn call_tool(
+        &self,
+        input: P,
+        context: McpConnectionTo<Counterpart>,
+    ) -> impl Future<Output = Result<Ret, sacp::Error>> + Send {
+        let func = self.func.clone();
+        let to_future = self.to_future.clone();
+        run_to_completion(async move { to_future(&func, input, context).await })
+    }
+}
+
+/// Spawns `future` right away and returns a future for its output. Dropping the returned
+/// future does not stop the spawned one.
+fn run_to_completion<T: Send + 'static>(
+    future: impl Future<Output = Result<T, sacp::Error>> + Send + 'static,
+) -> impl Future<Output = Result<T, sacp::Error>> + Send {
+    let task = tokio::spawn(future);
+    async move { task.await.map_err(sacp::util::internal_error)? }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use sacp::mcp_server::McpServer;
+    use sacp::role::mcp;
+    use sacp::{ByteStreams, ConnectTo};
+    use serde::Deserialize;
+    use serde_json::{Value, json};
+    use tokio::io::{AsyncBufReadExt, AsyncWrite, AsyncWriteExt, BufReader, Lines};
+    use tokio::sync::{Barrier, oneshot};
+    use tokio_util::compat::{TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
+
+    use super::*;
+
+    #[derive(Deserialize, JsonSchema)]
+    struct NoInputs {}
+
+    async fn send(writer: &mut (impl AsyncWrite + Unpin), message: Value) {
+        let line = format!("{}\n", message);
+        writer.write_all(line.as_bytes()).await.unwrap();
+    }
+
+    /// Reads messages until the response to request `id`.
+    async fn response(lines: &mut Lines<impl AsyncBufReadExt + Unpin>, id: u64) -> Value {
+        loop {
+            let line = lines.next_line().await.unwrap().expect("server hung up");
+            let message: Value = serde_json::from_str(&line).unwrap();
+            if message["id"] == id {
+                return message;
+            }
+        }
+    }
+
+    fn call(id: u64, tool: &str) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "tools/call",
+            "params": { "name": tool, "arguments": {} }
+        })
+    }
+
+    #[tokio::test]
+    async fn calls_run_concurrently() {
+        let barrier = Arc::new(Barrier::new(2));
+        let server = McpServer::builder("test")
+            .direct_tool_fn(
+                "meet",
+                "Returns once two calls are running",
+                async move |_: NoInputs, _cx| {
+                    barrier.wait().await;
+                    Ok("met".to_string())
+                },
+                sacp::tool_fn!(),
+            )
+            .build();
+
+        let (client_stream, server_stream) = tokio::io::duplex(8192);
+        let (server_read, server_write) = tokio::io::split(server_stream);
+        let (client_read, mut client_write) = tokio::io::split(client_stream);
+        let serve = ConnectTo::<mcp::Client>::connect_to(
+            server,
+            ByteStreams::new(server_write.compat_write(), server_read.compat()),
+        );
+        let client = async {
+            let mut lines = BufReader::new(client_read).lines();
+            send(
+                &mut client_write,
+                json!({
+                    "jsonrpc": "2.0",
+                    "id": 0,
+                    "method": "initialize",
+                    "params": {
+                        "protocolVersion": "2025-03-26",
+                        "capabilities": {},
+                        "clientInfo": { "name": "test", "version": "0" }
+                    }
+                }),
+            )
+            .await;
+            response(&mut lines, 0).await;
+            send(
+                &mut client_write,
+                json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+            )
+            .await;
+
+            // Each call only returns once the other one is running too.
+            send(&mut client_write, call(1, "meet")).await;
+            send(&mut client_write, call(2, "meet")).await;
+            let mut ids = Vec::new();
+            while ids.len() < 2 {
+                let line = lines.next_line().await.unwrap().expect("server hung up");
+                let message: Value = serde_json::from_str(&line).unwrap();
+                if message.get("id").is_some() {
+                    assert_eq!(message["result"]["isError"], false, "{}", message);
+                    ids.push(message["id"].as_u64().unwrap());
+                }
+            }
+            ids.sort();
+            assert_eq!(ids, [1, 2]);
+            Ok(())
+        };
+
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            sacp::util::run_until(serve, client),
+        )
+        .await
+        .expect("calls didn't finish")
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn cancelled_calls_run_to_completion() {
+        let (release, released) = oneshot::channel::<()>();
+        let (finish, finished) = oneshot::channel();
+        let call = run_to_completion(async move {
+            released.await.unwrap();
+            finish.send(()).unwrap();
+            Ok(())
+        });
+        drop(call);
+
+        release.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(10), finished)
+            .await
+            .expect("the dropped call didn't finish")
+            .unwrap();
+    }
+}
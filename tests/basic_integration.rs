@@ -1,7 +1,13 @@
+use std::collections::HashMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 use anyhow::Result;
-use sacp::Agent;
+use sacp::schema::{
+    InitializeRequest, ProtocolVersion, SessionNotification, SessionUpdate, StopReason,
+};
+use sacp::util::MatchDispatch;
+use sacp::{Agent, Client, SessionMessage};
 use sacp_conductor::{ConductorImpl, ProxiesAndAgent};
 use symposium_rust_analyzer::RustAnalyzerProxy;
 
@@ -33,6 +39,7 @@ async fn create_conductor() -> ConductorImpl<Agent> {
     let test_project = get_test_project_path();
     let proxy = RustAnalyzerProxy {
         workspace_path: Some(test_project.display().to_string()),
+        ..Default::default()
     };
 
     ConductorImpl::new_agent(
@@ -42,167 +49,1969 @@ async fn create_conductor() -> ConductorImpl<Agent> {
     )
 }
 
+/// Like `yopo::prompt`, but sends `prompts` one after another in a single session, so each
+/// sees what the earlier ones left in the server. Returns the response to each prompt.
+async fn prompt_session(
+    conductor: ConductorImpl<Agent>,
+    prompts: &[String],
+) -> Result<Vec<String>> {
+    let responses = Client
+        .connect_with(conductor, async |cx: sacp::ConnectionTo<Agent>| {
+            cx.send_request(InitializeRequest::new(ProtocolVersion::LATEST))
+                .block_task()
+                .await?;
+            let mut session = cx
+                .build_session(PathBuf::from("."))
+                .block_task()
+                .start_session()
+                .await?;
+
+            let mut responses = Vec::with_capacity(prompts.len());
+            for prompt in prompts {
+                session.send_prompt(prompt)?;
+                let mut response = String::new();
+                loop {
+                    match session.read_update().await? {
+                        SessionMessage::SessionMessage(message) => {
+                            MatchDispatch::new(message)
+                                .if_notification(async |notification: SessionNotification| {
+                                    if let SessionUpdate::AgentMessageChunk(chunk) =
+                                        notification.update
+                                    {
+                                        response.push_str(&yopo::content_block_to_string(
+                                            &chunk.content,
+                                        ));
+                                    }
+                                    Ok(())
+                                })
+                                .await
+                                .otherwise(async |_message| Ok(()))
+                                .await?;
+                        }
+                        SessionMessage::StopReason(StopReason::EndTurn) => break,
+                        other => panic!("unexpected session message: {:?}", other),
+                    }
+                }
+                responses.push(response);
+            }
+            Ok(responses)
+        })
+        .await?;
+    Ok(responses)
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_workspace_folders() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_workspace_folders with { "add": ["src"] }"#,
+    )
+    .await?;
+
+    assert!(result.contains("test-project/src"));
+    assert!(result.contains("is_error: Some(false)"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_workspace_folders_rejects_missing_folder() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_workspace_folders with { "add": ["no-such-dir"] }"#,
+    )
+    .await?;
+
+    assert!(result.contains("is not a directory"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_set_workspace() -> Result<()> {
+    let test_project = get_test_project_path();
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_set_workspace with {{ "workspace_path": "{}" }}"#,
+            test_project.display()
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("Workspace set successfully"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_hover() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_hover with {{ "file_path": "{}", "line": 3, "character": 11 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("name: String"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_hover_with_workspace_path() -> Result<()> {
+    let conductor = create_conductor().await;
+    let test_project = get_test_project_path();
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_hover with {{ "file_path": "{}", "line": 3, "character": 11, "workspace_path": "{}" }}"#,
+            file_path,
+            test_project.display()
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("name: String"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_definition() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_definition with {{ "file_path": "{}", "line": 0, "character": 25 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("src/collections/hash/map.rs"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_definition_opens_document_first() -> Result<()> {
+    // A fresh server that has never had main.rs opened.
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // The `calculate_sum` call in `main`.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_definition with {{ "file_path": "{}", "line": 106, "character": 14 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""line":40"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_file_metrics() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_file_metrics with {{ "file_path": "{}" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""structs":2"#));
+    assert!(result.contains(r#""enums":1"#));
+    assert!(result.contains(r#""traits":1"#));
+    assert!(result.contains(r#""impls":3"#));
+    assert!(result.contains(r#""longest_function":{"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_definition_source() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // The `calculate_sum` call in `main`.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_definition_source with {{ "file_path": "{}", "line": 106, "character": 14 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains("pub fn calculate_sum(numbers: &[i32]) -> i32 {"));
+    assert!(result.contains("numbers.iter().sum()"));
+    assert!(result.contains(r#""start_line":41"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_lsp_call_opens_document_first() -> Result<()> {
+    let conductor = create_conductor().await;
+    let test_project = get_test_project_path();
+    let uri = format!("file://{}", get_test_file_path());
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_lsp_call with {{ "method": "textDocument/definition", "params": {{ "textDocument": {{ "uri": "{}" }}, "position": {{ "line": 106, "character": 14 }} }}, "workspace_path": "{}" }}"#,
+            uri,
+            test_project.display()
+        ),
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""line":40"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_references() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // Test finding references to Person struct
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_references with {{ "file_path": "{}", "line": 3, "character": 11 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("test-project/src/main.rs"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_references_structured_output() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_references with {{ "file_path": "{}", "line": 3, "character": 11 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("structured_content"));
+    assert!(result.replace('\\', "").contains(r#""references":["#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_references_paged() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // `Person` is used many times; ask for the second one only.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_references with {{ "file_path": "{}", "line": 3, "character": 11, "offset": 1, "limit": 1 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""references":[{"#));
+    assert!(result.contains(r#""has_more":true"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_references_grouped_by_file() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // `Person::name` is read by `greet`.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_references with {{ "file_path": "{}", "line": 4, "character": 4, "group_by_file": true }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""total":"#));
+    assert!(result.contains(r#""count":"#));
+    assert!(result.contains(r#""access":"read""#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_completion() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_completion with {{ "file_path": "{}", "line": 99, "character": 29 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("greet"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_completion_after_trigger_character() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+    // Leave `db.` in `main` as if the `.` had just been typed.
+    let content = std::fs::read_to_string(&file_path)?.replace(
+        r#"    let _ = db.update_user_email(id1, "alice@example.com".to_string());"#,
+        "    db.",
+    );
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_completion with {{ "file_path": "{}", "line": 102, "character": 7, "trigger_character": ".", "content": {} }}"#,
+            file_path,
+            serde_json::to_string(&content)?
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("add_user"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_completion_filtered() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_completion with {{ "file_path": "{}", "line": 99, "character": 29, "prefix_filter": "gr", "max_items": 1 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("greet"));
+    assert!(!result.contains("display"));
+    assert!(result.replace('\\', "").contains(r#""total":"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_symbols() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_symbols with {{ "file_path": "{}" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("Person"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_folding_ranges() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_folding_ranges with {{ "file_path": "{}" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("startLine"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_selection_range() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_selection_range with {{ "file_path": "{}", "line": 23, "character": 50 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("start"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_semantic_tokens() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_semantic_tokens with {{ "file_path": "{}" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("mutable"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_runnables() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_runnables with {{ "file_path": "{}" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("run test-project"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_run_test() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_run_test with {{ "file_path": "{}", "label": "run test-project" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""exit_code":0"#));
+    assert!(result.contains("Sum: 15"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_related_tests() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // The test project has no tests, so this should succeed with an empty list.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_related_tests with {{ "file_path": "{}", "line": 40, "character": 7 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("is_error: Some(false)"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_ssr() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_ssr with {{ "query": "calculate_sum($a) ==>> find_max($a)", "file_path": "{}" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("find_max(&numbers)"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_ssr_parse_only() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_ssr with {{ "query": "calculate_sum($a) ==>>", "parse_only": true, "file_path": "{}" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("parsed") && result.contains("false"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_view_hir() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_view_hir with {{ "file_path": "{}", "line": 41, "character": 8 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("numbers.iter().sum()"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_view_mir() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_view_mir with {{ "file_path": "{}", "line": 41, "character": 8 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("StorageLive"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_analyzer_status() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_analyzer_status with {}"#,
+    )
+    .await?;
+
+    assert!(result.contains("Workspaces"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_memory_usage() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_memory_usage with {}"#,
+    )
+    .await?;
+
+    // Release builds of rust-analyzer don't include memory profiling; the server's
+    // explanation should still reach the caller.
+    assert!(
+        result.contains("is_error: Some(false)")
+            || result.contains("Memory profiling is not enabled")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_workspace_diagnostics() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    // The test project calls the undefined `error_function()`.
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_workspace_diagnostics with {}"#,
+    )
+    .await?;
+
+    assert!(result.contains("error_function"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_list_files() -> Result<()> {
+    let conductor = create_conductor().await;
+    let test_project = get_test_project_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_list_files with {{ "directory": "src", "workspace_path": "{}" }}"#,
+            test_project.display()
+        ),
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""path":"src/main.rs""#));
+    assert!(result.contains(r#""total":1"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_diagnostics_glob() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_diagnostics_glob with { "pattern": "src/**/*.rs" }"#,
+    )
+    .await?;
+
+    assert!(result.contains("error_function"));
+    assert!(result.contains("test-project/src/main.rs"));
+    assert!(result.replace('\\', "").contains(r#""timed_out":false"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_check_workspace() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_check_workspace with {}"#,
+    )
+    .await?;
+
+    assert!(result.contains("error_function"));
+    assert!(result.contains("files_with_errors"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_after_manifest_edit() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_after_manifest_edit with { "manifest_path": "Cargo.toml" }"#,
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""reloaded":true"#));
+    assert!(result.contains("error_function"));
+    assert!(!result.contains(r#""warning":""#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_after_manifest_edit_warns_about_other_files() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_after_manifest_edit with { "manifest_path": "src/main.rs" }"#,
+    )
+    .await?;
+
+    assert!(result.contains("isn't a Cargo.toml"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_rebuild_proc_macros() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_rebuild_proc_macros with {}"#,
+    )
+    .await?;
+
+    assert!(result.replace('\\', "").contains(r#""after":"ready""#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_set_check_command() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_set_check_command with { "command": "clippy" }"#,
+    )
+    .await?;
+
+    assert!(result.replace('\\', "").contains(r#""command":"clippy""#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_configure() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_configure with { "settings": { "cargo": { "allTargets": true } } }"#,
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""allTargets":true"#));
+    // The defaults are still there.
+    assert!(result.contains(r#""buildScripts":{"enable":true}"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_configure_rejects_non_object() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_configure with { "settings": [1, 2] }"#,
+    )
+    .await?;
+
+    assert!(result.contains("must be a JSON object"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_symbols_flatten() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_symbols with {{ "file_path": "{}", "flatten": true }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("Person::greet"));
+    assert!(result.contains("Database::add_user"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_symbol_path() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // The `add_user` in `db.add_user(person1)`.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_symbol_path with {{ "file_path": "{}", "line": 95, "character": 18 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("test_project::Database::add_user"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_symbol_path_of_local() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // The `person1` in `db.add_user(person1)`.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_symbol_path with {{ "file_path": "{}", "line": 95, "character": 27 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.replace('\\', "").contains(r#""path":null"#));
+    assert!(result.contains("local variable"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_type_at() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // The `sum` in `let sum = calculate_sum(&numbers);`.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_type_at with {{ "file_path": "{}", "line": 106, "character": 8 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.replace('\\', "").contains(r#""type":"i32""#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_type_at_function() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // The `calculate_sum` in `let sum = calculate_sum(&numbers);`.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_type_at with {{ "file_path": "{}", "line": 106, "character": 14 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.replace('\\', "").contains(r#""type":null"#));
+    assert!(result.contains("is a function, not a value"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_enclosing_item() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // Inside the body of `Database::add_user`.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_enclosing_item with {{ "file_path": "{}", "line": 69, "character": 8 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("impl Database"));
+    assert!(result.contains("add_user"));
+    assert!(result.contains("fn add_user(&mut self"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_find_symbol() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_find_symbol with {{ "file_path": "{}", "name": "Person::greet" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("pub fn greet"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_find_symbol_in_document_source() -> Result<()> {
+    init_tracing();
+    let test_project = get_test_project_path();
+    // Served by the document source only; there is no such file on disk.
+    let file_path = test_project.join("src/unsaved.rs");
+    assert!(!file_path.exists());
+    let proxy = RustAnalyzerProxy {
+        workspace_path: Some(test_project.display().to_string()),
+        document_source: Some(Arc::new(HashMap::from([(
+            file_path.clone(),
+            "pub struct Ghost;\n\nimpl Ghost {\n    pub fn haunt(&self) {}\n}\n".to_string(),
+        )]))),
+        ..Default::default()
+    };
+    let conductor = ConductorImpl::new_agent(
+        "test-conductor".to_string(),
+        ProxiesAndAgent::new(elizacp::ElizaAgent::new(true)).proxy(proxy),
+        Default::default(),
+    );
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_find_symbol with {{ "file_path": "{}", "name": "Ghost::haunt" }}"#,
+            file_path.display()
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("pub fn haunt"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_public_api() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_public_api with {{ "file_path": "{}" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""path":"Person::greet""#));
+    assert!(result.contains("pub fn calculate_sum(numbers: &[i32]) -> i32"));
+    // `main` isn't `pub`.
+    assert!(!result.contains(r#""path":"main""#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_list_symbols_by_kind() -> Result<()> {
+    let conductor = create_conductor().await;
+    let test_project = get_test_project_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_list_symbols_by_kind with {{ "kind": "struct", "limit": 1, "workspace_path": "{}" }}"#,
+            test_project.display()
+        ),
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""name":"Person""#));
+    assert!(!result.contains(r#""name":"Database""#));
+    assert!(result.contains(r#""total":2"#));
+    assert!(result.contains(r#""has_more":true"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_declaration() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_declaration with {{ "file_path": "{}", "line": 0, "character": 25 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("src/collections/hash/map.rs"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_references_with_preview() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_references with {{ "file_path": "{}", "line": 3, "character": 11, "include_preview": true }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("pub struct Person"));
+    assert!(result.contains("impl Displayable for Person"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_describe() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // The `Person` struct name.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_describe with {{ "file_path": "{}", "line": 3, "character": 11 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains("struct Person"));
+    assert!(result.contains(r#""definition":{"result":"#));
+    assert!(result.contains(r#""implementations":{"result":"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_trait_impls() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_trait_impls with {{ "file_path": "{}", "name": "Displayable" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.replace('\\', "").contains(r#""type_name":"Person""#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_hover_one_based() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // Same position as `test_rust_analyzer_hover`, counted from 1.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_hover with {{ "file_path": "{}", "line": 4, "character": 12, "one_based": true }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("name: String"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_definition_by_symbol_name() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // Occurrence 1 is the call in `main`; occurrence 0 is the definition itself.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_definition with {{ "file_path": "{}", "symbol_name": "calculate_sum", "occurrence": 1, "include_preview": true }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("pub fn calculate_sum"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_hover_position_out_of_range() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_hover with {{ "file_path": "{}", "line": 500, "character": 0, "one_based": true }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("line 500 out of range; the file has"));
+    assert!(result.contains("invalid_position"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_references_symbol_occurrence_out_of_range() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_references with {{ "file_path": "{}", "symbol_name": "calculate_sum", "occurrence": 5 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("Occurrence 5 of `calculate_sum` not found; 2 available"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_parent_module_at_crate_root() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_parent_module with {{ "file_path": "{}", "line": 40, "character": 8 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("crate root"));
+    assert!(result.contains("Cargo.toml"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_on_enter() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_on_enter with {{ "file_path": "{}", "line": 119, "character": 20 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    // Splitting a `//` comment continues it on the next line.
+    assert!(result.contains("new_text"));
+    assert!(result.contains("cursor"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_matching_brace() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // The opening brace of `Person::greet`'s body.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_matching_brace with {{ "file_path": "{}", "line": 22, "character": 34 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains(r#"{\\\"line\\\":24,\\\"character\\\":4}"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_join_lines() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_join_lines with {{ "file_path": "{}", "line": 22, "character": 0, "end_line": 23, "end_character": 0 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("newText"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_move_item() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // Move `calculate_sum` below `find_max`.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_move_item with {{ "file_path": "{}", "line": 40, "character": 8, "end_line": 40, "end_character": 8, "direction": "down" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("new_text"));
+    assert!(result.contains("find_max"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_move_item_invalid_direction() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_move_item with {{ "file_path": "{}", "line": 40, "character": 8, "end_line": 40, "end_character": 8, "direction": "sideways" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("Invalid direction `sideways`"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_code_lens() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_code_lens with {{ "file_path": "{}" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("rust-analyzer.runSingle"));
+    assert!(result.contains("implementation"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_document_links() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_document_links with {{ "file_path": "{}" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    // Current rust-analyzer releases don't advertise `textDocument/documentLink`, so the
+    // tool answers `{"unsupported": "documentLink"}` instead of failing the request.
+    assert!(result.contains("is_error: Some(false)"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_explain_diagnostic() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // The `error_function()` call in `main`.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_explain_diagnostic with {{ "file_path": "{}", "line": 120, "character": 6 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("cannot find function"));
+    assert!(result.contains("Generate error_function function"));
+    assert!(result.contains("An unresolved name was used."));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_explain_error_code() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_explain_error_code with { "code": "e0425" }"#,
+    )
+    .await?;
+
+    assert!(result.replace('\\', "").contains(r#""code":"E0425""#));
+    assert!(result.contains("An unresolved name was used."));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_explain_error_code_rejects_invalid_codes() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_explain_error_code with { "code": "--help" }"#,
+    )
+    .await?;
+
+    assert!(result.contains("Invalid error code"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_hover_unsaved_content() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+    let content = std::fs::read_to_string(&file_path)?.replace("name: String", "name: Box<str>");
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_hover with {{ "file_path": "{}", "line": 3, "character": 11, "content": {} }}"#,
+            file_path,
+            serde_json::to_string(&content)?
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("name: Box<str>"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_diagnostics_for_content() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+    let content = std::fs::read_to_string(&file_path)?
+        .replace("error_function();", "let broken: u32 = \"text\";");
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_diagnostics_for_content with {{ "file_path": "{}", "content": {} }}"#,
+            file_path,
+            serde_json::to_string(&content)?
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("expected u32, found &'static str"));
+    assert!(!result.contains("error_function"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_capabilities() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_capabilities with {}"#,
+    )
+    .await?;
+
+    assert!(result.contains("textDocument/hover"));
+    assert!(result.contains("rust-analyzer"));
+    assert!(result.contains("is_error: Some(false)"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_hover_relative_path() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_hover with { "file_path": "src/main.rs", "line": 3, "character": 11 }"#,
+    )
+    .await?;
+
+    assert!(result.contains("name: String"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_on_type_formatting() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+    // Break the method chain in `calculate_sum` as if `.` had just been typed on a new line.
+    let content = std::fs::read_to_string(&file_path)?
+        .replace("    numbers.iter().sum()", "    numbers.iter()\n    .sum()");
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_on_type_formatting with {{ "file_path": "{}", "line": 42, "character": 5, "ch": ".", "content": {} }}"#,
+            file_path,
+            serde_json::to_string(&content)?
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("newText"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_linked_editing() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_linked_editing with {{ "file_path": "{}", "line": 44, "character": 16 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    // The type parameter `T` of `find_max`. rust-analyzer doesn't advertise linked editing
+    // yet, in which case the tool answers `{"unsupported": ...}` rather than failing.
+    assert!(result.contains("is_error: Some(false)"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_type_hierarchy() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_type_hierarchy with {{ "file_path": "{}", "line": 27, "character": 10 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    // The `Displayable` trait, implemented by `Person`. rust-analyzer releases without
+    // type hierarchy support get `{"unsupported": ...}` instead.
+    assert!(result.contains("is_error: Some(false)"));
+    assert!(result.contains("Person") || result.contains("unsupported"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_status() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_status with {}"#,
+    )
+    .await?;
+
+    assert!(result.contains("ready") || result.contains("loading"));
+    assert!(result.contains("test-project"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_progress() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_progress with {}"#,
+    )
+    .await?;
+
+    assert!(result.contains("ready") || result.contains("loading"));
+    assert!(result.replace('\\', "").contains(r#""progress":["#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_definition_no_results() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // A blank line: once indexing is done this is a genuine empty result.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_definition with {{ "file_path": "{}", "line": 1, "character": 0 }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("no_results"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_diagnostics() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_diagnostics with {{ "file_path": "{}", "include_context": true }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    // The call to the undefined `error_function`, underlined.
+    assert!(result.contains("121 |     error_function();"));
+    assert!(result.contains("|     ^^^^^^^^^^^^^^"));
+    // rust-analyzer offers to generate the missing function.
+    assert!(result.replace('\\', "").contains(r#""has_quickfix":true"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_diagnostics_min_severity() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_diagnostics with {{ "file_path": "{}", "min_severity": "error" }}"#,
+            file_path
+        ),
+    )
+    .await?;
+
+    // Hints and warnings are counted but not listed.
+    let result = result.replace('\\', "");
+    assert!(result.contains("error_function"));
+    assert!(result.contains(r#""severity":1"#));
+    assert!(!result.contains(r#""severity":2"#) && !result.contains(r#""severity":4"#));
+    Ok(())
+}
+
 #[tokio::test]
-async fn test_rust_analyzer_set_workspace() -> Result<()> {
-    let test_project = get_test_project_path();
+async fn test_rust_analyzer_batch() -> Result<()> {
     let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
 
     let result = yopo::prompt(
         conductor,
         &format!(
-            r#"Use tool rust-analyzer-mcp::rust_analyzer_set_workspace with {{ "workspace_path": "{}" }}"#,
-            test_project.display()
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_batch with {{ "requests": [{{ "tool": "hover", "file_path": "{file_path}", "line": 3, "character": 11 }}, {{ "tool": "definition", "file_path": "{file_path}", "line": 120, "character": 4 }}, {{ "tool": "rename", "file_path": "{file_path}", "line": 3, "character": 11 }}] }}"#
         ),
     )
     .await?;
 
-    assert!(result.contains("Workspace set successfully"));
+    assert!(result.contains("name: String"));
+    assert!(result.contains("Unknown batch tool `rename`"));
+    assert!(result.contains("is_error: Some(false)"));
     Ok(())
 }
 
 #[tokio::test]
-async fn test_rust_analyzer_hover() -> Result<()> {
+async fn test_rust_analyzer_server_log() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_server_log with {}"#,
+    )
+    .await?;
+
+    assert!(result.contains("is_error: Some(false)"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_ping() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_ping with {}"#,
+    )
+    .await?;
+
+    assert!(result.contains("latency_ms"));
+    assert!(result.contains("server_version"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_version() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_version with {}"#,
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""name":"rust-analyzer""#));
+    assert!(result.contains(r#""version":"#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_interpret() -> Result<()> {
     let conductor = create_conductor().await;
     let file_path = get_test_file_path();
+    let original = std::fs::read_to_string(&file_path)?;
+    let line = original.lines().count() + 1;
+    let content = format!("{}\nfn answer() -> i32 {{\n    6 * 7\n}}\n", original);
 
     let result = yopo::prompt(
         conductor,
         &format!(
-            r#"Use tool rust-analyzer-mcp::rust_analyzer_hover with {{ "file_path": "{}", "line": 3, "character": 11 }}"#,
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_interpret with {{ "file_path": "{}", "line": {}, "character": 3, "content": {} }}"#,
+            file_path,
+            line,
+            serde_json::to_string(&content)?
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("42"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_resolve_doc_link() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+    let original = std::fs::read_to_string(&file_path)?;
+    let line = original.lines().count() + 1;
+    let content = format!(
+        "{}\n/// Like [`Person::greet`].\nfn greet_twice() {{}}\n",
+        original
+    );
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_resolve_doc_link with {{ "file_path": "{}", "line": {}, "character": 18, "content": {} }}"#,
+            file_path,
+            line,
+            serde_json::to_string(&content)?
+        ),
+    )
+    .await?;
+
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""target":"Person::greet""#));
+    assert!(result.contains("test-project/src/main.rs"));
+    assert!(result.contains(r#"::Person::greet""#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_crate_graph() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_crate_graph with { "full": false, "max_bytes": 40 }"#,
+    )
+    .await?;
+
+    assert!(result.contains("digraph"));
+    assert!(result.contains("// truncated:"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_syntax_tree() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    // The body of `calculate_sum`.
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_syntax_tree with {{ "file_path": "{}", "line": 41, "character": 4, "end_line": 41, "end_character": 24 }}"#,
             file_path
         ),
     )
     .await?;
 
-    assert!(result.contains("name: String"));
+    assert!(result.contains("METHOD_CALL_EXPR"));
+    assert!(!result.contains("SOURCE_FILE"));
     Ok(())
 }
 
 #[tokio::test]
-async fn test_rust_analyzer_definition() -> Result<()> {
+async fn test_rust_analyzer_item_tree() -> Result<()> {
     let conductor = create_conductor().await;
     let file_path = get_test_file_path();
 
     let result = yopo::prompt(
         conductor,
         &format!(
-            r#"Use tool rust-analyzer-mcp::rust_analyzer_definition with {{ "file_path": "{}", "line": 0, "character": 25 }}"#,
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_item_tree with {{ "file_path": "{}" }}"#,
             file_path
         ),
     )
     .await?;
 
-    assert!(result.contains("src/collections/hash/map.rs"));
+    assert!(result.contains("struct Person"));
     Ok(())
 }
 
 #[tokio::test]
-async fn test_rust_analyzer_references() -> Result<()> {
+async fn test_rust_analyzer_open_cargo_toml() -> Result<()> {
     let conductor = create_conductor().await;
     let file_path = get_test_file_path();
 
-    // Test finding references to Person struct
     let result = yopo::prompt(
         conductor,
         &format!(
-            r#"Use tool rust-analyzer-mcp::rust_analyzer_references with {{ "file_path": "{}", "line": 3, "character": 11 }}"#,
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_open_cargo_toml with {{ "file_path": "{}" }}"#,
             file_path
         ),
     )
     .await?;
 
-    assert!(result.contains("test-project/src/main.rs"));
+    assert!(result.contains("test-project/Cargo.toml"));
     Ok(())
 }
 
 #[tokio::test]
-async fn test_rust_analyzer_completion() -> Result<()> {
+async fn test_rust_analyzer_hover_structured() -> Result<()> {
     let conductor = create_conductor().await;
     let file_path = get_test_file_path();
 
+    // `Person::new`.
     let result = yopo::prompt(
         conductor,
         &format!(
-            r#"Use tool rust-analyzer-mcp::rust_analyzer_completion with {{ "file_path": "{}", "line": 99, "character": 29 }}"#,
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_hover with {{ "file_path": "{}", "line": 10, "character": 11, "structured": true }}"#,
             file_path
         ),
     )
     .await?;
 
-    assert!(result.contains("greet"));
+    let result = result.replace('\\', "");
+    assert!(result.contains(r#""signature":"pub fn new(name: String, age: u32) -> Self""#));
+    assert!(result.contains(r#""source_crate":"test_project""#));
     Ok(())
 }
 
 #[tokio::test]
-async fn test_rust_analyzer_symbols() -> Result<()> {
+async fn test_rust_analyzer_extract_variable() -> Result<()> {
     let conductor = create_conductor().await;
     let file_path = get_test_file_path();
 
+    // `calculate_sum(&numbers)` in `let sum = calculate_sum(&numbers);`.
     let result = yopo::prompt(
         conductor,
         &format!(
-            r#"Use tool rust-analyzer-mcp::rust_analyzer_symbols with {{ "file_path": "{}" }}"#,
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_extract with {{ "file_path": "{}", "line": 106, "character": 14, "end_line": 106, "end_character": 37, "extract_kind": "variable" }}"#,
             file_path
         ),
     )
     .await?;
 
-    assert!(result.contains("Person"));
+    assert!(result.contains("Extract into variable"));
+    assert!(result.replace('\\', "").contains(r#""applied":false"#));
     Ok(())
 }
 
-/*
 #[tokio::test]
-async fn test_rust_analyzer_format() -> Result<()> {
+async fn test_rust_analyzer_extract_refuses_stale_buffer() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+    let on_disk = std::fs::read_to_string(&file_path)?;
+    let content = format!("{}\n// Not saved.\n", on_disk);
+
+    let responses = prompt_session(
+        conductor,
+        &[
+            format!(
+                r#"Use tool rust-analyzer-mcp::rust_analyzer_extract with {{ "file_path": "{}", "line": 106, "character": 14, "end_line": 106, "end_character": 37, "extract_kind": "variable", "content": {} }}"#,
+                file_path,
+                serde_json::to_string(&content)?
+            ),
+            format!(
+                r#"Use tool rust-analyzer-mcp::rust_analyzer_extract with {{ "file_path": "{}", "line": 106, "character": 14, "end_line": 106, "end_character": 37, "extract_kind": "variable", "apply": true }}"#,
+                file_path
+            ),
+        ],
+    )
+    .await?;
+
+    assert!(responses[0].contains("Extract into variable"));
+    assert!(responses[1].contains("differs from the file on disk"));
+    assert_eq!(std::fs::read_to_string(&file_path)?, on_disk);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_add_import() -> Result<()> {
     let conductor = create_conductor().await;
     let file_path = get_test_file_path();
+    // Drop the `use`, leaving `HashMap` unresolved without shifting any lines.
+    let content =
+        std::fs::read_to_string(&file_path)?.replace("use std::collections::HashMap;", "");
 
     let result = yopo::prompt(
         conductor,
         &format!(
-            r#"Use tool rust-analyzer-mcp::rust_analyzer_format with {{ "file_path": "{}" }}"#,
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_add_import with {{ "file_path": "{}", "line": 55, "character": 11, "content": {} }}"#,
+            file_path,
+            serde_json::to_string(&content)?
+        ),
+    )
+    .await?;
+
+    assert!(result.contains("Import `std::collections::HashMap`"));
+    assert!(result.contains("use std::collections::HashMap;"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_add_import_refuses_stale_buffer() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+    let on_disk = std::fs::read_to_string(&file_path)?;
+    // Leaves the server with `HashMap` unresolved, in text that isn't on disk.
+    let content = on_disk.replace("use std::collections::HashMap;", "");
+
+    let responses = prompt_session(
+        conductor,
+        &[
+            format!(
+                r#"Use tool rust-analyzer-mcp::rust_analyzer_add_import with {{ "file_path": "{}", "line": 55, "character": 11, "content": {} }}"#,
+                file_path,
+                serde_json::to_string(&content)?
+            ),
+            format!(
+                r#"Use tool rust-analyzer-mcp::rust_analyzer_add_import with {{ "file_path": "{}", "line": 55, "character": 11, "apply": true }}"#,
+                file_path
+            ),
+        ],
+    )
+    .await?;
+
+    assert!(responses[0].contains("Import `std::collections::HashMap`"));
+    assert!(responses[1].contains("differs from the file on disk"));
+    assert_eq!(std::fs::read_to_string(&file_path)?, on_disk);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rust_analyzer_fix_plan() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+
+    let result = yopo::prompt(
+        conductor,
+        &format!(
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_fix_plan with {{ "file_path": "{}" }}"#,
             file_path
         ),
     )
     .await?;
 
-    assert!(result.contains("is_error: Some(false)"));
+    // `add_user` takes `mut person` without needing it.
+    assert!(result.contains("remove this `mut`"));
+    assert!(result.replace('\\', "").contains(r#""applied":false"#));
     Ok(())
 }
 
 #[tokio::test]
-async fn test_rust_analyzer_code_actions() -> Result<()> {
+async fn test_rust_analyzer_fix_plan_refuses_stale_buffer() -> Result<()> {
+    let conductor = create_conductor().await;
+    let file_path = get_test_file_path();
+    let on_disk = std::fs::read_to_string(&file_path)?;
+    let content = format!("{}\n// Not saved.\n", on_disk);
+
+    let responses = prompt_session(
+        conductor,
+        &[
+            format!(
+                r#"Use tool rust-analyzer-mcp::rust_analyzer_fix_plan with {{ "file_path": "{}", "content": {} }}"#,
+                file_path,
+                serde_json::to_string(&content)?
+            ),
+            format!(
+                r#"Use tool rust-analyzer-mcp::rust_analyzer_fix_plan with {{ "file_path": "{}", "apply": true }}"#,
+                file_path
+            ),
+        ],
+    )
+    .await?;
+
+    assert!(responses[0].contains("remove this `mut`"));
+    assert!(responses[1].contains("differs from the file on disk"));
+    assert_eq!(std::fs::read_to_string(&file_path)?, on_disk);
+    Ok(())
+}
+
+/*
+#[tokio::test]
+async fn test_rust_analyzer_format() -> Result<()> {
     let conductor = create_conductor().await;
     let file_path = get_test_file_path();
 
     let result = yopo::prompt(
         conductor,
         &format!(
-            r#"Use tool rust-analyzer-mcp::rust_analyzer_code_actions with {{ "file_path": "{}", "line": 104, "character": 4, "end_line": 104, "end_character": 20 }}"#,
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_format with {{ "file_path": "{}" }}"#,
             file_path
         ),
     )
     .await?;
 
-    assert!(result.contains("structured_content"));
+    assert!(result.contains("is_error: Some(false)"));
     Ok(())
 }
 
 #[tokio::test]
-async fn test_rust_analyzer_diagnostics() -> Result<()> {
+async fn test_rust_analyzer_code_actions() -> Result<()> {
     let conductor = create_conductor().await;
     let file_path = get_test_file_path();
 
     let result = yopo::prompt(
         conductor,
         &format!(
-            r#"Use tool rust-analyzer-mcp::rust_analyzer_diagnostics with {{ "file_path": "{}" }}"#,
+            r#"Use tool rust-analyzer-mcp::rust_analyzer_code_actions with {{ "file_path": "{}", "line": 104, "character": 4, "end_line": 104, "end_character": 20 }}"#,
             file_path
         ),
     )
     .await?;
 
-    dbg!(&result);
     assert!(result.contains("structured_content"));
     Ok(())
 }
@@ -226,6 +2035,20 @@ async fn test_rust_analyzer_lsp_call_notification() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_rust_analyzer_server_messages() -> Result<()> {
+    let conductor = create_conductor().await;
+
+    let result = yopo::prompt(
+        conductor,
+        r#"Use tool rust-analyzer-mcp::rust_analyzer_server_messages with {}"#,
+    )
+    .await?;
+
+    assert!(result.contains("is_error: Some(false)"));
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_rust_analyzer_failed_obligations() -> Result<()> {
     let conductor = create_conductor().await;
@@ -275,3 +2098,133 @@ async fn test_direct_bridge_hover() -> Result<()> {
     assert!(result.contains("contents"));
     Ok(())
 }
+
+#[tokio::test]
+async fn test_direct_bridge_relative_path_without_workspace() -> Result<()> {
+    use std::sync::Arc;
+    use symposium_rust_analyzer::{BridgeState, BridgeType, with_bridge_and_document};
+    use tokio::sync::Mutex;
+
+    let bridge: BridgeType = Arc::new(Mutex::new(BridgeState::new()));
+
+    let result = with_bridge_and_document(&bridge, None, "src/main.rs", async move |_, _| {
+        Ok(String::new())
+    })
+    .await;
+
+    let error = result.expect_err("relative path without a workspace should fail");
+    assert!(error.to_string().contains("needs a workspace"));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_direct_bridge_loading_timeout() -> Result<()> {
+    use std::sync::Arc;
+    use std::time::Duration;
+    use symposium_rust_analyzer::{BridgeState, BridgeType, with_bridge_and_document};
+    use tokio::sync::Mutex;
+
+    init_tracing();
+    let test_project = get_test_project_path();
+    let file_path = get_test_file_path();
+
+    let mut bridge_state = BridgeState::new();
+    bridge_state.set_ready_timeout(Duration::ZERO);
+    let bridge: BridgeType = Arc::new(Mutex::new(bridge_state));
+
+    // A freshly started server is still loading, so the closure must not run.
+    let result = with_bridge_and_document(
+        &bridge,
+        Some(&test_project.display().to_string()),
+        &file_path,
+        async move |_, _| Ok("ran".to_string()),
+    )
+    .await?;
+
+    assert!(result.contains(r#""status":"loading""#));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_direct_bridge_close_document() -> Result<()> {
+    use std::sync::Arc;
+    use symposium_rust_analyzer::{
+        BridgeState, BridgeType, close_document, with_bridge_and_document,
+    };
+    use tokio::sync::Mutex;
+
+    init_tracing();
+    let test_project = get_test_project_path();
+    let workspace_path = test_project.display().to_string();
+    let file_path = get_test_file_path();
+
+    let bridge: BridgeType = Arc::new(Mutex::new(BridgeState::new()));
+
+    // Nothing is open before a server is started.
+    let (closed, _) = close_document(&bridge, Some(&workspace_path), &file_path).await?;
+    assert!(!closed);
+
+    with_bridge_and_document(
+        &bridge,
+        Some(&workspace_path),
+        &file_path,
+        async move |_, _| Ok(String::new()),
+    )
+    .await?;
+
+    let (closed, _) = close_document(&bridge, Some(&workspace_path), &file_path).await?;
+    assert!(closed);
+    let (closed, _) = close_document(&bridge, Some(&workspace_path), &file_path).await?;
+    assert!(!closed);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_direct_bridge_tcp_transport() -> Result<()> {
+    use lsp_types::Position;
+    use std::process::Stdio;
+    use std::sync::Arc;
+    use symposium_rust_analyzer::{BridgeState, BridgeType, with_bridge_and_document};
+    use tokio::sync::Mutex;
+
+    init_tracing();
+    let test_project = get_test_project_path();
+    let file_path = get_test_file_path();
+
+    // Stand in for a remote server: relay one TCP connection to a local rust-analyzer.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await?;
+        let mut child = tokio::process::Command::new("rust-analyzer")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let mut server = tokio::io::join(child.stdout.take().unwrap(), child.stdin.take().unwrap());
+        tokio::io::copy_bidirectional(&mut stream, &mut server).await?;
+        anyhow::Ok(())
+    });
+
+    let mut bridge_state = BridgeState::new();
+    bridge_state.set_tcp_address(address.to_string());
+    let bridge: BridgeType = Arc::new(Mutex::new(bridge_state));
+
+    let result = with_bridge_and_document(
+        &bridge,
+        Some(&test_project.display().to_string()),
+        &file_path,
+        async move |lsp, uri| {
+            let hover_result = lsp
+                .hover(uri, Position::new(3, 11))
+                .await
+                .map_err(|e| anyhow::anyhow!("Hover request failed: {}", e))?;
+            Ok(serde_json::to_string(&hover_result)?)
+        },
+    )
+    .await?;
+
+    assert!(result.contains("Person"));
+    Ok(())
+}